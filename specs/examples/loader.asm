@@ -0,0 +1,26 @@
+// Loader for the Von Neumann mode.
+//
+// Copies the program from the tape reader into RAM[1024..],
+// and jumps to it, when the tape has ended.
+// The ROM must end at 1024, so that the CPU fetches the instructions there from the RAM.
+// The program is assembled with `.origin 1024`.
+
+    @1024
+    D=A
+    @R13        // where the next word goes
+    M=D
+(LOAD)
+    @24597      // words left on the tape
+    D=M
+    @1024
+    D;JEQ       // the whole program is in RAM: run it
+    @24596      // the next word
+    D=M
+    M=0         // move the tape
+    @R13
+    A=M
+    M=D
+    @R13
+    M=M+1
+    @LOAD
+    0;JMP
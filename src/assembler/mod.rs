@@ -1,3 +1,504 @@
-pub fn asm_to_binary(content: &str) {
-    // ignore whitespace + comments
+use std::collections::HashMap;
+
+use crate::machine::{
+    config::{MemoryMap, BANK_SELECT_ADDRESS},
+    instruction::{
+        Instruction, COMP_TABLE, DEST_A, DEST_D, DEST_TABLE, JUMP_TABLE, RETI, SHIFT_TABLE,
+        STACK_CALL, STACK_GET_SP, STACK_POP, STACK_PUSH, STACK_RET, STACK_SET_SP,
+    },
+};
+
+// Hack assembler
+//
+// Two passes: the first one collects the labels, the second one translates the instructions.
+// Variables are allocated from RAM[16] upwards, in the order they are first used.
+// `RETI`, the shifts (`D=D<<`, `M=A>>`) and the stack instructions (`PUSH D`, `POP A`, `CALL`,
+// `RET`, `SP=D`, `D=SP`) are only executed by a CPU with the extensions.
+//
+// With bank switching in the memory map, `.bank N` places the following code into ROM bank N,
+// and `FARJMP label` jumps to a label in any bank. The bank cannot be switched by the code
+// in the window, so the jump goes through a trampoline, that the assembler puts at the end
+// of the fixed part of the ROM: it selects the bank of the label, and jumps there.
+// Its label is `$far.label`, so the program's own labels cannot start with `$far.`.
+// A and D are overwritten. `ROMBANK` and `RAMBANK` are the addresses of the bank registers.
+//
+// `.origin N` before the first instruction assembles the program for address N,
+// e.g. for loading it into the RAM in the Von Neumann mode: the labels start from N.
+// The machine code still starts with the first instruction.
+
+/// First RAM address for the variables.
+const VARIABLE_BASE: i16 = 16;
+
+/// Prefix of the trampoline labels.
+const TRAMPOLINE_PREFIX: &str = "$far.";
+
+/// Words of one trampoline: `@bank D=A @ROMBANK M=D @label 0;JMP`.
+const TRAMPOLINE_SIZE: usize = 6;
+
+fn predefined_symbols(memory_map: &MemoryMap) -> HashMap<String, i16> {
+    let mut symbols = HashMap::new();
+    for i in 0..16 {
+        symbols.insert(format!("R{}", i), i);
+    }
+    for (i, name) in ["SP", "LCL", "ARG", "THIS", "THAT"].iter().enumerate() {
+        symbols.insert(name.to_string(), i as i16);
+    }
+    symbols.insert("SCREEN".to_owned(), memory_map.screen_address as i16);
+    symbols.insert("KBD".to_owned(), memory_map.keyboard_address as i16);
+    if memory_map.is_banked() {
+        symbols.insert("ROMBANK".to_owned(), BANK_SELECT_ADDRESS as i16);
+        symbols.insert("RAMBANK".to_owned(), BANK_SELECT_ADDRESS as i16 + 1);
+    }
+
+    symbols
+}
+
+/// Removes the comment and every whitespace from the line.
+fn clean_line(line: &str) -> String {
+    let code = match line.find("//") {
+        Some(index) => &line[..index],
+        None => line,
+    };
+
+    code.chars().filter(|c| !c.is_whitespace()).collect()
+}
+
+fn is_valid_symbol(symbol: &str) -> bool {
+    let mut chars = symbol.chars();
+    match chars.next() {
+        Some(first) if !first.is_ascii_digit() => {}
+        _ => return false,
+    }
+
+    symbol
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "_.$:".contains(c))
+}
+
+fn parse_c_instruction(code: &str) -> Option<Instruction> {
+    let (dest, rest) = match code.split_once('=') {
+        Some((dest, rest)) => (dest, rest),
+        None => ("", code),
+    };
+    let (comp, jump) = match rest.split_once(';') {
+        Some((comp, jump)) => (comp, jump),
+        None => (rest, ""),
+    };
+
+    let dest = DEST_TABLE.iter().position(|mnemonic| *mnemonic == dest)? as u16;
+    let jump = JUMP_TABLE.iter().position(|mnemonic| *mnemonic == jump)? as u16;
+
+    let find = |table: &[(&str, u16)]| {
+        table
+            .iter()
+            .find(|(mnemonic, _)| *mnemonic == comp)
+            .map(|(_, bits)| *bits)
+    };
+    match (find(&COMP_TABLE), find(&SHIFT_TABLE)) {
+        (Some(comp), _) => Some(Instruction::C { comp, dest, jump }),
+        (None, Some(comp)) => Some(Instruction::Shift { comp, dest, jump }),
+        (None, None) => None,
+    }
+}
+
+/// `PUSH comp`, `POP dest`, `CALL`, `RET`, `SP=comp` and `dest=SP`.
+/// comp cannot use M, because the memory is busy with the stack, and dest is only A and D.
+fn parse_stack_instruction(code: &str) -> Option<Instruction> {
+    let comp = |comp: &str| {
+        COMP_TABLE
+            .iter()
+            .find(|(mnemonic, bits)| *mnemonic == comp && bits & 0b1000000 == 0)
+            .map(|(_, bits)| *bits)
+    };
+    let dest = |dest: &str| {
+        DEST_TABLE
+            .iter()
+            .position(|mnemonic| *mnemonic == dest)
+            .map(|dest| dest as u16)
+            .filter(|dest| dest & !(DEST_A | DEST_D) == 0)
+    };
+    let stack = |op, comp, dest| Some(Instruction::Stack { op, comp, dest });
+
+    match code {
+        "CALL" => stack(STACK_CALL, 0, 0),
+        "RET" => stack(STACK_RET, 0, 0),
+        _ => {
+            if let Some(operand) = code.strip_prefix("PUSH") {
+                stack(STACK_PUSH, comp(operand)?, 0)
+            } else if let Some(operand) = code.strip_prefix("POP") {
+                stack(STACK_POP, 0, dest(operand)?)
+            } else if let Some(operand) = code.strip_prefix("SP=") {
+                stack(STACK_SET_SP, comp(operand)?, 0)
+            } else if let Some(operand) = code.strip_suffix("=SP") {
+                stack(STACK_GET_SP, 0, dest(operand)?)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// Translates the assembly into machine code.
+/// The error tells the line number (starting from 1) and the reason.
+pub fn asm_to_binary(content: &str) -> Result<Vec<i16>, String> {
+    asm_to_binary_with_memory_map(content, &MemoryMap::default())
+}
+
+/// Same as `asm_to_binary`, but `SCREEN` and `KBD` are taken from the memory map.
+pub fn asm_to_binary_with_memory_map(
+    content: &str,
+    memory_map: &MemoryMap,
+) -> Result<Vec<i16>, String> {
+    let mut symbols = predefined_symbols(memory_map);
+
+    // first pass: labels point to the next instruction.
+    // The instructions are placed by their position in the program, which has gaps between banks.
+    let mut instructions = Vec::new();
+    let mut position = 0;
+    let mut origin = 0;
+    let mut label_banks = HashMap::new();
+    let mut far_targets: Vec<(usize, String)> = Vec::new();
+    for (line_number, line) in content.lines().enumerate() {
+        let code = clean_line(line);
+        if code.is_empty() {
+            continue;
+        }
+
+        if let Some(address) = code.strip_prefix(".origin") {
+            origin = address
+                .parse::<usize>()
+                .ok()
+                .filter(|address| *address < 0x8000)
+                .filter(|_| position == 0 && !memory_map.is_banked())
+                .ok_or(format!(
+                    "Line {}: '{}' must be an address before the first instruction, \
+                     and without bank switching",
+                    line_number + 1,
+                    code
+                ))?;
+        } else if let Some(bank) = code.strip_prefix(".bank") {
+            if !memory_map.is_banked() {
+                return Err(format!(
+                    "Line {}: '{}' needs bank switching",
+                    line_number + 1,
+                    code
+                ));
+            }
+            let bank = bank
+                .parse::<usize>()
+                .ok()
+                .filter(|bank| *bank < memory_map.rom_banks)
+                .ok_or(format!(
+                    "Line {}: invalid bank '{}', the ROM has {} banks",
+                    line_number + 1,
+                    bank,
+                    memory_map.rom_banks
+                ))?;
+            let start = memory_map.rom_image_address(bank, memory_map.rom_window());
+            if start < position {
+                return Err(format!(
+                    "Line {}: bank {} starts at {}, but the code before it already reaches {}",
+                    line_number + 1,
+                    bank,
+                    start,
+                    position
+                ));
+            }
+            position = start;
+        } else if let Some(label) = code.strip_prefix("FARJMP") {
+            if !memory_map.is_banked() || !is_valid_symbol(label) {
+                return Err(format!(
+                    "Line {}: FARJMP needs bank switching and a label, found '{}'",
+                    line_number + 1,
+                    code
+                ));
+            }
+            if !far_targets.iter().any(|(_, target)| target == label) {
+                far_targets.push((line_number + 1, label.to_owned()));
+            }
+            instructions.push((
+                line_number + 1,
+                position,
+                format!("@{}{}", TRAMPOLINE_PREFIX, label),
+            ));
+            instructions.push((line_number + 1, position + 1, "0;JMP".to_owned()));
+            position += 2;
+        } else if let Some(label) = code.strip_prefix('(') {
+            let label = label
+                .strip_suffix(')')
+                .filter(|label| is_valid_symbol(label) && !label.starts_with(TRAMPOLINE_PREFIX))
+                .ok_or(format!(
+                    "Line {}: invalid label '{}'",
+                    line_number + 1,
+                    code
+                ))?;
+
+            if symbols.contains_key(label) {
+                return Err(format!(
+                    "Line {}: label '{}' is already defined",
+                    line_number + 1,
+                    label
+                ));
+            }
+            let (bank, address) = memory_map.rom_bank_address(position);
+            symbols.insert(label.to_owned(), (origin + address) as i16);
+            label_banks.insert(label.to_owned(), bank);
+        } else {
+            instructions.push((line_number + 1, position, code));
+            position += 1;
+        }
+    }
+
+    // the trampolines fill the end of the fixed part of the ROM
+    let trampolines = memory_map.rom_window()
+        - (far_targets.len() * TRAMPOLINE_SIZE).min(memory_map.rom_window());
+    if !far_targets.is_empty() {
+        if let Some((line_number, _, _)) = instructions
+            .iter()
+            .find(|(_, position, _)| (trampolines..memory_map.rom_window()).contains(position))
+        {
+            return Err(format!(
+                "Line {}: the FARJMP trampolines need ROM[{}..{}], the code cannot be there",
+                line_number,
+                trampolines,
+                memory_map.rom_window()
+            ));
+        }
+    }
+    for (i, (line_number, label)) in far_targets.into_iter().enumerate() {
+        let bank = *label_banks.get(&label).ok_or(format!(
+            "Line {}: FARJMP to unknown label '{}'",
+            line_number, label
+        ))?;
+        let start = trampolines + i * TRAMPOLINE_SIZE;
+        symbols.insert(format!("{}{}", TRAMPOLINE_PREFIX, label), start as i16);
+
+        let code = [
+            format!("@{}", bank),
+            "D=A".to_owned(),
+            format!("@{}", BANK_SELECT_ADDRESS),
+            "M=D".to_owned(),
+            format!("@{}", label),
+            "0;JMP".to_owned(),
+        ];
+        for (offset, code) in code.into_iter().enumerate() {
+            instructions.push((line_number, start + offset, code));
+        }
+    }
+
+    // second pass: translate
+    let mut next_variable = VARIABLE_BASE;
+    let length = instructions
+        .iter()
+        .map(|(_, position, _)| position + 1)
+        .max()
+        .unwrap_or(0);
+    let mut binary = vec![0; length];
+    for (line_number, position, code) in instructions {
+        let instruction = if let Some(value) = code.strip_prefix('@') {
+            if let Ok(number) = value.parse::<u32>() {
+                if number > 0x7FFF {
+                    return Err(format!(
+                        "Line {}: '{}' does not fit into 15 bits",
+                        line_number, value
+                    ));
+                }
+                Instruction::A(number as i16)
+            } else if is_valid_symbol(value) {
+                let address = *symbols.entry(value.to_owned()).or_insert_with(|| {
+                    next_variable += 1;
+                    next_variable - 1
+                });
+                Instruction::A(address)
+            } else {
+                return Err(format!("Line {}: invalid symbol '{}'", line_number, value));
+            }
+        } else if code == "RETI" {
+            binary[position] = RETI;
+            continue;
+        } else {
+            parse_stack_instruction(&code)
+                .or_else(|| parse_c_instruction(&code))
+                .ok_or(format!(
+                    "Line {}: invalid instruction '{}'",
+                    line_number, code
+                ))?
+        };
+
+        binary[position] = instruction.encode();
+    }
+
+    Ok(binary)
+}
+
+/// Reads a `.hack` file: one instruction per line, as 16 binary digits.
+/// Empty lines and `//` comments are skipped.
+pub fn hack_to_binary(content: &str) -> Result<Vec<i16>, String> {
+    content
+        .lines()
+        .enumerate()
+        .map(|(line_number, line)| (line_number, clean_line(line)))
+        .filter(|(_, code)| !code.is_empty())
+        .map(|(line_number, code)| match u16::from_str_radix(&code, 2) {
+            Ok(word) if code.len() == 16 && !code.starts_with('+') => Ok(word as i16),
+            _ => Err(format!(
+                "Line {}: expected 16 binary digits, found '{}'",
+                line_number + 1,
+                code
+            )),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn test_asm_to_binary() {
+        use super::asm_to_binary;
+
+        let program = "
+            // comment only
+            @6 // value
+            D=A
+            @R2
+            M=D
+            (LOOP)
+            @i
+            AM=M+1
+            @LOOP
+            D;JGT
+            @SCREEN
+            0;JMP
+        ";
+
+        assert_eq!(
+            asm_to_binary(program),
+            Ok(vec![6, -5104, 2, -7416, 16, -536, 4, -7423, 16384, -5497])
+        );
+    }
+
+    #[test]
+    fn test_asm_to_binary_errors() {
+        use super::asm_to_binary;
+
+        assert_eq!(
+            asm_to_binary("@1\nD=X"),
+            Err("Line 2: invalid instruction 'D=X'".to_owned())
+        );
+        assert!(asm_to_binary("(1LOOP)").is_err());
+        assert!(asm_to_binary("(LOOP)\n(LOOP)").is_err());
+        assert!(asm_to_binary("@40000").is_err());
+    }
+
+    #[test]
+    fn test_asm_extensions() {
+        use super::asm_to_binary;
+
+        assert_eq!(
+            asm_to_binary("D=D<<\nM=A>>\nAM=M<<;JLT\nRETI"),
+            Ok(vec![
+                0b1010_1100_0001_0000u16 as i16,
+                0b1010_0000_0000_1000u16 as i16,
+                0b1011_1000_0010_1100u16 as i16,
+                0b1000_0000_0000_0000u16 as i16,
+            ])
+        );
+        assert!(asm_to_binary("D=D>>1").is_err());
+
+        assert_eq!(
+            asm_to_binary("PUSH D\nPUSH A+1\nPOP AD\nPOP\nCALL\nRET\nSP=D\nA=SP"),
+            Ok(vec![
+                0b1100_0011_0000_0000u16 as i16,
+                0b1100_1101_1100_0000u16 as i16,
+                0b1100_0000_0011_0001u16 as i16,
+                0b1100_0000_0000_0001u16 as i16,
+                0b1100_0000_0000_0010u16 as i16,
+                0b1100_0000_0000_0011u16 as i16,
+                0b1100_0011_0000_0100u16 as i16,
+                0b1100_0000_0010_0101u16 as i16,
+            ])
+        );
+        assert!(asm_to_binary("PUSH M").is_err());
+        assert!(asm_to_binary("POP M").is_err());
+        assert!(asm_to_binary("M=SP").is_err());
+    }
+
+    #[test]
+    fn test_hack_to_binary() {
+        use super::hack_to_binary;
+
+        assert_eq!(
+            hack_to_binary("0000000000000110\n\n1110110000010000 // D=A\n"),
+            Ok(vec![6, -5104])
+        );
+        assert!(hack_to_binary("111011000001000").is_err());
+        assert!(hack_to_binary("111011000001000x").is_err());
+    }
+
+    #[test]
+    fn test_asm_symbols_follow_memory_map() {
+        use super::*;
+
+        let memory_map = MemoryMap {
+            ram_size: 20000,
+            screen_address: 20000,
+            keyboard_address: 28192,
+            ..MemoryMap::default()
+        };
+
+        assert_eq!(
+            asm_to_binary_with_memory_map("@SCREEN\n@KBD\n@x", &memory_map),
+            Ok(vec![20000, 28192, 16])
+        );
+    }
+
+    #[test]
+    fn test_asm_banks() {
+        use super::*;
+
+        // the fixed part is ROM[0..16), the window ROM[16..32)
+        let memory_map = MemoryMap {
+            rom_size: 32,
+            rom_banks: 3,
+            ..MemoryMap::default()
+        };
+        let program = "@1\nD=A\nFARJMP FAR\n(LOOP)\n@LOOP\n0;JMP\n.bank 2\n(FAR)\n@FAR\n0;JMP";
+
+        let mut expected = vec![0; 50];
+        expected[..6].copy_from_slice(&[1, -5104, 10, -5497, 4, -5497]);
+        // trampoline: @2 D=A @ROMBANK M=D @FAR 0;JMP
+        expected[10..16].copy_from_slice(&[2, -5104, 32766, -7416, 16, -5497]);
+        // bank 2 starts after the fixed part and the banks 0 and 1
+        expected[48..].copy_from_slice(&[16, -5497]);
+        assert_eq!(
+            asm_to_binary_with_memory_map(program, &memory_map),
+            Ok(expected)
+        );
+
+        assert!(asm_to_binary_with_memory_map(".bank 3", &memory_map).is_err());
+        assert!(asm_to_binary_with_memory_map(".bank 1\n@1\n.bank 0", &memory_map).is_err());
+        assert!(asm_to_binary_with_memory_map("FARJMP NOWHERE", &memory_map).is_err());
+        assert!(asm_to_binary("(X)\nFARJMP X").is_err());
+        assert!(asm_to_binary(".bank 0\n@1").is_err());
+        // the trampoline labels are reserved
+        assert!(
+            asm_to_binary_with_memory_map("FARJMP X\n($far.X)\n.bank 1\n(X)", &memory_map).is_err()
+        );
+
+        // the code reaches the trampoline
+        let long = "@1\n".repeat(12) + "(X)\nFARJMP X";
+        assert!(asm_to_binary_with_memory_map(&long, &memory_map).is_err());
+    }
+
+    #[test]
+    fn test_asm_origin() {
+        use super::*;
+
+        assert_eq!(
+            asm_to_binary(".origin 1024\n(LOOP)\n@x\n@LOOP\n(END)\n@END"),
+            Ok(vec![16, 1024, 1026])
+        );
+        assert!(asm_to_binary("@1\n.origin 1024").is_err());
+        assert!(asm_to_binary(".origin 32768").is_err());
+    }
 }
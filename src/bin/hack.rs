@@ -0,0 +1,332 @@
+#![warn(clippy::all, rust_2018_idioms)]
+
+// Runs a Hack program in the terminal, without the GUI.
+//
+// usage: hack <program.asm | program.hack> [--gate] [--steps N] [--wav sound.wav] [--banks N]
+//             [--tape program.asm] [--seed N] [--uninitialised warn | trap]
+//             [--protect START-END:read-only | no-execute | guarded] ...
+//
+// The serial console is connected to stdin and stdout, and the other devices are attached too.
+// At the end of stdin, the console input reads -1; a program that keeps reading it is stopped.
+// With `--wav`, the output of the tone generator is saved, when the program stops.
+// With `--banks`, ROM and RAM have N banks each, for programs larger than 32K instructions.
+// With `--tape`, the computer is in the Von Neumann mode: the program in the ROM is a loader,
+// e.g. `specs/examples/loader.asm`, and the tape reader holds the program that it loads.
+// With `--seed`, the RAM and the registers start with garbage instead of zeros.
+// With `--uninitialised`, reads of registers and memory, that nothing has written,
+// are printed as warnings, or stop the program.
+// With `--protect`, accesses to the region, that it forbids, stop the program, e.g.
+// `--protect 24576-32767:read-only` catches loops, that write past the end of the screen.
+// Runs until the program exits through the test port, halts in its end loop, traps,
+// or has executed N instructions.
+// Exit code: 0 when the program passed or halted, 1 on errors, traps and failed assertions,
+// 2 when the steps ran out. A non-zero code, that the program wrote into the test port,
+// exits with 16 + the code, or 255 when that does not fit, so that it never looks like a pass
+// or like one of the codes above.
+
+#[cfg(not(target_arch = "wasm32"))]
+fn main() {
+    std::process::exit(cli::run(std::env::args().skip(1).collect()));
+}
+
+#[cfg(target_arch = "wasm32")]
+fn main() {}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod cli {
+    use std::{
+        cell::RefCell,
+        io::{self, BufRead, Write},
+        rc::Rc,
+        sync::mpsc::{self, TryRecvError},
+        thread,
+    };
+
+    use web_pc::{
+        assembler::{asm_to_binary_with_memory_map, hack_to_binary},
+        machine::{
+            config::{MachineConfig, MemoryMap},
+            devices::{
+                multiplier::{Multiplier, MULTIPLIER_ADDRESS},
+                sound::{self, Sound, SOUND_ADDRESS},
+                tape::{Tape, LOAD_ADDRESS, TAPE_ADDRESS},
+                test_port::{run_steps, TestOutcome, TestPort, TEST_PORT_ADDRESS},
+                timer::{Timer, TIMER_ADDRESS},
+                tty::{Tty, TTY_ADDRESS},
+            },
+            power_on_with_config,
+            protection::Protection,
+            runner::{IllegalPolicy, Runner},
+            Backend,
+        },
+    };
+
+    /// Exit code for the first failure code, that the program writes into the test port.
+    const FAILED_BASE: i32 = 16;
+
+    /// Instructions between the console updates.
+    const CHUNK: usize = 10_000;
+
+    /// Reads of the console after the end of stdin, that stop the program.
+    const MAX_POLLS_AFTER_END: usize = 1000;
+
+    const USAGE: &str = "usage: hack <program.asm | program.hack> [--gate] [--steps N] \
+                         [--wav sound.wav] [--banks N] [--tape program.asm] [--seed N] \
+                         [--uninitialised warn | trap] \
+                         [--protect START-END:read-only | no-execute | guarded] ...";
+
+    struct Options {
+        path: String,
+        backend: Backend,
+        steps: Option<usize>,
+        wav: Option<String>,
+        banks: usize,
+        tape: Option<String>,
+        seed: Option<u64>,
+        uninitialised: IllegalPolicy,
+        protection: Vec<Protection>,
+    }
+
+    fn parse_options(args: Vec<String>) -> Result<Options, String> {
+        let mut path = None;
+        let mut backend = Backend::Emulated;
+        let mut steps = None;
+        let mut wav = None;
+        let mut banks = 1;
+        let mut tape = None;
+        let mut seed = None;
+        let mut uninitialised = IllegalPolicy::Ignore;
+        let mut protection = Vec::new();
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--gate" => backend = Backend::GateLevel,
+                "--steps" => {
+                    let value = args.next().ok_or("--steps needs a number")?;
+                    let value = value
+                        .parse()
+                        .map_err(|_| format!("Invalid number of steps '{}'", value))?;
+                    steps = Some(value);
+                }
+                "--banks" => {
+                    let value = args.next().ok_or("--banks needs a number")?;
+                    banks = value
+                        .parse()
+                        .map_err(|_| format!("Invalid number of banks '{}'", value))?;
+                }
+                "--wav" => wav = Some(args.next().ok_or("--wav needs a file name")?),
+                "--seed" => {
+                    let value = args.next().ok_or("--seed needs a number")?;
+                    let value = value
+                        .parse()
+                        .map_err(|_| format!("Invalid seed '{}'", value))?;
+                    seed = Some(value);
+                }
+                "--uninitialised" => {
+                    uninitialised = match args.next().as_deref() {
+                        Some("warn") => IllegalPolicy::Warn,
+                        Some("trap") => IllegalPolicy::Trap,
+                        _ => return Err("--uninitialised needs 'warn' or 'trap'".to_owned()),
+                    };
+                }
+                "--protect" => {
+                    let value = args.next().ok_or("--protect needs a region")?;
+                    protection.push(Protection::parse(&value)?);
+                }
+                "--tape" => tape = Some(args.next().ok_or("--tape needs a file name")?),
+                _ if path.is_none() && !arg.starts_with("--") => path = Some(arg),
+                _ => return Err(format!("Unknown argument '{}'", arg)),
+            }
+        }
+
+        Ok(Options {
+            path: path.ok_or(USAGE)?,
+            backend,
+            steps,
+            wav,
+            banks,
+            tape,
+            seed,
+            uninitialised,
+            protection,
+        })
+    }
+
+    fn load_program(path: &str, memory_map: &MemoryMap) -> Result<Vec<i16>, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|error| format!("Cannot read '{}': {}", path, error))?;
+
+        if path.ends_with(".hack") {
+            hack_to_binary(&content)
+        } else {
+            asm_to_binary_with_memory_map(&content, memory_map)
+        }
+    }
+
+    /// Lines from stdin, read on another thread, so that the program keeps running.
+    /// Hangs up the channel at the end of stdin.
+    fn spawn_stdin_reader() -> mpsc::Receiver<String> {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines() {
+                let Ok(line) = line else { break };
+                if sender.send(line + "\n").is_err() {
+                    break;
+                }
+            }
+        });
+
+        receiver
+    }
+
+    pub fn run(args: Vec<String>) -> i32 {
+        let options = match parse_options(args) {
+            Ok(options) => options,
+            Err(error) => {
+                eprintln!("{}", error);
+                return 1;
+            }
+        };
+        let mut memory = MemoryMap {
+            rom_banks: options.banks,
+            ram_banks: options.banks,
+            ..MemoryMap::default()
+        };
+        if options.tape.is_some() {
+            memory.rom_size = LOAD_ADDRESS;
+            memory.execute_ram = true;
+        }
+        let config = MachineConfig {
+            memory,
+            power_on_seed: options.seed,
+            ..MachineConfig::default()
+        };
+        let program = match load_program(&options.path, &config.memory) {
+            Ok(program) => program,
+            Err(error) => {
+                eprintln!("{}", error);
+                return 1;
+            }
+        };
+        let tape = match &options.tape {
+            Some(path) => match load_program(path, &config.memory) {
+                Ok(words) => Tape::new(words),
+                Err(error) => {
+                    eprintln!("{}", error);
+                    return 1;
+                }
+            },
+            None => Tape::default(),
+        };
+
+        let tty = Rc::new(RefCell::new(Tty::default()));
+        let port = Rc::new(RefCell::new(TestPort::default()));
+        let sound = Rc::new(RefCell::new(Sound::default()));
+        let mut machine = match power_on_with_config(options.backend, config, program.clone()) {
+            Ok(machine) => machine,
+            Err(error) => {
+                eprintln!("{}", error);
+                return 1;
+            }
+        };
+        machine
+            .attach_device(TIMER_ADDRESS, Box::<Timer>::default())
+            .expect("the Hack memory map has room for the timer");
+        machine
+            .attach_device(TTY_ADDRESS, Box::new(tty.clone()))
+            .expect("the Hack memory map has room for the console");
+        machine
+            .attach_device(TEST_PORT_ADDRESS, Box::new(port.clone()))
+            .expect("the Hack memory map has room for the test port");
+        machine
+            .attach_device(SOUND_ADDRESS, Box::new(sound.clone()))
+            .expect("the Hack memory map has room for the tone generator");
+        machine
+            .attach_device(MULTIPLIER_ADDRESS, Box::<Multiplier>::default())
+            .expect("the Hack memory map has room for the multiplier");
+        machine
+            .attach_device(TAPE_ADDRESS, Box::new(tape))
+            .expect("the Hack memory map has room for the tape reader");
+        let mut runner = Runner::new(machine, program.len(), IllegalPolicy::Trap);
+        runner.uninitialised = options.uninitialised;
+        runner.protection = options.protection;
+
+        let input = spawn_stdin_reader();
+        let mut stdout = io::stdout();
+        let mut write_output = || {
+            let output = tty.borrow_mut().take_output();
+            let _ = stdout.write_all(output.as_bytes());
+            let _ = stdout.flush();
+        };
+        let max_steps = options.steps.unwrap_or(usize::MAX);
+
+        let mut steps = 0;
+        let mut warnings = 0;
+        // the tone generator is drained every chunk, and only kept for `--wav`
+        let mut samples = Vec::new();
+        let outcome = loop {
+            write_output();
+            loop {
+                match input.try_recv() {
+                    Ok(line) => tty.borrow_mut().push_input(&line),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        tty.borrow_mut().close_input();
+                        break;
+                    }
+                }
+            }
+            if tty.borrow().polls_after_end() > MAX_POLLS_AFTER_END {
+                break Err("The program keeps reading the console after the end of the input");
+            }
+
+            let result = run_steps(&mut runner, &port, CHUNK.min(max_steps - steps));
+            for warning in &runner.warnings()[warnings..] {
+                eprintln!("{}", warning);
+            }
+            warnings = runner.warnings().len();
+            let chunk_samples = sound.borrow_mut().take_samples();
+            if options.wav.is_some() {
+                samples.extend(chunk_samples);
+            }
+            steps += result.steps;
+            match result.outcome {
+                TestOutcome::Timeout if steps < max_steps => {}
+                outcome => break Ok(outcome),
+            }
+        };
+        write_output();
+
+        if let Some(path) = options.wav {
+            let sample_rate = sound.borrow().sample_rate();
+            if let Err(error) = std::fs::write(&path, sound::wav(&samples, sample_rate)) {
+                eprintln!("Cannot write '{}': {}", path, error);
+                return 1;
+            }
+        }
+
+        let outcome = match outcome {
+            Ok(outcome) => outcome,
+            Err(error) => {
+                eprintln!("{}", error);
+                return 1;
+            }
+        };
+        match outcome {
+            TestOutcome::Passed | TestOutcome::Halted => 0,
+            TestOutcome::Failed { exit_code } if exit_code > 0 => {
+                (FAILED_BASE + exit_code as i32).min(255)
+            }
+            TestOutcome::Failed { .. } => 255,
+            TestOutcome::Timeout => {
+                eprintln!("The program did not halt in {} steps", max_steps);
+                2
+            }
+            _ => {
+                eprintln!("{}", outcome);
+                1
+            }
+        }
+    }
+}
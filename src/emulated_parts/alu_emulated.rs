@@ -0,0 +1,110 @@
+// Word-level version of the ALU in `hack_computer::chips::alu`.
+// Same truth table, but calculated with native integers instead of gates.
+
+use crate::hack_computer::chips::alu::AluFlags;
+
+/// ALU for 16-bit words.
+///
+/// `control` holds the six control bits in the same order as in the C-instruction:
+/// `zx nx zy ny f no` (bit 5 is zx, bit 0 is no).
+pub fn alu_emulated(x: i16, y: i16, control: u16) -> (i16, bool, bool) {
+    let (out, flags) = alu_emulated_with_flags(x, y, control);
+
+    (out, flags.zr, flags.ng)
+}
+
+/// Same as `alu_emulated`, but also gives the carry and overflow flags.
+pub fn alu_emulated_with_flags(x: i16, y: i16, control: u16) -> (i16, AluFlags) {
+    let zx = control & 0b100000 != 0;
+    let nx = control & 0b010000 != 0;
+    let zy = control & 0b001000 != 0;
+    let ny = control & 0b000100 != 0;
+    let f = control & 0b000010 != 0;
+    let no = control & 0b000001 != 0;
+
+    let x = if zx { 0 } else { x };
+    let x = if nx { !x } else { x };
+    let y = if zy { 0 } else { y };
+    let y = if ny { !y } else { y };
+    let out = if f { x.wrapping_add(y) } else { x & y };
+    let out = if no { !out } else { out };
+
+    let carry = (x as u16).checked_add(y as u16).is_none();
+
+    (
+        out,
+        AluFlags {
+            zr: out == 0,
+            ng: out < 0,
+            carry: f && carry != no,
+            overflow: f && x.checked_add(y).is_none(),
+        },
+    )
+}
+
+/// Word-level version of `hack_computer::chips::shifter::shifter16`.
+/// The right shift is arithmetic.
+pub fn shifter_emulated(input: i16, left: bool) -> (i16, bool, bool) {
+    let out = if left { input << 1 } else { input >> 1 };
+
+    (
+        out,
+        out == 0, // zr
+        out < 0,  // ng
+    )
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn test_alu_emulated_matches_alu() {
+        use super::{alu_emulated, alu_emulated_with_flags};
+        use crate::{
+            hack_computer::chips::alu::{alu, alu_with_flags},
+            utils::convert_16b::{from_b16, from_i16},
+        };
+
+        let inputs: [(i16, i16); 4] = [(0, 0), (-5242, 6253), (32767, 1), (-32768, -1)];
+
+        for (x, y) in inputs {
+            for control in 0..64u16 {
+                let bit = |n: u16| control & (1 << n) != 0;
+                let (out, zr, ng) = alu(
+                    from_i16(x).unwrap().as_array_b16,
+                    from_i16(y).unwrap().as_array_b16,
+                    bit(5),
+                    bit(4),
+                    bit(3),
+                    bit(2),
+                    bit(1),
+                    bit(0),
+                );
+
+                let expected = (from_b16(out).unwrap().as_integer, zr, ng);
+                assert_eq!(
+                    alu_emulated(x, y, control),
+                    expected,
+                    "x: {}, y: {}, control: {:06b}",
+                    x,
+                    y,
+                    control
+                );
+
+                let control_bits = [5, 4, 3, 2, 1, 0].map(bit);
+                let (_, flags) = alu_with_flags(
+                    from_i16(x).unwrap().as_array_b16,
+                    from_i16(y).unwrap().as_array_b16,
+                    control_bits,
+                );
+                assert_eq!(
+                    alu_emulated_with_flags(x, y, control).1,
+                    flags,
+                    "x: {}, y: {}, control: {:06b}",
+                    x,
+                    y,
+                    control
+                );
+            }
+        }
+    }
+}
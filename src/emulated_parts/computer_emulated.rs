@@ -0,0 +1,475 @@
+use crate::machine::{
+    config::{Extensions, MachineConfig, MemoryMap, Region, BANK_SELECT_ADDRESS},
+    device::{Device, DeviceMap, ADDRESS_SPACE},
+    instruction::{
+        is_reti, STACK_CALL, STACK_GET_SP, STACK_POP, STACK_PUSH, STACK_RET, STACK_SET_SP,
+    },
+    key::{Key, KeyQueue},
+    CpuState, InterruptState, Machine, StepInfo,
+};
+
+use super::alu_emulated::{alu_emulated, shifter_emulated};
+
+/// Hack computer that executes the instructions directly on words.
+///
+/// It behaves like `hack_computer::computer::Computer`,
+/// but it skips the gates, latches and buses completely.
+/// Use it when you need speed, e.g. programs that redraw the screen.
+///
+/// Unlike the gate-level computer, it can use any valid `MemoryMap`, also with bank switching
+/// or in the Von Neumann mode.
+pub struct ComputerEmulated {
+    memory_map: MemoryMap,
+    extensions: Extensions,
+    power_on_seed: Option<u64>,
+
+    // every ROM bank, see `MemoryMap::rom_image_address`
+    rom: Vec<i16>,
+
+    // the whole data address space, only the mapped regions and the bank registers are used
+    ram: Vec<i16>,
+
+    // RAM window of the banks 1 and up, bank 0 is in `ram`
+    ram_banks: Vec<i16>,
+
+    // cpu
+    a: i16,
+    d: i16,
+    pc: u16,
+
+    // interrupt extension
+    interrupt: InterruptState,
+
+    // stack extension
+    sp: i16,
+
+    // events
+    keys: KeyQueue,
+
+    // memory-mapped peripherals after the keyboard
+    devices: DeviceMap,
+}
+
+impl ComputerEmulated {
+    pub fn power_on(rom_disk: Vec<i16>) -> Self {
+        Self::power_on_with_memory_map(rom_disk, MemoryMap::default())
+    }
+
+    /// The memory map must be valid, see `MemoryMap::validate`.
+    pub fn power_on_with_memory_map(rom_disk: Vec<i16>, memory_map: MemoryMap) -> Self {
+        Self::power_on_with_config(
+            rom_disk,
+            MachineConfig {
+                memory: memory_map,
+                ..MachineConfig::default()
+            },
+        )
+    }
+
+    /// Uses the memory map and the extensions of the config. The fidelities do not matter here.
+    pub fn power_on_with_config(rom_disk: Vec<i16>, config: MachineConfig) -> Self {
+        let memory_map = config.memory;
+        let mut rom = vec![0; memory_map.rom_capacity()];
+        for (i, word) in rom_disk.into_iter().take(rom.len()).enumerate() {
+            rom[i] = word;
+        }
+        let ram_window = memory_map.ram_size - memory_map.ram_window();
+
+        let mut values = config.power_on_values();
+        let (a, d, sp) = (values(), values(), values());
+        let mut ram = vec![0; ADDRESS_SPACE];
+        let screen =
+            memory_map.screen_address..memory_map.screen_address + memory_map.screen_words();
+        for address in (0..memory_map.ram_size).chain(screen) {
+            ram[address] = values();
+        }
+        let ram_banks = (0..ram_window * (memory_map.ram_banks - 1))
+            .map(|_| values())
+            .collect();
+
+        Self {
+            memory_map,
+            extensions: config.extensions,
+            power_on_seed: config.power_on_seed,
+            rom,
+            ram,
+            ram_banks,
+            a,
+            d,
+            pc: 0,
+            interrupt: InterruptState::default(),
+            sp,
+            keys: KeyQueue::default(),
+            devices: DeviceMap::new(memory_map),
+        }
+    }
+
+    /// Sets the value of the keyboard register.
+    pub fn get_input_from_io_device(&mut self, input: i16) {
+        self.ram[self.memory_map.keyboard_address] = input;
+    }
+
+    /// Executes one instruction.
+    pub fn run_instruction(&mut self) -> StepInfo {
+        // nothing is connected after the end of the ROM, and it reads as 0
+        let instruction = self.read_rom(self.pc as usize) as u16;
+        let mut next_pc = self.pc.wrapping_add(1);
+        let mut info = StepInfo {
+            pc: self.pc as i16,
+            instruction: instruction as i16,
+            memory_write: None,
+        };
+        // the address of the word, that the CPU takes from the memory
+        let mut read_address = None;
+
+        if instruction & 0x8000 == 0 {
+            // A-instruction
+            self.a = instruction as i16;
+        } else if self.extensions.interrupts() && is_reti(instruction as i16) {
+            next_pc = self.interrupt.return_address as u16;
+            self.interrupt.in_handler = false;
+        } else if self.extensions.stack && instruction & 0xE000 == 0xC000 {
+            // stack: 1 1 0 a c1 c2 c3 c4 c5 c6 d1 d2 d3 o1 o2 o3
+            let op = instruction & 0x7;
+            let pops = op == STACK_POP || op == STACK_RET;
+
+            // the memory is addressed with SP instead of A, the ALU still sees A
+            let sp = self.sp;
+            let address = if pops { sp.wrapping_sub(1) } else { sp };
+            if pops || instruction & 0x1000 != 0 {
+                read_address = Some(address);
+            }
+            let y = if instruction & 0x1000 != 0 {
+                self.read(address)
+            } else {
+                self.a
+            };
+            let (alu_out, _, _) = alu_emulated(self.d, y, (instruction >> 6) & 0x3F);
+
+            match op {
+                STACK_PUSH | STACK_CALL => {
+                    let value = match op {
+                        STACK_CALL => self.pc.wrapping_add(1) as i16,
+                        _ => alu_out,
+                    };
+                    self.write(address, value);
+                    info.memory_write = Some(((address as u16 & 0x7FFF) as usize, value));
+                    self.sp = sp.wrapping_add(1);
+                }
+                STACK_POP | STACK_RET => self.sp = address,
+                STACK_SET_SP => self.sp = alu_out,
+                _ => {}
+            }
+
+            let out = match op {
+                STACK_POP => self.read(address),
+                STACK_GET_SP => sp,
+                _ => 0,
+            };
+            if op == STACK_POP || op == STACK_GET_SP {
+                if instruction & 0x20 != 0 {
+                    self.a = out;
+                }
+                if instruction & 0x10 != 0 {
+                    self.d = out;
+                }
+            }
+
+            match op {
+                STACK_CALL => next_pc = self.a as u16,
+                STACK_RET => next_pc = self.read(address) as u16,
+                _ => {}
+            }
+        } else {
+            // C-instruction: 1 x x a c1 c2 c3 c4 c5 c6 d1 d2 d3 j1 j2 j3
+            let address = self.a;
+            if instruction & 0x1000 != 0 {
+                read_address = Some(address);
+            }
+            let y = if instruction & 0x1000 != 0 {
+                self.read(address)
+            } else {
+                self.a
+            };
+
+            // shift: 1 0 1 a l s 0 0 0 0 d1 d2 d3 j1 j2 j3
+            let (out, zr, ng) = if self.extensions.shifts && instruction & 0xE000 == 0xA000 {
+                let source = if instruction & 0x1400 == 0x0400 {
+                    self.d
+                } else {
+                    y
+                };
+                shifter_emulated(source, instruction & 0x0800 != 0)
+            } else {
+                alu_emulated(self.d, y, (instruction >> 6) & 0x3F)
+            };
+
+            if instruction & 0x20 != 0 {
+                self.a = out;
+            }
+            if instruction & 0x10 != 0 {
+                self.d = out;
+            }
+            if instruction & 0x08 != 0 {
+                self.write(address, out);
+                info.memory_write = Some(((address as u16 & 0x7FFF) as usize, out));
+            }
+
+            let jump = (instruction & 0x4 != 0 && ng)
+                || (instruction & 0x2 != 0 && zr)
+                || (instruction & 0x1 != 0 && !zr && !ng);
+            if jump {
+                next_pc = address as u16;
+            }
+        }
+
+        // the keyboard register is latched at the end of the cycle, like in the gate-level computer
+        if let Some(code) = self.keys.next_code() {
+            let keyboard = &mut self.ram[self.memory_map.keyboard_address];
+            self.interrupt.pending |= *keyboard != code;
+            *keyboard = code;
+        }
+
+        // an interrupt replaces the next instruction with the handler
+        if let Some(vector) = self.extensions.interrupt_vector {
+            if self.interrupt.pending && !self.interrupt.in_handler {
+                self.interrupt.pending = false;
+                self.interrupt.in_handler = true;
+                self.interrupt.return_address = next_pc as i16;
+                next_pc = vector;
+            }
+        }
+
+        self.pc = next_pc & 0x7FFF;
+
+        // requests from the devices are taken after the next instruction
+        if let Some(address) = read_address {
+            self.devices.read_strobe(address as u16 as usize & 0x7FFF);
+        }
+        self.interrupt.pending |= self.devices.tick();
+
+        info
+    }
+
+    fn read(&self, address: i16) -> i16 {
+        // the data address bus is 15 bits wide
+        let address = (address as u16 & 0x7FFF) as usize;
+        self.read_memory(address)
+    }
+
+    fn write(&mut self, address: i16, value: i16) {
+        let address = (address as u16 & 0x7FFF) as usize;
+
+        // the keyboard is read-only, and the devices are in the unmapped addresses
+        match self.memory_map.region(address) {
+            Region::Ram => match self.ram_bank_index(address) {
+                Some(index) => self.ram_banks[index] = value,
+                None => self.ram[address] = value,
+            },
+            Region::Screen | Region::BankSelect => self.ram[address] = value,
+            Region::Keyboard => {}
+            Region::Unmapped => {
+                self.devices.write(address, value);
+            }
+        }
+    }
+
+    pub fn get_ram(&self, start: usize, end: usize) -> Vec<(usize, i16)> {
+        let max = end.min(self.memory_map.size());
+        (start..max).map(|i| (i, self.read_memory(i))).collect()
+    }
+
+    /// Selected ROM bank and RAM bank.
+    fn banks(&self) -> (usize, usize) {
+        let register = |offset: usize| self.ram[BANK_SELECT_ADDRESS + offset] as u16 as usize;
+        (
+            register(0) % self.memory_map.rom_banks,
+            register(1) % self.memory_map.ram_banks,
+        )
+    }
+
+    /// Index in `ram_banks`, when the address is in the RAM window, and it shows a bank after 0.
+    fn ram_bank_index(&self, address: usize) -> Option<usize> {
+        let window = self.memory_map.ram_window()..self.memory_map.ram_size;
+        let (_, bank) = self.banks();
+        if bank == 0 || !window.contains(&address) {
+            return None;
+        }
+
+        Some((bank - 1) * window.len() + address - window.start)
+    }
+}
+
+impl Machine for ComputerEmulated {
+    fn step(&mut self) -> StepInfo {
+        self.run_instruction()
+    }
+
+    fn cpu_state(&self) -> CpuState {
+        CpuState {
+            a: self.a,
+            d: self.d,
+            pc: self.pc as i16,
+        }
+    }
+
+    fn read_memory(&self, address: usize) -> i16 {
+        match self.memory_map.region(address) {
+            Region::Unmapped => self.devices.read(address).unwrap_or(0),
+            Region::Ram => match self.ram_bank_index(address) {
+                Some(index) => self.ram_banks[index],
+                None => self.ram[address],
+            },
+            _ => self.ram[address],
+        }
+    }
+
+    /// Reads through the selected ROM bank, or from the data memory in the Von Neumann mode.
+    fn read_rom(&self, address: usize) -> i16 {
+        if self.memory_map.fetches_from_ram(address) {
+            return self.read_memory(address);
+        }
+        if address >= self.memory_map.rom_size {
+            return 0;
+        }
+
+        let (bank, _) = self.banks();
+        let address = self.memory_map.rom_image_address(bank, address);
+        self.rom.get(address).copied().unwrap_or(0)
+    }
+
+    fn key_down(&mut self, key: Key) {
+        self.keys.key_down(key);
+    }
+
+    fn key_up(&mut self, key: Key) {
+        self.keys.key_up(key);
+    }
+
+    fn memory_map(&self) -> MemoryMap {
+        self.memory_map
+    }
+
+    fn extensions(&self) -> Extensions {
+        self.extensions
+    }
+
+    fn interrupt_state(&self) -> InterruptState {
+        if !self.extensions.interrupts() {
+            return InterruptState::default();
+        }
+
+        InterruptState {
+            return_address: self.interrupt.return_address & 0x7FFF,
+            ..self.interrupt
+        }
+    }
+
+    fn may_interrupt(&self) -> bool {
+        self.extensions.interrupts() && (self.interrupt.pending || self.devices.may_interrupt())
+    }
+
+    fn attach_device(&mut self, base: usize, device: Box<dyn Device>) -> Result<(), String> {
+        self.devices.attach(base, device)
+    }
+
+    fn stack_pointer(&self) -> Option<i16> {
+        self.extensions.stack.then_some(self.sp)
+    }
+
+    fn reset(&mut self) {
+        self.pc = 0;
+        self.interrupt = InterruptState::default();
+    }
+
+    fn power_cycle(&mut self) {
+        let config = MachineConfig {
+            memory: self.memory_map,
+            extensions: self.extensions,
+            power_on_seed: self.power_on_seed,
+            ..MachineConfig::default()
+        };
+        let rom = std::mem::take(&mut self.rom);
+        let devices = std::mem::take(&mut self.devices);
+        *self = Self {
+            rom,
+            devices,
+            ..Self::power_on_with_config(Vec::new(), config)
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn test_computer_emulated_asm6times7() {
+        use super::*;
+
+        // same script as in hack_computer::computer tests
+        let code = [
+            6, -5104, 0, -7416, 7, -5104, 1, -7416, 0, -5104, 2, -7416, 0, -5104, 3, -7416, 3,
+            -1008, 1, -2864, 31, -7422, 0, -1008, 2, -3952, -7416, 3, -568, 16, -5497, 2, -1008,
+            31, -5497,
+        ];
+
+        let mut computer = ComputerEmulated::power_on(code.to_vec());
+        computer.run(150);
+
+        assert_eq!(computer.cpu_state().d, 42);
+        assert_eq!(computer.read_memory(2), 42);
+    }
+
+    #[test]
+    fn test_computer_emulated_keyboard_is_read_only() {
+        use super::*;
+
+        // @24576, M=-1, D=M
+        let code = [24576, -4472, -1008];
+
+        let mut computer = ComputerEmulated::power_on(code.to_vec());
+        computer.get_input_from_io_device(75);
+        computer.run(3);
+
+        assert_eq!(computer.cpu_state().d, 75);
+    }
+
+    #[test]
+    fn test_computer_emulated_banks() {
+        use super::*;
+        use crate::{
+            assembler::asm_to_binary_with_memory_map,
+            machine::{power_on_with_config, Backend},
+        };
+
+        // the ROM window is ROM[32..64), the RAM window RAM[8192..16384)
+        let memory_map = MemoryMap {
+            rom_size: 64,
+            rom_banks: 3,
+            ram_banks: 2,
+            ..MemoryMap::default()
+        };
+        // 5 into RAM[9000] of bank 0 and 7 of bank 1, and R0 = the sum from the code in ROM bank 1
+        let program = asm_to_binary_with_memory_map(
+            "@5\nD=A\n@9000\nM=D\n@RAMBANK\nM=1\n@7\nD=A\n@9000\nM=D\nFARJMP FAR\n\
+             .bank 1\n(FAR)\n@9000\nD=M\n@RAMBANK\nM=0\n@9000\nD=D+M\n@R0\nM=D\n\
+             (END)\n@END\n0;JMP",
+            &memory_map,
+        )
+        .unwrap();
+        assert_eq!(program.len(), 64 + 10);
+
+        let config = MachineConfig {
+            memory: memory_map,
+            ..MachineConfig::default()
+        };
+        assert!(power_on_with_config(Backend::GateLevel, config, program.clone()).is_err());
+
+        let mut computer = power_on_with_config(Backend::Emulated, config, program).unwrap();
+        computer.run(40);
+
+        assert_eq!(computer.read_memory(0), 12);
+        assert_eq!(computer.read_memory(9000), 5);
+        assert_eq!(computer.read_memory(BANK_SELECT_ADDRESS), 1);
+        assert_eq!(computer.read_rom(32), 9000);
+    }
+}
@@ -0,0 +1,24 @@
+/// Keyboard register, that stores the key code as it is.
+pub struct KeyboardEmulated {
+    values: [bool; 16],
+}
+
+impl KeyboardEmulated {
+    pub fn power_on() -> Self {
+        Self {
+            values: [false; 16],
+        }
+    }
+
+    pub fn keyboard(&mut self, input: [bool; 16], clock: bool) -> [bool; 16] {
+        if clock {
+            self.values = input;
+        }
+
+        self.values
+    }
+
+    pub fn get_debug_info(&self) -> [bool; 16] {
+        self.values
+    }
+}
@@ -1,6 +1,13 @@
 // this is a file crate, that are not constructed from pure logical gates.
-// they are meant to be helpers during the development, and later be removed.
+// computer_emulated (with alu_emulated) is the fast backend for running programs.
+// The other parts have the same interface as their gate-level counterparts,
+// so that `MachineConfig` can swap them into the gate-level computer one by one.
+// register_16bit_emulated is also the word-level memory cell of the RAM chips:
+// 16K words of gate-level registers would be too slow to clock on every instruction.
+pub mod alu_emulated;
+pub mod computer_emulated;
+pub mod keyboard_emulated;
 pub mod program_counter_emulated;
 pub mod ram16k_emulated;
 pub mod register_16bit_emulated;
-pub mod rom_emulated;
+pub mod screen_emulated;
@@ -1,41 +1,13 @@
-use crate::{
-    emulated_parts::register_16bit_emulated::Register16BitEmulated,
-    hack_computer::{
-        chips::adder::inc16,
-        gates::{gates_b1::or, gates_b16::mux16, gates_mw::mux4way16},
-    },
-};
+use crate::utils::bit_manipulation::{bits_from_i16, i16_from_bits};
 
+/// Program counter that counts with an integer.
 pub struct ProgramCounterEmulated {
-    base_circuit: Register16BitEmulated,
-    feedback_out: [bool; 16],
+    value: i16,
 }
 
 impl ProgramCounterEmulated {
     pub fn power_on() -> Self {
-        Self {
-            base_circuit: Register16BitEmulated::power_on(),
-            feedback_out: [false; 16],
-        }
-    }
-
-    pub fn get_next_val(
-        &mut self,
-        input: [bool; 16],
-        load: bool,
-        inc: bool,
-        reset: bool,
-    ) -> [bool; 16] {
-        // TODO: replace with muxes
-        if reset {
-            [false; 16]
-        } else if load {
-            input
-        } else if inc {
-            inc16(self.feedback_out)
-        } else {
-            self.feedback_out
-        }
+        Self { value: 0 }
     }
 
     pub fn program_counter_clocked(
@@ -47,25 +19,31 @@ impl ProgramCounterEmulated {
         clock: bool,
     ) -> [bool; 16] {
         // NOTE: EMULATED
+        if clock {
+            self.value = if reset {
+                0
+            } else if load {
+                i16_from_bits(input)
+            } else if inc {
+                self.value.wrapping_add(1)
+            } else {
+                self.value
+            };
+        }
 
-        let reg_in = self.get_next_val(input, load, inc, reset);
-
-        self.feedback_out = self
-            .base_circuit
-            .register_16bit_clocked(reg_in, true, clock);
-
-        self.feedback_out
+        self.get_debug_info()
     }
 
-    pub fn get_debug_info(&mut self) -> [bool; 16] {
-        self.base_circuit.get_debug_info()
+    pub fn get_debug_info(&self) -> [bool; 16] {
+        bits_from_i16(self.value)
     }
 }
 
 // TODO: Write either better tests or panel for this.
 // in order to test this meaningfully, you might need to consider mocking some of the child circuits
 // or just write e2e tests
-pub mod test {
+#[cfg(test)]
+mod test {
     use super::*;
 
     #[test]
@@ -92,4 +70,34 @@ pub mod test {
 
         assert_eq!(output, [false; 16], "tick tock 2");
     }
+
+    #[test]
+    fn test_program_counter_counts() {
+        let mut pc = ProgramCounterEmulated::power_on();
+        let mut tick = |input: i16, load, inc, reset| {
+            pc.program_counter_clocked(bits_from_i16(input), load, inc, reset, false);
+            i16_from_bits(pc.program_counter_clocked(bits_from_i16(input), load, inc, reset, true))
+        };
+
+        assert_eq!(tick(32766, true, false, false), 32766, "load");
+        assert_eq!(tick(0, false, true, false), 32767, "inc");
+        assert_eq!(tick(0, false, true, false), -32768, "inc wraps");
+        assert_eq!(tick(5, true, true, true), 0, "reset before load and inc");
+        assert_eq!(tick(5, true, true, false), 5, "load before inc");
+        assert_eq!(tick(9, false, false, false), 5, "keeps the value");
+    }
+
+    #[test]
+    fn test_program_counter_waits_for_the_clock() {
+        let mut pc = ProgramCounterEmulated::power_on();
+
+        let output = pc.program_counter_clocked(bits_from_i16(7), true, false, false, false);
+        assert_eq!(i16_from_bits(output), 0);
+        let output = pc.program_counter_clocked(bits_from_i16(7), false, true, false, false);
+        assert_eq!(i16_from_bits(output), 0);
+        assert_eq!(i16_from_bits(pc.get_debug_info()), 0);
+
+        let output = pc.program_counter_clocked(bits_from_i16(7), true, false, false, true);
+        assert_eq!(i16_from_bits(output), 7);
+    }
 }
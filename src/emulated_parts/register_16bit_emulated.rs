@@ -1,6 +1,4 @@
-use crate::utils::{
-    bit_manipulation::get_bit_from_i16, convert_16b::from_i16, convert_bn::from_bool_array,
-};
+use crate::utils::bit_manipulation::{bits_from_i16, get_bit_from_i16, i16_from_bits};
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub struct Register16BitEmulated {
@@ -34,14 +32,19 @@ impl Register16BitEmulated {
         load: bool,
         clock: bool,
     ) -> [bool; 16] {
-        if clock {
-            self.value = from_bool_array(self.mux(input, load)).unwrap().as_integer;
+        // the RAM clocks every register on every cycle, so the conversions are done with bits
+        if clock && load {
+            self.value = i16_from_bits(input);
         }
 
-        from_i16(self.value).unwrap().as_array_b16
+        self.get_debug_info()
     }
 
-    pub fn get_debug_info(&mut self) -> [bool; 16] {
-        from_i16(self.value).unwrap().as_array_b16
+    pub fn get_debug_info(&self) -> [bool; 16] {
+        bits_from_i16(self.value)
+    }
+
+    pub fn set_debug_info(&mut self, value: [bool; 16]) {
+        self.value = i16_from_bits(value);
     }
 }
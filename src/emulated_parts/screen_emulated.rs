@@ -0,0 +1,37 @@
+use crate::utils::bit_manipulation::{bits_from_i16, i16_from_bits};
+
+/// Screen memory map, stored as integers.
+pub struct ScreenEmulated {
+    values: Vec<i16>,
+}
+
+impl ScreenEmulated {
+    pub fn power_on() -> Self {
+        Self {
+            values: vec![0; 8192],
+        }
+    }
+
+    pub fn screen(
+        &mut self,
+        input: [bool; 16],
+        load: bool,
+        address: [bool; 13],
+        clock: bool,
+    ) -> [bool; 16] {
+        let address = i16_from_bits(address) as usize;
+        if clock && load {
+            self.values[address] = i16_from_bits(input);
+        }
+
+        bits_from_i16(self.values[address])
+    }
+
+    pub fn get_debug_info(&self, address: usize) -> [bool; 16] {
+        bits_from_i16(self.values[address & 0x1FFF])
+    }
+
+    pub fn set_debug_info(&mut self, address: usize, value: [bool; 16]) {
+        self.values[address & 0x1FFF] = i16_from_bits(value);
+    }
+}
@@ -1,5 +1,6 @@
 use super::panels::{
     alu::{panel_alu, AluData},
+    computer::{panel_computer, ComputerData},
     // adder::{panel_adder, AdderData},
     memory_wdr::{panel_memory, MemoryData},
 };
@@ -20,6 +21,9 @@ pub struct GuiApp {
 
     #[serde(skip)]
     alu_data: AluData,
+
+    #[serde(skip)]
+    computer_data: ComputerData,
 }
 
 impl Default for GuiApp {
@@ -29,6 +33,7 @@ impl Default for GuiApp {
             value: 2.7,
             memory_data: MemoryData::default(),
             alu_data: AluData::default(),
+            computer_data: ComputerData::default(),
             // adder_data: AdderData::default(),
         }
     }
@@ -64,6 +69,7 @@ impl eframe::App for GuiApp {
             value,
             memory_data,
             alu_data,
+            computer_data,
             // adder_data,
         } = self;
 
@@ -109,6 +115,9 @@ impl eframe::App for GuiApp {
 
         egui::CentralPanel::default().show(ctx, |ui| {
             // The central panel the region left after adding TopPanel's and SidePanel's
+            panel_computer(ui, computer_data, _frame);
+            ui.separator();
+
             panel_memory(ui, memory_data, _frame);
             egui::warn_if_debug_build(ui);
         });
@@ -1,3 +1,4 @@
 mod panels;
+mod speaker;
 
 pub mod app;
@@ -5,6 +5,7 @@ pub struct AdderData {
     input_a: String,
     input_b: String,
     output: String,
+    carry: bool,
     error: String,
 }
 
@@ -14,6 +15,7 @@ impl Default for AdderData {
             input_a: "0".to_owned(),
             input_b: "0".to_owned(),
             output: "0".to_owned(),
+            carry: false,
             error: "".to_owned(),
         }
     }
@@ -44,7 +46,9 @@ pub fn panel_adder(
 
         match result {
             Ok((a, b)) => {
-                let output_b16 = crate::hack_computer::chips::adder::adder_b16(a, b);
+                let (output_b16, carry) =
+                    crate::hack_computer::chips::adder::adder_b16_carry(a, b);
+                data.carry = carry;
 
                 let output_i32 = utils::convert_16b::from_b16(output_b16);
                 data.output = output_i32.unwrap().to_string(); // TODO: Do we need to check the error?
@@ -59,6 +63,8 @@ pub fn panel_adder(
     ui.horizontal(|ui| {
         ui.label("Result:");
         ui.add(egui::widgets::Label::new(format!("{}", data.output)));
+        ui.label("carry:");
+        ui.label(data.carry.to_string());
     });
 
     ui.horizontal(|ui| {
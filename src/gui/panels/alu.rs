@@ -14,6 +14,8 @@ pub struct AluData {
     output_out: String,
     output_zr: bool,
     output_ng: bool,
+    output_carry: bool,
+    output_overflow: bool,
 
     error: String,
 }
@@ -33,6 +35,8 @@ impl Default for AluData {
             output_out: "0".to_owned(),
             output_zr: false,
             output_ng: false,
+            output_carry: false,
+            output_overflow: false,
 
             error: "".to_owned(),
         }
@@ -78,21 +82,25 @@ pub fn panel_alu(
 
         match result {
             Ok((a, b)) => {
-                let alu_res = crate::hack_computer::chips::alu::alu(
+                let (out, flags) = crate::hack_computer::chips::alu::alu_with_flags(
                     a,
                     b,
-                    data.input_zx,
-                    data.input_nx,
-                    data.input_zy,
-                    data.input_ny,
-                    data.input_f,
-                    data.input_no,
+                    [
+                        data.input_zx,
+                        data.input_nx,
+                        data.input_zy,
+                        data.input_ny,
+                        data.input_f,
+                        data.input_no,
+                    ],
                 );
 
-                let output_i32 = utils::convert_16b::from_b16(alu_res.0);
+                let output_i32 = utils::convert_16b::from_b16(out);
                 data.output_out = output_i32.unwrap().to_string(); // TODO: Do we need to check the error?
-                data.output_zr = alu_res.1;
-                data.output_ng = alu_res.2;
+                data.output_zr = flags.zr;
+                data.output_ng = flags.ng;
+                data.output_carry = flags.carry;
+                data.output_overflow = flags.overflow;
             }
             Err(e) => {
                 data.error = e;
@@ -114,6 +122,14 @@ pub fn panel_alu(
                 // output_ng: bool,
                 ui.label("ng:");
                 ui.label(data.output_ng.to_string());
+
+                // unsigned carry out, for a subtraction it means no borrow
+                ui.label("carry:");
+                ui.label(data.output_carry.to_string());
+
+                // signed overflow
+                ui.label("overflow:");
+                ui.label(data.output_overflow.to_string());
             });
 
             ui.horizontal(|ui| {
@@ -0,0 +1,599 @@
+use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+use crate::gui::speaker::Speaker;
+use crate::machine::{
+    self,
+    config::{Fidelity, MachineConfig, BANK_SELECT_ADDRESS},
+    devices::{
+        multiplier::{Multiplier, MULTIPLIER_ADDRESS},
+        sound::{self, Sound, DEFAULT_SAMPLE_RATE, SOUND_ADDRESS},
+        timer::{Timer, TIMER_ADDRESS},
+        tty::{Tty, TTY_ADDRESS},
+    },
+    key::Key,
+    protection::{Access, Protection},
+    runner::{IllegalPolicy, Runner},
+    Backend, Machine,
+};
+
+// assembly script that calculates 6 * 7 into D register
+const DEFAULT_PROGRAM: &str = "6 -5104 0 -7416 7 -5104 1 -7416 0 -5104 2 -7416 \
+0 -5104 3 -7416 3 -1008 1 -2864 31 -7422 0 -1008 2 -3952 -7416 3 -568 16 -5497 2 -1008 31 -5497";
+
+/// Samples, that the panel keeps: 10 seconds.
+const MAX_SAMPLES: usize = 10 * DEFAULT_SAMPLE_RATE as usize;
+
+pub struct ComputerData {
+    backend: Backend,
+    config: MachineConfig,
+    program: String,
+    rom_disk: Vec<i16>,
+    runner: Runner,
+
+    /// what to do with illegal instructions
+    policy: IllegalPolicy,
+
+    /// what to do with reads of registers and memory, that nothing has written
+    uninitialised: IllegalPolicy,
+
+    /// regions of the data memory, whose forbidden accesses stop the program
+    protection: Vec<Protection>,
+
+    running: bool,
+    steps_per_frame: usize,
+
+    /// sends the key presses to the keyboard register
+    capture_keys: bool,
+
+    /// peripherals, that the panel shows
+    devices: Devices,
+    console: String,
+    console_input: String,
+
+    /// the latest `MAX_SAMPLES`, that the tone generator has played
+    samples: VecDeque<i16>,
+
+    /// plays the samples, while it is on
+    speaker: Option<Speaker>,
+
+    error: String,
+
+    screen_texture: Option<egui::TextureHandle>,
+}
+
+impl Default for ComputerData {
+    fn default() -> Self {
+        let backend = Backend::Emulated;
+        let program = DEFAULT_PROGRAM.to_owned();
+        let rom_disk = parse_program(&program).unwrap_or_default();
+        let policy = IllegalPolicy::default();
+        let mut machine = machine::power_on(backend, rom_disk.clone());
+        let (devices, errors) = attach_devices(machine.as_mut());
+        let runner = Runner::new(machine, rom_disk.len(), policy);
+
+        Self {
+            backend,
+            config: MachineConfig::default(),
+            program,
+            rom_disk,
+            runner,
+            policy,
+            uninitialised: IllegalPolicy::Ignore,
+            protection: Vec::new(),
+            running: false,
+            steps_per_frame: 100,
+            capture_keys: false,
+            devices,
+            console: String::new(),
+            console_input: String::new(),
+            samples: VecDeque::new(),
+            speaker: None,
+            error: errors.join("\n"),
+            screen_texture: None,
+        }
+    }
+}
+
+/// Peripherals for the programs. A custom memory map may use their addresses,
+/// and then the program runs without them. Returns the errors of those, that were not attached.
+fn attach_devices(machine: &mut dyn Machine) -> (Devices, Vec<String>) {
+    let devices = Devices {
+        tty: Rc::new(RefCell::new(Tty::default())),
+        sound: Rc::new(RefCell::new(Sound::default())),
+    };
+    let errors = [
+        machine.attach_device(TIMER_ADDRESS, Box::<Timer>::default()),
+        machine.attach_device(TTY_ADDRESS, Box::new(devices.tty.clone())),
+        machine.attach_device(SOUND_ADDRESS, Box::new(devices.sound.clone())),
+        machine.attach_device(MULTIPLIER_ADDRESS, Box::<Multiplier>::default()),
+    ]
+    .into_iter()
+    .filter_map(Result::err)
+    .collect();
+
+    (devices, errors)
+}
+
+struct Devices {
+    tty: Rc<RefCell<Tty>>,
+    sound: Rc<RefCell<Sound>>,
+}
+
+/// Parses the machine code: 16-bit integers separated by whitespace or commas.
+fn parse_program(program: &str) -> Result<Vec<i16>, String> {
+    program
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            word.parse::<i16>()
+                .map_err(|_| format!("Cannot convert '{}' into i16 value", word))
+        })
+        .collect()
+}
+
+pub fn panel_computer(ui: &mut egui::Ui, data: &mut ComputerData, _frame: &mut eframe::Frame) {
+    ui.label("Computer");
+
+    ui.horizontal(|ui| {
+        ui.label("Backend:");
+        ui.radio_value(
+            &mut data.backend,
+            Backend::GateLevel,
+            Backend::GateLevel.name(),
+        );
+        ui.radio_value(
+            &mut data.backend,
+            Backend::Emulated,
+            Backend::Emulated.name(),
+        );
+    });
+
+    if data.backend == Backend::GateLevel {
+        ui.collapsing("Parts", |ui| {
+            egui::Grid::new("computer_parts").show(ui, |ui| {
+                for (name, fidelity) in data.config.components_mut() {
+                    ui.label(name);
+                    for option in [Fidelity::Gate, Fidelity::Emulated] {
+                        ui.radio_value(fidelity, option, option.name());
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+    } else {
+        ui.collapsing("Memory map", |ui| {
+            let memory = &mut data.config.memory;
+            egui::Grid::new("computer_memory_map").show(ui, |ui| {
+                for (name, value) in [
+                    ("RAM size", &mut memory.ram_size),
+                    ("Screen address", &mut memory.screen_address),
+                    ("Screen width", &mut memory.screen_width),
+                    ("Screen height", &mut memory.screen_height),
+                    ("Keyboard address", &mut memory.keyboard_address),
+                    ("ROM size", &mut memory.rom_size),
+                ] {
+                    ui.label(name);
+                    ui.add(egui::DragValue::new(value).clamp_range(0..=32768));
+                    ui.end_row();
+                }
+                for (name, value) in [
+                    ("ROM banks", &mut memory.rom_banks),
+                    ("RAM banks", &mut memory.ram_banks),
+                ] {
+                    ui.label(name);
+                    ui.add(egui::DragValue::new(value).clamp_range(1..=256));
+                    ui.end_row();
+                }
+            });
+            ui.checkbox(
+                &mut memory.execute_ram,
+                "Von Neumann: execute the RAM after the ROM",
+            );
+            if let Err(e) = memory.validate() {
+                ui.label(e);
+            }
+        });
+    }
+
+    ui.collapsing("Extensions", |ui| {
+        let extensions = &mut data.config.extensions;
+        ui.horizontal(|ui| {
+            let mut interrupts = extensions.interrupts();
+            let mut vector = extensions.interrupt_vector.unwrap_or(0);
+            ui.checkbox(&mut interrupts, "Interrupts, handler at ROM");
+            ui.add_enabled(
+                interrupts,
+                egui::DragValue::new(&mut vector).clamp_range(0..=32767),
+            );
+            extensions.interrupt_vector = interrupts.then_some(vector);
+        });
+        ui.checkbox(&mut extensions.shifts, "Shift instructions: D<<, M>> ...");
+        ui.checkbox(&mut extensions.stack, "Stack: PUSH, POP, CALL, RET ...");
+    });
+
+    ui.horizontal(|ui| {
+        let mut random = data.config.power_on_seed.is_some();
+        let mut seed = data.config.power_on_seed.unwrap_or(0);
+        ui.checkbox(&mut random, "Garbage in RAM at power on, seed");
+        ui.add_enabled(random, egui::DragValue::new(&mut seed));
+        data.config.power_on_seed = random.then_some(seed);
+    });
+
+    ui.horizontal(|ui| {
+        ui.label("Illegal instructions:");
+        for option in [
+            IllegalPolicy::Ignore,
+            IllegalPolicy::Warn,
+            IllegalPolicy::Trap,
+        ] {
+            ui.radio_value(&mut data.policy, option, option.name());
+        }
+    });
+    data.runner.policy = data.policy;
+
+    ui.horizontal(|ui| {
+        ui.label("Uninitialised reads:");
+        for option in [
+            IllegalPolicy::Ignore,
+            IllegalPolicy::Warn,
+            IllegalPolicy::Trap,
+        ] {
+            ui.radio_value(&mut data.uninitialised, option, option.name());
+        }
+    });
+    data.runner.uninitialised = data.uninitialised;
+
+    ui.collapsing("Memory protection", |ui| {
+        egui::Grid::new("computer_protection").show(ui, |ui| {
+            let mut removed = None;
+            for (i, protection) in data.protection.iter_mut().enumerate() {
+                ui.add(egui::DragValue::new(&mut protection.start).clamp_range(0..=32767));
+                ui.add(egui::DragValue::new(&mut protection.end).clamp_range(0..=32767));
+                for access in Access::ALL {
+                    ui.radio_value(&mut protection.access, access, access.name());
+                }
+                if ui.button("Remove").clicked() {
+                    removed = Some(i);
+                }
+                ui.end_row();
+            }
+            if let Some(i) = removed {
+                data.protection.remove(i);
+            }
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Guard the stack").clicked() {
+                data.protection.push(Protection {
+                    start: 256,
+                    end: 2047,
+                    access: Access::Guarded,
+                });
+            }
+            if ui.button("Protect the keyboard and after").clicked() {
+                data.protection.push(Protection {
+                    start: 24576,
+                    end: 32767,
+                    access: Access::ReadOnly,
+                });
+            }
+        });
+    });
+    data.runner.protection.clone_from(&data.protection);
+    data.runner.keyboard_interrupts = data.capture_keys;
+
+    ui.label("Program (machine code):");
+    ui.add(egui::widgets::TextEdit::multiline(&mut data.program).desired_rows(3));
+
+    ui.horizontal(|ui| {
+        if ui.button("Power on").clicked() {
+            let machine = parse_program(&data.program).and_then(|rom_disk| {
+                machine::power_on_with_config(data.backend, data.config, rom_disk.clone())
+                    .map(|machine| (machine, rom_disk))
+            });
+            match machine {
+                Ok((mut machine, rom_disk)) => {
+                    let (devices, errors) = attach_devices(machine.as_mut());
+                    data.devices = devices;
+                    data.console.clear();
+                    data.samples.clear();
+                    data.runner = Runner::new(machine, rom_disk.len(), data.policy);
+                    data.rom_disk = rom_disk;
+                    data.running = false;
+                    data.error = errors.join("\n");
+                }
+                Err(e) => data.error = e,
+            }
+        }
+
+        if ui.button("Reset").clicked() {
+            data.runner.reset();
+            data.error = "".to_owned();
+        }
+
+        if ui.button("Power cycle").clicked() {
+            data.runner.power_cycle();
+            data.error = "".to_owned();
+        }
+
+        if ui.button("Step").clicked() {
+            if let Err(trap) = data.runner.step() {
+                data.error = trap.to_string();
+            }
+        }
+
+        let run_label = if data.running { "Stop" } else { "Run" };
+        if ui.button(run_label).clicked() {
+            data.running = !data.running;
+        }
+
+        ui.add(egui::Slider::new(&mut data.steps_per_frame, 1..=10000).text("steps / frame"));
+    });
+
+    if data.running {
+        if let Err(trap) = data.runner.run(data.steps_per_frame) {
+            data.error = trap.to_string();
+            data.running = false;
+        }
+        if data.runner.is_halted() {
+            data.running = false;
+        }
+        ui.ctx().request_repaint();
+    }
+
+    let state = data.runner.machine.cpu_state();
+    ui.horizontal(|ui| {
+        ui.label(format!("A: {}", state.a));
+        ui.label(format!("D: {}", state.d));
+        ui.label(format!("PC: {}", state.pc));
+        if let Some(sp) = data.runner.machine.stack_pointer() {
+            ui.label(format!("SP: {}", sp));
+        }
+        if data.runner.machine.memory_map().is_banked() {
+            ui.label(format!(
+                "ROM bank: {}, RAM bank: {}",
+                data.runner.machine.read_memory(BANK_SELECT_ADDRESS),
+                data.runner.machine.read_memory(BANK_SELECT_ADDRESS + 1)
+            ));
+        }
+        if data.runner.machine.extensions().interrupts() {
+            let interrupt = data.runner.machine.interrupt_state();
+            if interrupt.in_handler {
+                ui.label(format!(
+                    "In interrupt, returns to {}",
+                    interrupt.return_address
+                ));
+            }
+            if interrupt.pending {
+                ui.label("Interrupt pending");
+            }
+        }
+        if data.runner.is_halted() {
+            ui.label("Program finished");
+        }
+    });
+
+    egui::Grid::new("computer_ram").show(ui, |ui| {
+        for (i, (address, value)) in data.runner.machine.get_ram(0, 16).iter().enumerate() {
+            ui.label(format!("RAM[{:02}]: {}", address, value));
+            if i % 4 == 3 {
+                ui.end_row();
+            }
+        }
+    });
+
+    ui.collapsing("Screen", |ui| {
+        ui.checkbox(&mut data.capture_keys, "Send key presses to the keyboard");
+        show_screen(ui, data);
+    });
+
+    if data.capture_keys {
+        send_keys(ui, data);
+    }
+
+    data.console
+        .push_str(&data.devices.tty.borrow_mut().take_output());
+    ui.collapsing("Console", |ui| {
+        egui::ScrollArea::vertical()
+            .max_height(200.0)
+            .stick_to_bottom(true)
+            .show(ui, |ui| ui.monospace(&data.console));
+
+        ui.horizontal(|ui| {
+            let input = ui.text_edit_singleline(&mut data.console_input);
+            let entered = input.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            if ui.button("Send").clicked() || entered {
+                data.devices
+                    .tty
+                    .borrow_mut()
+                    .push_input(&format!("{}\n", data.console_input));
+                data.console_input.clear();
+            }
+            if ui.button("Clear").clicked() {
+                data.console.clear();
+            }
+        });
+    });
+
+    let samples = data.devices.sound.borrow_mut().take_samples();
+    if let Some(speaker) = &mut data.speaker {
+        let sample_rate = data.devices.sound.borrow().sample_rate();
+        if let Err(e) = speaker.play(&samples, sample_rate) {
+            data.error = e;
+            data.speaker = None;
+        }
+    }
+    data.samples.extend(samples);
+    let excess = data.samples.len().saturating_sub(MAX_SAMPLES);
+    data.samples.drain(..excess);
+    ui.collapsing("Sound", |ui| show_sound(ui, data));
+
+    ui.collapsing("Profile", |ui| {
+        ui.monospace(data.runner.profiler.report(&data.rom_disk, 5));
+    });
+
+    if !data.runner.warnings().is_empty() {
+        ui.collapsing(
+            format!("Warnings ({})", data.runner.warnings().len()),
+            |ui| {
+                for warning in data.runner.warnings().iter().rev().take(20) {
+                    ui.label(warning.to_string());
+                }
+            },
+        );
+    }
+
+    ui.horizontal(|ui| {
+        if !data.error.is_empty() {
+            ui.label("Error!:");
+            ui.add(egui::widgets::Label::new(data.error.clone()));
+        }
+    });
+}
+
+fn show_screen(ui: &mut egui::Ui, data: &mut ComputerData) {
+    let framebuffer = data.runner.machine.framebuffer();
+    let pixels = framebuffer
+        .pixels()
+        .iter()
+        .map(|black| {
+            if *black {
+                egui::Color32::BLACK
+            } else {
+                egui::Color32::WHITE
+            }
+        })
+        .collect();
+    let size = [framebuffer.width(), framebuffer.height()];
+    let image = egui::ColorImage { size, pixels };
+
+    let texture = match &mut data.screen_texture {
+        Some(texture) => {
+            texture.set(image, egui::TextureOptions::NEAREST);
+            texture
+        }
+        None => data.screen_texture.insert(ui.ctx().load_texture(
+            "computer_screen",
+            image,
+            egui::TextureOptions::NEAREST,
+        )),
+    };
+
+    ui.image(texture.id(), egui::vec2(size[0] as f32, size[1] as f32));
+}
+
+/// Waveform of the latest samples, and the speaker. The latest samples can be saved as WAV.
+fn show_sound(ui: &mut egui::Ui, data: &mut ComputerData) {
+    let sample_rate = data.devices.sound.borrow().sample_rate();
+    ui.label(format!(
+        "latest {} samples, {:.2} s at {} Hz",
+        data.samples.len(),
+        data.samples.len() as f32 / sample_rate as f32,
+        sample_rate
+    ));
+
+    let (response, painter) = ui.allocate_painter(egui::vec2(512.0, 64.0), egui::Sense::hover());
+    let rect = response.rect;
+    painter.rect_filled(rect, 0.0, egui::Color32::BLACK);
+
+    let latest = data.samples.len().saturating_sub(512);
+    let points = data
+        .samples
+        .range(latest..)
+        .enumerate()
+        .map(|(i, sample)| {
+            let y = rect.center().y - *sample as f32 / i16::MAX as f32 * rect.height() / 2.0;
+            egui::pos2(rect.left() + i as f32, y)
+        })
+        .collect();
+    painter.add(egui::Shape::line(
+        points,
+        egui::Stroke::new(1.0, egui::Color32::GREEN),
+    ));
+
+    ui.horizontal(|ui| {
+        let mut playing = data.speaker.is_some();
+        if ui.checkbox(&mut playing, "Play").changed() {
+            data.speaker = None;
+            if playing {
+                match Speaker::new(sample_rate) {
+                    Ok(speaker) => data.speaker = Some(speaker),
+                    Err(e) => data.error = e,
+                }
+            }
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if ui.button("Save sound.wav").clicked() {
+            let samples = data.samples.make_contiguous();
+            if let Err(e) = std::fs::write("sound.wav", sound::wav(samples, sample_rate)) {
+                data.error = format!("Cannot save sound.wav: {}", e);
+            }
+        }
+        if ui.button("Clear").clicked() {
+            data.samples.clear();
+        }
+    });
+}
+
+fn send_keys(ui: &mut egui::Ui, data: &mut ComputerData) {
+    let events = ui.input(|input| input.events.clone());
+    for event in events {
+        if let egui::Event::Key {
+            key,
+            pressed,
+            repeat: false,
+            ..
+        } = event
+        {
+            if let Some(key) = to_hack_key(key) {
+                if pressed {
+                    data.runner.machine.key_down(key);
+                } else {
+                    data.runner.machine.key_up(key);
+                }
+            }
+        }
+    }
+}
+
+fn to_hack_key(key: egui::Key) -> Option<Key> {
+    let hack_key = match key {
+        egui::Key::Enter => Key::Newline,
+        egui::Key::Backspace => Key::Backspace,
+        egui::Key::ArrowLeft => Key::Left,
+        egui::Key::ArrowUp => Key::Up,
+        egui::Key::ArrowRight => Key::Right,
+        egui::Key::ArrowDown => Key::Down,
+        egui::Key::Home => Key::Home,
+        egui::Key::End => Key::End,
+        egui::Key::PageUp => Key::PageUp,
+        egui::Key::PageDown => Key::PageDown,
+        egui::Key::Insert => Key::Insert,
+        egui::Key::Delete => Key::Delete,
+        egui::Key::Escape => Key::Esc,
+        egui::Key::Space => Key::Char(' '),
+        egui::Key::F1 => Key::F(1),
+        egui::Key::F2 => Key::F(2),
+        egui::Key::F3 => Key::F(3),
+        egui::Key::F4 => Key::F(4),
+        egui::Key::F5 => Key::F(5),
+        egui::Key::F6 => Key::F(6),
+        egui::Key::F7 => Key::F(7),
+        egui::Key::F8 => Key::F(8),
+        egui::Key::F9 => Key::F(9),
+        egui::Key::F10 => Key::F(10),
+        egui::Key::F11 => Key::F(11),
+        egui::Key::F12 => Key::F(12),
+
+        // letters and digits: the key name is the character
+        _ => {
+            let mut name = key.symbol_or_name().chars();
+            match (name.next(), name.next()) {
+                (Some(c), None) => return Key::from_char(c),
+                _ => return None,
+            }
+        }
+    };
+
+    Some(hack_key)
+}
@@ -3,4 +3,5 @@
 // TODO: Add generic way to add gates in the panels.
 
 pub mod alu;
+pub mod computer;
 pub mod memory_wdr;
@@ -0,0 +1,134 @@
+// Speaker
+//
+// Plays the samples of the tone generator, while the program runs.
+// On the web, every batch of samples becomes a WebAudio buffer, scheduled right after the previous one.
+// On native, the samples are piped into `aplay` (ALSA), on a thread, so that the GUI never waits for it.
+//
+// The emulator rarely runs at the real speed of the clock, so the sound has gaps when it is slower,
+// and batches are dropped when it is faster than the speaker can play them.
+
+/// Seconds of sound, that may wait for the speaker.
+const MAX_LATENCY: f64 = 1.0;
+
+#[cfg(target_arch = "wasm32")]
+pub struct Speaker {
+    context: web_sys::AudioContext,
+
+    /// audio time, when the scheduled samples end
+    end_time: f64,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Speaker {
+    /// Must be called from a user action, e.g. a click, or the browser keeps the audio suspended.
+    pub fn new(_sample_rate: u32) -> Result<Self, String> {
+        let context = web_sys::AudioContext::new().map_err(js_error)?;
+
+        Ok(Self {
+            context,
+            end_time: 0.0,
+        })
+    }
+
+    pub fn play(&mut self, samples: &[i16], sample_rate: u32) -> Result<(), String> {
+        if samples.is_empty() {
+            return Ok(());
+        }
+        let now = self.context.current_time();
+        if self.end_time - now > MAX_LATENCY {
+            return Ok(());
+        }
+
+        let mut channel: Vec<f32> = samples
+            .iter()
+            .map(|&sample| sample as f32 / i16::MAX as f32)
+            .collect();
+        let buffer = self
+            .context
+            .create_buffer(1, channel.len() as u32, sample_rate as f32)
+            .map_err(js_error)?;
+        buffer.copy_to_channel(&mut channel, 0).map_err(js_error)?;
+
+        let source = self.context.create_buffer_source().map_err(js_error)?;
+        source.set_buffer(Some(&buffer));
+        source
+            .connect_with_audio_node(&self.context.destination())
+            .map_err(js_error)?;
+        let start = self.end_time.max(now);
+        source.start_with_when(start).map_err(js_error)?;
+        self.end_time = start + samples.len() as f64 / sample_rate as f64;
+
+        Ok(())
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Drop for Speaker {
+    fn drop(&mut self) {
+        let _ = self.context.close();
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn js_error(error: impl std::fmt::Debug) -> String {
+    format!("Cannot play the sound: {:?}", error)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub struct Speaker {
+    player: std::process::Child,
+    batches: std::sync::mpsc::SyncSender<Vec<u8>>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Speaker {
+    pub fn new(sample_rate: u32) -> Result<Self, String> {
+        use std::{
+            io::Write,
+            process::{Command, Stdio},
+            sync::mpsc,
+            thread,
+        };
+
+        let mut player = Command::new("aplay")
+            .args(["-q", "-t", "raw", "-f", "S16_LE", "-c", "1", "-r"])
+            .arg(sample_rate.to_string())
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Cannot play the sound with aplay: {}", e))?;
+        let mut stdin = player.stdin.take().expect("the speaker's stdin is piped");
+
+        // a batch is written about 60 times per second
+        let (batches, receiver) = mpsc::sync_channel::<Vec<u8>>((MAX_LATENCY * 60.0) as usize);
+        thread::spawn(move || {
+            for batch in receiver {
+                if stdin.write_all(&batch).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self { player, batches })
+    }
+
+    pub fn play(&mut self, samples: &[i16], _sample_rate: u32) -> Result<(), String> {
+        use std::sync::mpsc::TrySendError;
+
+        if samples.is_empty() {
+            return Ok(());
+        }
+        let bytes = samples.iter().flat_map(|sample| sample.to_le_bytes());
+        match self.batches.try_send(bytes.collect()) {
+            Ok(()) | Err(TrySendError::Full(_)) => Ok(()),
+            Err(TrySendError::Disconnected(_)) => Err("aplay stopped playing the sound".to_owned()),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for Speaker {
+    fn drop(&mut self) {
+        let _ = self.player.kill();
+        let _ = self.player.wait();
+    }
+}
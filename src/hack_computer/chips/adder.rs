@@ -14,6 +14,12 @@ pub fn full_adder(a: bool, b: bool, c: bool) -> (bool, bool) {
 /// Does not handle negative numbers,
 /// because the adder is using one's complement repsrentation.
 pub fn adder_b16(a: [bool; 16], b: [bool; 16]) -> [bool; 16] {
+    adder_b16_carry(a, b).0
+}
+
+/// Same as `adder_b16`, but also returns the carry out of the highest bit.
+/// With unsigned numbers, it's the 17th bit of the sum.
+pub fn adder_b16_carry(a: [bool; 16], b: [bool; 16]) -> ([bool; 16], bool) {
     let (sum00, c01) = half_adder(a[0], b[0]);
     let (sum01, c02) = full_adder(a[1], b[1], c01);
     let (sum02, c03) = full_adder(a[2], b[2], c02);
@@ -29,12 +35,15 @@ pub fn adder_b16(a: [bool; 16], b: [bool; 16]) -> [bool; 16] {
     let (sum12, c13) = full_adder(a[12], b[12], c12);
     let (sum13, c14) = full_adder(a[13], b[13], c13);
     let (sum14, c15) = full_adder(a[14], b[14], c14);
-    let (sum15, _) = full_adder(a[15], b[15], c15);
-
-    [
-        sum00, sum01, sum02, sum03, sum04, sum05, sum06, sum07, sum08, sum09, sum10, sum11, sum12,
-        sum13, sum14, sum15,
-    ]
+    let (sum15, carry) = full_adder(a[15], b[15], c15);
+
+    (
+        [
+            sum00, sum01, sum02, sum03, sum04, sum05, sum06, sum07, sum08, sum09, sum10, sum11,
+            sum12, sum13, sum14, sum15,
+        ],
+        carry,
+    )
 }
 
 pub fn inc16(input: [bool; 16]) -> [bool; 16] {
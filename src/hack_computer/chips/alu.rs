@@ -1,8 +1,8 @@
 use crate::{
     hack_computer::{
-        chips::adder::adder_b16,
+        chips::adder::adder_b16_carry,
         gates::{
-            gates_b1::{mux, not, or},
+            gates_b1::{and, mux, not, or, xor},
             gates_b16::{and16, demux16, mux16, not16},
             gates_mw::{or16way, or8way},
         },
@@ -45,10 +45,6 @@ fn zero_negator(input: [bool; 16], zero: bool, negate: bool) -> [bool; 16] {
     mux16(z_res, not16(z_res), negate)
 }
 
-fn func(input_a: [bool; 16], input_b: [bool; 16], func: bool) -> [bool; 16] {
-    mux16(and16(input_a, input_b), adder_b16(input_a, input_b), func)
-}
-
 // our ALU can't do multiplication or division
 // they will be implemented on the software level.
 // However, that will be a trade-off between speed and having more "hardware".
@@ -66,16 +62,63 @@ pub fn alu(
     bool,       // zr: zero result
     bool,       // ng: negative result
 ) {
+    let (out, flags) = alu_with_flags(x, y, [zx, nx, zy, ny, f, no]);
+
+    (out, flags.zr, flags.ng)
+}
+
+/// Flags of the ALU output.
+///
+/// `carry` and `overflow` come from the adder, and they are false for `x&y` and `x|y`.
+/// `carry` is the 17th bit of the unsigned result. For a subtraction, it's set when there
+/// was no borrow: `x-y` carries when `x >= y` unsigned.
+/// `overflow` is set, when the signed result does not fit into 16 bits.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AluFlags {
+    pub zr: bool,       // zero result
+    pub ng: bool,       // negative result
+    pub carry: bool,    // unsigned carry out
+    pub overflow: bool, // signed overflow
+}
+
+/// Same as `alu`, but also gives the carry and overflow flags.
+/// `control_bits` are zx, nx, zy, ny, f and no.
+pub fn alu_with_flags(
+    x: [bool; 16],
+    y: [bool; 16],
+    control_bits: [bool; 6],
+) -> ([bool; 16], AluFlags) {
+    let [zx, nx, zy, ny, f, no] = control_bits;
+
     // like with full adder, this chip has a drawback
     // you can't run gates in parallel, because the application has to excecute bits one by one
     // this might cause performance issues.
-    let out_func = func(zero_negator(x, zx, nx), zero_negator(y, zy, ny), f);
+    let input_x = zero_negator(x, zx, nx);
+    let input_y = zero_negator(y, zy, ny);
+    let (sum, carry) = adder_b16_carry(input_x, input_y);
+    let out_func = mux16(and16(input_x, input_y), sum, f);
     let out = mux16(out_func, not16(out_func), no);
 
+    // negating the output turns a borrow into a carry: !(!x + y) is x - y
+    let carry = and(f, xor(carry, no));
+    // the sum has the wrong sign, when both inputs have the same sign.
+    // !(a + b) is -(a + b) - 1, that fits into 16 bits exactly when a + b does.
+    let overflow = and(
+        f,
+        and(
+            not(xor(input_x[15], input_y[15])),
+            xor(sum[15], input_x[15]),
+        ),
+    );
+
     (
         out,
-        not(or16way(out)), // zr
-        out[15],           // ng
+        AluFlags {
+            zr: not(or16way(out)),
+            ng: out[15],
+            carry,
+            overflow,
+        },
     )
 }
 
@@ -286,4 +329,35 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_alu_carry_and_overflow() {
+        use crate::hack_computer::chips::alu::alu_with_flags;
+        use crate::utils::opcodes::{get_opcodes, Opcode};
+
+        let opcodes = get_opcodes();
+        // carry and overflow
+        let flags = |opcode: Opcode, x: i16, y: i16| {
+            let bits = opcodes.get(&opcode).unwrap();
+            let (_, flags) = alu_with_flags(
+                i16_to_b16(x),
+                i16_to_b16(y),
+                [bits.zx, bits.nx, bits.zy, bits.ny, bits.f, bits.no],
+            );
+            (flags.carry, flags.overflow)
+        };
+
+        assert_eq!(flags(Opcode::XPlusY, 2, 3), (false, false));
+        assert_eq!(flags(Opcode::XPlusY, -1, 1), (true, false));
+        assert_eq!(flags(Opcode::XPlusY, 32767, 1), (false, true));
+        assert_eq!(flags(Opcode::XPlusY, -32768, -1), (true, true));
+        assert_eq!(flags(Opcode::XMinusY, 5, 3), (true, false));
+        assert_eq!(flags(Opcode::XMinusY, 3, 5), (false, false));
+        assert_eq!(flags(Opcode::XMinusY, -32768, 1), (true, true));
+        assert_eq!(flags(Opcode::YMinusX, 5, 3), (false, false));
+        assert_eq!(flags(Opcode::XPlusOne, -1, 0), (true, false));
+        assert_eq!(flags(Opcode::XPlusOne, 32767, 0), (false, true));
+        assert_eq!(flags(Opcode::MinusX, -32768, 0), (false, true));
+        assert_eq!(flags(Opcode::XAndY, -1, -1), (false, false));
+    }
 }
@@ -3,3 +3,5 @@ pub mod alu;
 pub mod alu_debug;
 // pub mod flipflop;
 pub mod latch;
+pub mod multiplier;
+pub mod shifter;
@@ -0,0 +1,151 @@
+use crate::hack_computer::{
+    chips::{
+        adder::{adder_b16, adder_b16_carry, inc16},
+        shifter::shift_left16,
+    },
+    gates::{
+        gates_b1::xor,
+        gates_b16::{mux16, not16},
+    },
+};
+
+// Sequential multiplier and divider
+//
+// Both take one bit per step, 16 steps in total:
+// - multiply: shift and add. For every 1 bit of y, the shifted x is added to the product.
+//   The low 16 bits of the product are the same for signed and unsigned numbers.
+// - divide: shift and subtract (restoring division) on the magnitudes.
+//   The remainder takes the next bit of |x|, and |y| is subtracted, when it fits.
+//   The signs are fixed at the end: the quotient is truncated towards zero,
+//   and the remainder has the sign of x.
+//
+// Division by zero gives the quotient 0 and the remainder x.
+// The step counter is the control logic, and it's not built from gates.
+
+pub const STEPS: usize = 16;
+
+pub fn negate16(input: [bool; 16]) -> [bool; 16] {
+    inc16(not16(input))
+}
+
+fn abs16(input: [bool; 16]) -> [bool; 16] {
+    mux16(input, negate16(input), input[15])
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MultiplierDivider {
+    step: usize,
+    x: [bool; 16],
+    y: [bool; 16],
+
+    // multiply
+    multiplicand: [bool; 16],
+    product: [bool; 16],
+
+    // divide
+    dividend: [bool; 16],
+    divisor: [bool; 16],
+    remainder: [bool; 16],
+    quotient: [bool; 16],
+}
+
+impl MultiplierDivider {
+    /// Loads the operands, and starts from the first step.
+    pub fn start(&mut self, x: [bool; 16], y: [bool; 16]) {
+        *self = Self {
+            step: 0,
+            x,
+            y,
+            multiplicand: x,
+            product: [false; 16],
+            dividend: abs16(x),
+            divisor: abs16(y),
+            remainder: [false; 16],
+            quotient: [false; 16],
+        };
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.step == STEPS
+    }
+
+    /// Calculates one bit of the product and of the quotient. Does nothing, when done.
+    pub fn step(&mut self) {
+        if self.is_done() {
+            return;
+        }
+
+        // multiply: add the shifted x, when the bit of y is 1
+        let sum = adder_b16(self.product, self.multiplicand);
+        self.product = mux16(self.product, sum, self.y[self.step]);
+        self.multiplicand = shift_left16(self.multiplicand);
+
+        // divide: bring down the next bit of the dividend, and subtract, when the divisor fits
+        let mut shifted = shift_left16(self.remainder);
+        shifted[0] = self.dividend[STEPS - 1 - self.step];
+        let (difference, fits) = adder_b16_carry(shifted, negate16(self.divisor));
+        self.remainder = mux16(shifted, difference, fits);
+        self.quotient = shift_left16(self.quotient);
+        self.quotient[0] = fits;
+
+        self.step += 1;
+    }
+
+    /// Product, quotient and remainder. Valid, when done.
+    pub fn results(&self) -> ([bool; 16], [bool; 16], [bool; 16]) {
+        let quotient_negative = xor(self.x[15], self.y[15]);
+
+        (
+            self.product,
+            mux16(self.quotient, negate16(self.quotient), quotient_negative),
+            mux16(self.remainder, negate16(self.remainder), self.x[15]),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::{
+        bit_manipulation::{bits_from_i16, i16_from_bits},
+        random::Random,
+    };
+
+    fn calculate(x: i16, y: i16) -> (i16, i16, i16) {
+        let mut datapath = MultiplierDivider::default();
+        datapath.start(bits_from_i16(x), bits_from_i16(y));
+        for _ in 0..STEPS {
+            assert!(!datapath.is_done());
+            datapath.step();
+        }
+        assert!(datapath.is_done());
+
+        let (product, quotient, remainder) = datapath.results();
+        (
+            i16_from_bits(product),
+            i16_from_bits(quotient),
+            i16_from_bits(remainder),
+        )
+    }
+
+    #[test]
+    fn test_multiplier_divider() {
+        assert_eq!(calculate(6, 7), (42, 0, 6));
+        assert_eq!(calculate(100, 7), (700, 14, 2));
+        assert_eq!(calculate(-100, 7), (-700, -14, -2));
+        assert_eq!(calculate(100, -7), (-700, -14, 2));
+        assert_eq!(calculate(5, 0), (0, 0, 5));
+        assert_eq!(calculate(i16::MIN, -1), (i16::MIN, i16::MIN, 0));
+        assert_eq!(calculate(i16::MAX, i16::MIN), (i16::MIN, 0, i16::MAX));
+
+        let mut random = Random::new(43);
+        for _ in 0..200 {
+            let (x, y) = (random.next_i16(), random.next_i16());
+            let expected = match y {
+                0 => (0, 0, x),
+                _ => (x.wrapping_mul(y), x.wrapping_div(y), x.wrapping_rem(y)),
+            };
+            assert_eq!(calculate(x, y), expected, "{} and {}", x, y);
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use crate::hack_computer::gates::{gates_b1::not, gates_b16::mux16, gates_mw::or16way};
+
+// Shifter for the extended instruction set
+//
+// A shift by one bit is only wiring: every output bit is connected to its neighbour input.
+// The right shift is arithmetic, the sign bit is copied, so that `D>>` halves negative numbers too.
+
+/// Shifts one bit to the left, the lowest bit becomes 0.
+pub fn shift_left16(input: [bool; 16]) -> [bool; 16] {
+    let mut out = [false; 16];
+    out[1..].copy_from_slice(&input[..15]);
+    out
+}
+
+/// Shifts one bit to the right, and keeps the sign bit.
+pub fn shift_right16(input: [bool; 16]) -> [bool; 16] {
+    let mut out = [input[15]; 16];
+    out[..15].copy_from_slice(&input[1..]);
+    out
+}
+
+/// Shifts left or right, and gives the same flags as the ALU: the output, zr and ng.
+pub fn shifter16(input: [bool; 16], left: bool) -> ([bool; 16], bool, bool) {
+    let out = mux16(shift_right16(input), shift_left16(input), left);
+
+    (
+        out,
+        not(or16way(out)), // zr
+        out[15],           // ng
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::bit_manipulation::{bits_from_i16, i16_from_bits};
+
+    #[test]
+    fn test_shifter16() {
+        let shift = |value: i16, left: bool| {
+            let (out, zr, ng) = shifter16(bits_from_i16(value), left);
+            (i16_from_bits(out), zr, ng)
+        };
+
+        assert_eq!(shift(21, true), (42, false, false));
+        assert_eq!(shift(21, false), (10, false, false));
+        assert_eq!(shift(-6, false), (-3, false, true));
+        assert_eq!(shift(-1, false), (-1, false, true));
+        assert_eq!(shift(0x4000, true), (i16::MIN, false, true));
+        assert_eq!(shift(i16::MIN, true), (0, true, false));
+        assert_eq!(shift(1, false), (0, true, false));
+    }
+}
@@ -1,44 +1,127 @@
-use crate::{emulated_parts::rom_emulated::RomEmulated, utils::convert_16b::from_b16};
+use crate::{
+    machine::{
+        config::{Extensions, MachineConfig},
+        device::Device,
+        framebuffer::Framebuffer,
+        key::{Key, KeyQueue},
+        shadow::reads_memory,
+        CpuState, InterruptState, Machine, StepInfo, KEYBOARD_ADDRESS,
+    },
+    utils::{
+        bit_manipulation::{bits_from_i16, i16_from_bits},
+        convert_16b::from_b16,
+    },
+};
 
-use super::parts::{cpu::Cpu, memory::Memory};
+use super::parts::{cpu::Cpu, memory::Memory, rom::Rom32k};
 
 pub struct Computer {
     // parts
     cpu: Cpu,
     memory: Memory,
-    rom: RomEmulated,
+    rom: Rom32k,
 
     // buses
     cpu_data_bus: [bool; 16],
     instruction_address_bus: [bool; 16],
 
     // events
-    pub reset: bool,
     pub screen_out: [bool; 16],
     pub keyboard_in: [bool; 16],
+    keys: KeyQueue,
+
+    // interrupt controller: holds the requests of the keyboard and the devices,
+    // until the CPU takes them. Not built from gates.
+    extensions: Extensions,
+    interrupt_pending: bool,
+
+    // power on and power cycle
+    config: MachineConfig,
+
+    // debug
+    last_memory_write: Option<(usize, i16)>,
 }
 
 impl Computer {
     pub fn power_on(rom_disk: Vec<i16>) -> Self {
-        Self {
+        Self::power_on_with_config(rom_disk, MachineConfig::default())
+    }
+
+    /// Chooses for each part, whether it's built from gates or emulated.
+    pub fn power_on_with_config(rom_disk: Vec<i16>, config: MachineConfig) -> Self {
+        let mut computer = Self {
             // power on parts
-            cpu: Cpu::power_on(),
-            memory: Memory::power_on(),
-            rom: RomEmulated::power_on(rom_disk),
+            cpu: Cpu::power_on_with_config(config),
+            memory: Memory::power_on_with_config(config),
+            rom: Rom32k::power_on(rom_disk),
 
             // initialize buses
             cpu_data_bus: [false; 16],
             instruction_address_bus: [false; 16],
 
             // initialize events
-            reset: false,
             screen_out: [false; 16],
             keyboard_in: [false; 16],
+            keys: KeyQueue::default(),
+
+            extensions: config.extensions,
+            interrupt_pending: false,
+
+            config,
+
+            last_memory_write: None,
+        };
+        computer.load_power_on_values();
+
+        computer
+    }
+
+    /// Fills the registers and the memories like `MachineConfig::power_on_seed` describes.
+    fn load_power_on_values(&mut self) {
+        let mut values = self.config.power_on_values();
+        let (a, d, sp) = (values(), values(), values());
+        self.cpu
+            .load_registers(bits_from_i16(a), bits_from_i16(d), bits_from_i16(sp));
+        // RAM and screen
+        for address in 0..KEYBOARD_ADDRESS {
+            self.memory.set_word(address, values());
         }
     }
 
-    pub fn get_input_from_io_device(&mut self, input: [bool; 16], clock: bool) {
-        self.memory.write_from_io_driver(input, clock);
+    /// Warm reset. See `Machine::reset`.
+    pub fn reset(&mut self) {
+        self.cpu.reset();
+        self.instruction_address_bus = [false; 16];
+        self.interrupt_pending = false;
+    }
+
+    /// Cold reset. See `Machine::power_cycle`.
+    pub fn power_cycle(&mut self) {
+        self.cpu = Cpu::power_on_with_config(self.config);
+        self.memory.power_cycle(self.config);
+        self.cpu_data_bus = [false; 16];
+        self.instruction_address_bus = [false; 16];
+        self.screen_out = [false; 16];
+        self.keyboard_in = [false; 16];
+        self.keys = KeyQueue::default();
+        self.interrupt_pending = false;
+        self.last_memory_write = None;
+        self.load_power_on_values();
+    }
+
+    /// Puts a raw key code on the keyboard bus.
+    /// The keyboard register latches it on the next rising edge of the clock.
+    pub fn get_input_from_io_device(&mut self, input: [bool; 16]) {
+        self.memory.write_from_io_driver(input);
+    }
+
+    /// Queues a key press. See `KeyQueue`.
+    pub fn key_down(&mut self, key: Key) {
+        self.keys.key_down(key);
+    }
+
+    pub fn key_up(&mut self, key: Key) {
+        self.keys.key_up(key);
     }
 
     // separate events:
@@ -49,29 +132,70 @@ impl Computer {
 
     /// iterates one cyckle of the computer
     pub fn run_clock(&mut self, clock: bool) {
+        // Keyboard: the next key event is on the bus during the low phase,
+        // and the keyboard register latches it with everything else on the rising edge.
+        if !clock {
+            if let Some(code) = self.keys.next_code() {
+                self.interrupt_pending |= code != self.memory.get_word(KEYBOARD_ADDRESS);
+                self.get_input_from_io_device(bits_from_i16(code));
+            }
+        }
+        self.cpu.set_interrupt_request(self.interrupt_pending);
+
         // ROM
         let cpu_instr = self.rom.rom(self.instruction_address_bus);
 
-        // CPU
-        let (
-            data_out_bus,            //
-            write_enable,            //
-            data_address_bus,        //
-            instruction_address_bus, //
-        ) = self
-            .cpu
-            .cpu(cpu_instr, self.cpu_data_bus, self.reset, clock);
-
-        // Memory
-        let ram_out = self.memory.memory(
-            data_out_bus,     //
-            write_enable,     //
-            data_address_bus, //
-            clock,
-        );
+        // While the clock is low, the first latches of the registers follow their inputs,
+        // and the memory output is one of those inputs (through the ALU).
+        // The CPU and the memory are run again until the data bus is stable,
+        // so that the registers see the settled value, when the clock rises.
+        let instruction_address_bus = loop {
+            // CPU
+            let (
+                data_out_bus,            //
+                write_enable,            //
+                data_address_bus,        //
+                instruction_address_bus, //
+            ) = self.cpu.cpu(cpu_instr, self.cpu_data_bus, false, clock);
+
+            if clock && write_enable {
+                self.last_memory_write = Some((
+                    from_b16(data_address_bus_16(data_address_bus))
+                        .unwrap()
+                        .as_usize,
+                    from_b16(data_out_bus).unwrap().as_integer,
+                ));
+            }
+
+            // Memory. The read strobe for the devices is control logic, like the bus settling,
+            // and it's not built from gates.
+            if clock && reads_memory(i16_from_bits(cpu_instr), self.config.extensions) {
+                self.memory.read_strobe(data_address_bus);
+            }
+            let ram_out = self.memory.memory(
+                data_out_bus,     //
+                write_enable,     //
+                data_address_bus, //
+                clock,
+            );
+
+            let settled = clock || ram_out == self.cpu_data_bus;
+            self.cpu_data_bus = ram_out;
+
+            if settled {
+                break instruction_address_bus;
+            }
+        };
+
+        // the devices tick on the rising edge, so their requests are taken after the next instruction
+        if clock {
+            if self.cpu.interrupt_acknowledge() {
+                self.interrupt_pending = false;
+            }
+            self.interrupt_pending |= self.memory.take_interrupt_request();
+        }
 
         // update buses / events
-        self.cpu_data_bus = ram_out;
         self.instruction_address_bus = [
             instruction_address_bus[0],
             instruction_address_bus[1],
@@ -94,7 +218,7 @@ impl Computer {
 
     // DEBUG
 
-    pub fn get_cpu_debug_info(&mut self) -> (i16, i16, i16) {
+    pub fn get_cpu_debug_info(&self) -> (i16, i16, i16) {
         fn to_i16(debug: &str, val: [bool; 16]) -> i16 {
             let cr = from_b16(val);
             match cr {
@@ -119,6 +243,11 @@ impl Computer {
         self.memory.get_ram(start, end)
     }
 
+    /// The screen as 512 x 256 pixels, read without ticking the clock.
+    pub fn framebuffer(&self) -> Framebuffer {
+        Framebuffer::from_memory(|address| self.memory.get_word(address))
+    }
+
     pub fn print_cpu_debug_info(&mut self) {
         let cpu_info = self.get_cpu_debug_info();
         println!("A: {}, D: {}, PC: {}", cpu_info.0, cpu_info.1, cpu_info.2);
@@ -136,6 +265,94 @@ impl Computer {
     }
 }
 
+fn data_address_bus_16(bus: [bool; 15]) -> [bool; 16] {
+    let mut out = [false; 16];
+    out[..15].copy_from_slice(&bus);
+    out
+}
+
+impl Machine for Computer {
+    /// One instruction takes one full clock cycle: tick (low) and tock (high).
+    fn step(&mut self) -> StepInfo {
+        let pc = from_b16(self.instruction_address_bus).unwrap().as_integer;
+        let instruction = from_b16(self.rom.rom(self.instruction_address_bus))
+            .unwrap()
+            .as_integer;
+
+        self.last_memory_write = None;
+        self.run_clock(false);
+        self.run_clock(true);
+
+        StepInfo {
+            pc,
+            instruction,
+            memory_write: self.last_memory_write,
+        }
+    }
+
+    fn cpu_state(&self) -> CpuState {
+        let (a, d, pc) = self.get_cpu_debug_info();
+
+        // the program counter register has 16 bits, but only 15 of them reach the ROM
+        CpuState {
+            a,
+            d,
+            pc: pc & 0x7FFF,
+        }
+    }
+
+    fn read_memory(&self, address: usize) -> i16 {
+        self.memory.get_word(address)
+    }
+
+    fn read_rom(&self, address: usize) -> i16 {
+        i16_from_bits(self.rom.rom(bits_from_i16(address as i16 & 0x7FFF)))
+    }
+
+    fn key_down(&mut self, key: Key) {
+        Computer::key_down(self, key);
+    }
+
+    fn key_up(&mut self, key: Key) {
+        Computer::key_up(self, key);
+    }
+
+    fn attach_device(&mut self, base: usize, device: Box<dyn Device>) -> Result<(), String> {
+        self.memory.attach_device(base, device)
+    }
+
+    fn extensions(&self) -> Extensions {
+        self.extensions
+    }
+
+    fn interrupt_state(&self) -> InterruptState {
+        match self.cpu.get_interrupt_debug_info() {
+            Some((return_address, in_handler)) => InterruptState {
+                pending: self.interrupt_pending,
+                in_handler,
+                return_address: i16_from_bits(return_address) & 0x7FFF,
+            },
+            None => InterruptState::default(),
+        }
+    }
+
+    fn may_interrupt(&self) -> bool {
+        self.extensions.interrupts() && (self.interrupt_pending || self.memory.may_interrupt())
+    }
+
+    fn stack_pointer(&self) -> Option<i16> {
+        self.cpu.get_stack_debug_info().map(i16_from_bits)
+    }
+
+    fn reset(&mut self) {
+        Computer::reset(self);
+    }
+
+    fn power_cycle(&mut self) {
+        Computer::power_cycle(self);
+    }
+}
+
 mod test {
     fn test_script() -> Vec<i16> {
         // assembly script that calculates 6 * 7 into D register
@@ -179,4 +396,37 @@ mod test {
         let reg_d = computer.get_cpu_debug_info().1;
         assert_eq!(reg_d, 42);
     }
+
+    #[test]
+    fn test_computer_framebuffer() {
+        use super::*;
+        use crate::{
+            assembler::asm_to_binary,
+            machine::config::{Fidelity, MachineConfig},
+        };
+
+        // a word in the upper and in the lower half of the screen
+        let program = asm_to_binary("@20481\nM=-1\n@16385\nM=1").unwrap();
+        let config = MachineConfig {
+            ram: Fidelity::Emulated,
+            ..MachineConfig::default()
+        };
+        let mut computer = Computer::power_on_with_config(program, config);
+        computer.run(4);
+
+        let framebuffer = computer.framebuffer();
+
+        // SCREEN + 4097 is row 128, columns 16..32
+        assert!((16..32).all(|col| framebuffer.pixel(128, col)));
+        assert!(!framebuffer.pixel(128, 15) && !framebuffer.pixel(128, 32));
+
+        // SCREEN + 1 is row 0, and only the lowest bit is on
+        assert!(framebuffer.pixel(0, 16));
+        assert!(!framebuffer.pixel(0, 17));
+
+        assert_eq!(
+            framebuffer.pixels().iter().filter(|pixel| **pixel).count(),
+            17
+        );
+    }
 }
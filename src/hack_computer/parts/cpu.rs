@@ -1,37 +1,147 @@
 use crate::{
-    emulated_parts::{
-        program_counter_emulated::ProgramCounterEmulated,
-        register_16bit_emulated::Register16BitEmulated,
-    },
     hack_computer::{
-        chips::alu::alu,
+        chips::{
+            adder::{adder_b16, inc16},
+            shifter::shifter16,
+        },
         gates::{
-            gates_b1::{and, nor, not, or},
-            gates_b16::{mux16, or16},
+            gates_b1::{and, mux, not, or},
+            gates_b16::mux16,
+            gates_mw::dmux8way_array,
         },
-        registers::{program_counter::ProgramCounter, register_16bit::Register16Bit},
+        registers::register_1bit::Register1Bit,
     },
+    machine::config::{Fidelity, MachineConfig},
+    utils::bit_manipulation::bits_from_i16,
 };
 
-// TODO: Replace emulated parts with parts made from gates.
+use super::mixed::{alu_part, Counter, Register};
+
+/// Interrupt extension: the return address in a shadow register,
+/// and a flag that keeps the handler from being interrupted.
+struct Interrupts {
+    vector: [bool; 16],
+    return_address: Register,
+    in_handler: Register1Bit,
+
+    // outputs of the registers, from the previous clock cycle
+    return_address_out: [bool; 16],
+    in_handler_out: bool,
+}
+
+/// Stack extension: the stack pointer register.
+struct Stack {
+    stack_pointer: Register,
+
+    // output of the register, from the previous clock cycle
+    stack_pointer_out: [bool; 16],
+}
+
 pub struct Cpu {
     data_out_bus: [bool; 16],
 
     /// address register
-    a_register: Register16BitEmulated,
+    a_register: Register,
 
     /// data register
-    d_register: Register16BitEmulated,
-    program_counter: ProgramCounterEmulated,
+    d_register: Register,
+    program_counter: Counter,
+    alu: Fidelity,
+
+    /// extended instruction set with shifts
+    shifts: bool,
+
+    // outputs of the registers, from the previous clock cycle
+    a_register_out: [bool; 16],
+    d_register_out: [bool; 16],
+    pc_out: [bool; 16],
+
+    /// `None` without the interrupt extension
+    interrupts: Option<Interrupts>,
+
+    /// `None` without the stack extension
+    stack: Option<Stack>,
+
+    // interrupt request line from the computer, and whether the CPU took the request
+    interrupt_request: bool,
+    interrupt_acknowledge: bool,
 }
 
 impl Cpu {
     pub fn power_on() -> Self {
+        Self::power_on_with_config(MachineConfig::default())
+    }
+
+    pub fn power_on_with_config(config: MachineConfig) -> Self {
         Self {
             data_out_bus: [false; 16],
-            a_register: Register16BitEmulated::power_on(),
-            d_register: Register16BitEmulated::power_on(),
-            program_counter: ProgramCounterEmulated::power_on(),
+            a_register: Register::power_on(config.registers),
+            d_register: Register::power_on(config.registers),
+            program_counter: Counter::power_on(config.pc),
+            alu: config.alu,
+            shifts: config.extensions.shifts,
+            a_register_out: [false; 16],
+            d_register_out: [false; 16],
+            pc_out: [false; 16],
+            interrupts: config.extensions.interrupt_vector.map(|vector| Interrupts {
+                vector: bits_from_i16(vector as i16),
+                return_address: Register::power_on(config.registers),
+                in_handler: Register1Bit::power_on(),
+                return_address_out: [false; 16],
+                in_handler_out: false,
+            }),
+            stack: config.extensions.stack.then(|| Stack {
+                stack_pointer: Register::power_on(config.registers),
+                stack_pointer_out: [false; 16],
+            }),
+            interrupt_request: false,
+            interrupt_acknowledge: false,
+        }
+    }
+
+    /// Drives the interrupt request line. It's read on the rising edge of the clock.
+    pub fn set_interrupt_request(&mut self, request: bool) {
+        self.interrupt_request = request;
+    }
+
+    /// Whether the CPU took the interrupt request on the last rising edge.
+    pub fn interrupt_acknowledge(&self) -> bool {
+        self.interrupt_acknowledge
+    }
+
+    /// Holds the reset line for one clock cycle, without an instruction:
+    /// the program counter goes to 0, and the interrupt handler is left.
+    pub fn reset(&mut self) {
+        for clock in [false, true] {
+            self.pc_out = self.program_counter.program_counter_clocked(
+                [false; 16],
+                false,
+                false,
+                true,
+                clock,
+            );
+            if let Some(interrupts) = &mut self.interrupts {
+                interrupts.return_address_out =
+                    interrupts
+                        .return_address
+                        .register_16bit_clocked([false; 16], true, clock);
+                interrupts.in_handler_out = interrupts
+                    .in_handler
+                    .register_1bit_clocked(false, true, clock);
+            }
+        }
+        self.interrupt_acknowledge = false;
+    }
+
+    /// Stores values into A, D and SP, e.g. the garbage, that the registers have at power on.
+    pub fn load_registers(&mut self, a: [bool; 16], d: [bool; 16], sp: [bool; 16]) {
+        self.a_register.set_debug_info(a);
+        self.a_register_out = a;
+        self.d_register.set_debug_info(d);
+        self.d_register_out = d;
+        if let Some(stack) = &mut self.stack {
+            stack.stack_pointer.set_debug_info(sp);
+            stack.stack_pointer_out = sp;
         }
     }
 
@@ -56,13 +166,29 @@ impl Cpu {
         // the leftover bytes are represented as value stored in register A
         let is_a_instruction = not(instr_bus[15]);
 
+        // # return from interrupt
+        // with the interrupt extension, instr[15..13] == 100 => RETI, which computes nothing
+        let is_reti = match self.interrupts {
+            Some(_) => and(instr_bus[15], and(not(instr_bus[14]), not(instr_bus[13]))),
+            None => false,
+        };
+
+        // # stack
+        // with the stack extension, instr[15..13] == 110 => the stack operation is in instr[2..0]
+        let is_stack = match self.stack {
+            Some(_) => and(instr_bus[15], and(instr_bus[14], not(instr_bus[13]))),
+            None => false,
+        };
+        let [is_push, is_pop, is_call, is_ret, is_set_sp, is_get_sp, _, _] =
+            dmux8way_array(is_stack, [instr_bus[0], instr_bus[1], instr_bus[2]]);
+
         // # computation instructions
         // if instr[15] == 1 => then instruction is C instruction
-        let is_c_instruction = instr_bus[15];
+        let is_c_instruction = and(instr_bus[15], not(or(is_reti, is_stack)));
 
         // ## These are labeled for C instruction
-        let _control_bit_x1 = instr_bus[14]; // not used
-        let _control_bit_x0 = instr_bus[13]; // not used
+        let _control_bit_x1 = instr_bus[14]; // not used, except for the extensions
+        let _control_bit_x0 = instr_bus[13]; // not used, except for the extensions
         let control_bit_a = instr_bus[12]; // source for y input of ALU
         let control_bit_c5 = instr_bus[11]; // 1. ALU operands and computation
         let control_bit_c4 = instr_bus[10]; // 2. ALU operands and computation
@@ -77,45 +203,101 @@ impl Cpu {
         let control_bit_j1 = instr_bus[1]; // 2. jump/branch
         let control_bit_j0 = instr_bus[0]; // 3. jump/branch
 
-        let (data_address_bus_16, data_address_bus_15) = self.run_a_register(
+        // The combinational part is calculated from the register outputs of the previous cycle.
+        // Only after that the registers are clocked,
+        // otherwise the ALU, the jump and the memory would see the values of the next instruction.
+        // the stack operations address the memory with SP, or with SP - 1 when they pop
+        let a_register_out = self.a_register_out;
+        let data_address_bus_16 = match &self.stack {
+            Some(stack) => {
+                let stack_address = mux16(
+                    stack.stack_pointer_out,
+                    adder_b16(stack.stack_pointer_out, [true; 16]),
+                    or(is_pop, is_ret),
+                );
+                mux16(a_register_out, stack_address, is_stack)
+            }
+            None => a_register_out,
+        };
+        let data_address_bus_15 = [
+            // pass two data address busses, because 16-bits are for PC and ALU
+            // and 15-bits are for return the instruction address bus
+            data_address_bus_16[0],
+            data_address_bus_16[1],
+            data_address_bus_16[2],
+            data_address_bus_16[3],
+            data_address_bus_16[4],
+            data_address_bus_16[5],
+            data_address_bus_16[6],
+            data_address_bus_16[7],
+            data_address_bus_16[8],
+            data_address_bus_16[9],
+            data_address_bus_16[10],
+            data_address_bus_16[11],
+            data_address_bus_16[12],
+            data_address_bus_16[13],
+            data_address_bus_16[14],
+        ];
+
+        let (zr, ng) = self.run_alu(
+            self.d_register_out,
+            a_register_out,
+            data_bus,
+            control_bit_a,
+            [
+                control_bit_c5,
+                control_bit_c4,
+                control_bit_c3,
+                control_bit_c2,
+                control_bit_c1,
+                control_bit_c0,
+            ],
+        );
+
+        // # shifts
+        // with the shift extension, instr[15..13] == 101 => the shifter replaces the ALU
+        let (zr, ng) = if self.shifts {
+            let is_shift = and(instr_bus[15], and(not(instr_bus[14]), instr_bus[13]));
+            self.run_shifter(
+                a_register_out,
+                data_bus,
+                [control_bit_a, control_bit_c5, control_bit_c4],
+                is_shift,
+                (zr, ng),
+            )
+        } else {
+            (zr, ng)
+        };
+
+        self.run_stack(
             data_bus,
+            [is_push, is_pop, is_call, is_ret, is_set_sp, is_get_sp],
+            clock_pulse,
+        );
+
+        // POP and =SP load A and D like a C-instruction, from the data out bus
+        let loads_registers = or(is_c_instruction, or(is_pop, is_get_sp));
+
+        self.run_a_register(
             instr_bus,
             is_a_instruction,
-            is_c_instruction,
+            loads_registers,
             control_bit_d2,
             clock_pulse,
         );
 
-        let data_out_bus = self.run_d_register(is_c_instruction, control_bit_d1, clock_pulse);
-
-        let (zr, ng) = self.run_alu(
-            data_out_bus,
-            data_address_bus_16,
-            data_bus,
-            control_bit_a,
-            control_bit_c5,
-            control_bit_c4,
-            control_bit_c3,
-            control_bit_c2,
-            control_bit_c1,
-            control_bit_c0,
-        );
+        self.run_d_register(loads_registers, control_bit_d1, clock_pulse);
 
         // Set bits for PC
-        let next_instr = self.run_pc(
-            data_address_bus_16,
-            is_c_instruction,
-            control_bit_j2,
-            control_bit_j1,
-            control_bit_j0,
-            zr,
-            ng,
-            reset,
-            clock_pulse,
-        );
+        // CALL jumps to A, RET to the popped return address
+        let jump = jump_condition([control_bit_j2, control_bit_j1, control_bit_j0], (zr, ng));
+        let pc_load = or(and(is_c_instruction, jump), or(is_call, is_ret));
+        let jump_target = mux16(a_register_out, data_bus, is_ret);
+        let next_instr = self.run_pc(jump_target, pc_load, is_reti, reset, clock_pulse);
 
         // Write enable
-        let write_enable = and(is_c_instruction, control_bit_d0);
+        // PUSH and CALL write RAM[SP]
+        let write_enable = or(and(is_c_instruction, control_bit_d0), or(is_push, is_call));
 
         // OUT
         (
@@ -128,46 +310,22 @@ impl Cpu {
 
     fn run_a_register(
         &mut self,
-        data_bus: [bool; 16],  // data bus
         instr_bus: [bool; 16], // instruction bus
 
         is_a_instruction: bool, // control bit
         is_c_instruction: bool, // control bit
         control_bit_d2: bool,   // control bit
         clock_pulse: bool,
-    ) -> ([bool; 16], [bool; 15]) {
-        // Select previous data or current instruction for register A
+    ) {
+        // Select ALU output or current instruction for register A
         let sel_a = and(is_c_instruction, control_bit_d2);
-        let data_or_instr_bus = mux16(instr_bus, data_bus, sel_a);
+        let alu_out_or_instr_bus = mux16(instr_bus, self.data_out_bus, sel_a);
 
         // Register A
         let load_a = or(is_a_instruction, sel_a);
-        let data_address_bus =
+        self.a_register_out =
             self.a_register
-                .register_16bit_clocked(data_or_instr_bus, load_a, clock_pulse);
-
-        (
-            data_address_bus,
-            [
-                // pass two data address busses, because 16-bits are for PC and ALU
-                // and 15-bits are for return the instruction address bus
-                data_address_bus[0],
-                data_address_bus[1],
-                data_address_bus[2],
-                data_address_bus[3],
-                data_address_bus[4],
-                data_address_bus[5],
-                data_address_bus[6],
-                data_address_bus[7],
-                data_address_bus[8],
-                data_address_bus[9],
-                data_address_bus[10],
-                data_address_bus[11],
-                data_address_bus[12],
-                data_address_bus[13],
-                data_address_bus[14],
-            ],
-        )
+                .register_16bit_clocked(alu_out_or_instr_bus, load_a, clock_pulse);
     }
 
     fn run_d_register(
@@ -175,10 +333,11 @@ impl Cpu {
         is_c_instruction: bool, // control bit
         control_bit_d1: bool,   // control bit
         clock_pulse: bool,
-    ) -> [bool; 16] {
+    ) {
         let load_d = and(control_bit_d1, is_c_instruction);
-        self.d_register
-            .register_16bit_clocked(self.data_out_bus, load_d, clock_pulse)
+        self.d_register_out =
+            self.d_register
+                .register_16bit_clocked(self.data_out_bus, load_d, clock_pulse);
     }
 
     fn run_alu(
@@ -187,28 +346,15 @@ impl Cpu {
         data_address_bus: [bool; 16], // data address bus, from A-register
         data_bus: [bool; 16],     // data bus, from input
 
-        control_bit_a: bool,  // control bit
-        control_bit_c5: bool, // control bit
-        control_bit_c4: bool, // control bit
-        control_bit_c3: bool, // control bit
-        control_bit_c2: bool, // control bit
-        control_bit_c1: bool, // control bit
-        control_bit_c0: bool, // control bit
+        control_bit_a: bool,       // control bit
+        control_bits_c: [bool; 6], // control bits c5..c0
     ) -> (bool, bool) {
         // ALU input y
         let alu_in_y = mux16(data_address_bus, data_bus, control_bit_a);
 
         // ALU
-        let (data_out_bus, zr, ng) = alu(
-            data_out_bus,   // 16-bit input x
-            alu_in_y,       // 16-bit input y
-            control_bit_c5, // zero the x input?
-            control_bit_c4, // negate the x input?
-            control_bit_c3, // zero the y input?
-            control_bit_c2, // negate the y input?
-            control_bit_c1, // function selector
-            control_bit_c0, // negate the output?
-        );
+        // control bits: zx, nx, zy, ny, f, no
+        let (data_out_bus, zr, ng) = alu_part(self.alu, data_out_bus, alu_in_y, control_bits_c);
 
         // update the data out bus
         self.data_out_bus = data_out_bus;
@@ -216,40 +362,114 @@ impl Cpu {
         (zr, ng)
     }
 
+    fn run_shifter(
+        &mut self,
+        data_address_bus: [bool; 16], // data address bus, from A-register
+        data_bus: [bool; 16],         // data bus, from input
+
+        control_bits: [bool; 3], // a: source M, l: shift left, s: source D
+        is_shift: bool,          // control bit
+        (zr, ng): (bool, bool),  // ALU out zero and negative flags
+    ) -> (bool, bool) {
+        let [control_bit_a, control_bit_l, control_bit_s] = control_bits;
+
+        // source: M, otherwise D or A
+        let source = mux16(data_address_bus, self.d_register_out, control_bit_s);
+        let source = mux16(source, data_bus, control_bit_a);
+        let (shift_out, shift_zr, shift_ng) = shifter16(source, control_bit_l);
+
+        self.data_out_bus = mux16(self.data_out_bus, shift_out, is_shift);
+
+        (mux(zr, shift_zr, is_shift), mux(ng, shift_ng, is_shift))
+    }
+
+    fn run_stack(
+        &mut self,
+        data_bus: [bool; 16], // data bus, RAM[SP - 1] when popping
+
+        ops: [bool; 6], // PUSH, POP, CALL, RET, SP= and =SP
+        clock_pulse: bool,
+    ) {
+        let Some(stack) = &mut self.stack else {
+            return;
+        };
+        let [is_push, is_pop, is_call, is_ret, is_set_sp, is_get_sp] = ops;
+        let stack_pointer = stack.stack_pointer_out;
+
+        // SP + 1 after pushing, SP - 1 after popping, or the ALU output
+        let pushes = or(is_push, is_call);
+        let pops = or(is_pop, is_ret);
+        let next_stack_pointer = mux16(
+            inc16(stack_pointer),
+            adder_b16(stack_pointer, [true; 16]),
+            pops,
+        );
+        let next_stack_pointer = mux16(next_stack_pointer, self.data_out_bus, is_set_sp);
+        stack.stack_pointer_out = stack.stack_pointer.register_16bit_clocked(
+            next_stack_pointer,
+            or(or(pushes, pops), is_set_sp),
+            clock_pulse,
+        );
+
+        // CALL pushes the address of the next instruction, only the 15 bits of the ROM address
+        let mut pc = self.pc_out;
+        pc[15] = false;
+        let data_out_bus = mux16(self.data_out_bus, inc16(pc), is_call);
+        let data_out_bus = mux16(data_out_bus, data_bus, is_pop);
+        self.data_out_bus = mux16(data_out_bus, stack_pointer, is_get_sp);
+    }
+
     fn run_pc(
         &mut self,
         data_address_bus: [bool; 16], // data bus
 
-        is_c_instruction: bool, // control bit
-        control_bit_j2: bool,   // control bit
-        control_bit_j1: bool,   // control bit
-        control_bit_j0: bool,   // control bit
-
-        zr: bool, // ALU out zero flag
-        ng: bool, // ALU out negative flag
+        pc_load: bool, // jump to A
+        is_reti: bool, // control bit
 
         reset: bool,       // reset
         clock_pulse: bool, // clock pulse
     ) -> [bool; 15] {
-        let zn = or(zr, ng);
-        let is_pos = not(zn);
-
-        let jlt = and(ng, control_bit_j2);
-        let jeq = and(zr, control_bit_j1);
-        let jgt = and(is_pos, control_bit_j0);
-
-        let jle = or(jlt, jeq);
-        let jmp_a = or(jle, jgt);
-        let pc_load = and(is_c_instruction, jmp_a);
         let inc = not(pc_load);
 
-        let next_instr = self.program_counter.program_counter_clocked(
-            data_address_bus,
-            pc_load,
-            inc,
-            reset,
-            clock_pulse,
-        );
+        let next_instr = match &mut self.interrupts {
+            None => self.program_counter.program_counter_clocked(
+                data_address_bus,
+                pc_load,
+                inc,
+                reset,
+                clock_pulse,
+            ),
+            Some(interrupts) => {
+                // where the program continues: PC + 1, A, or the return address for RETI
+                let next_pc = mux16(inc16(self.pc_out), data_address_bus, pc_load);
+                let next_pc = mux16(next_pc, interrupts.return_address_out, is_reti);
+
+                // take the request, unless the handler is already running
+                let take = and(self.interrupt_request, not(interrupts.in_handler_out));
+                self.interrupt_acknowledge = take;
+
+                interrupts.return_address_out =
+                    interrupts
+                        .return_address
+                        .register_16bit_clocked(next_pc, take, clock_pulse);
+
+                // set by the interrupt, cleared by RETI
+                interrupts.in_handler_out = interrupts.in_handler.register_1bit_clocked(
+                    take,
+                    or(take, is_reti),
+                    clock_pulse,
+                );
+
+                self.program_counter.program_counter_clocked(
+                    mux16(next_pc, interrupts.vector, take),
+                    true,
+                    false,
+                    reset,
+                    clock_pulse,
+                )
+            }
+        };
+        self.pc_out = next_instr;
 
         [
             next_instr[0],
@@ -270,13 +490,43 @@ impl Cpu {
         ]
     }
 
-    pub fn get_debug_info(&mut self) -> ([bool; 16], [bool; 16], [bool; 16]) {
+    pub fn get_debug_info(&self) -> ([bool; 16], [bool; 16], [bool; 16]) {
         (
             self.a_register.get_debug_info(),
             self.d_register.get_debug_info(),
             self.program_counter.get_debug_info(),
         )
     }
+
+    /// Stack pointer, `None` without the stack extension.
+    pub fn get_stack_debug_info(&self) -> Option<[bool; 16]> {
+        self.stack
+            .as_ref()
+            .map(|stack| stack.stack_pointer.get_debug_info())
+    }
+
+    /// Return address and whether the handler is running, `None` without the interrupt extension.
+    pub fn get_interrupt_debug_info(&self) -> Option<([bool; 16], bool)> {
+        self.interrupts.as_ref().map(|interrupts| {
+            (
+                interrupts.return_address.get_debug_info(),
+                interrupts.in_handler.current_value,
+            )
+        })
+    }
+}
+
+/// Whether the ALU output satisfies the jump bits j2..j0.
+fn jump_condition(control_bits_j: [bool; 3], (zr, ng): (bool, bool)) -> bool {
+    let zn = or(zr, ng);
+    let is_pos = not(zn);
+
+    let jlt = and(ng, control_bits_j[0]);
+    let jeq = and(zr, control_bits_j[1]);
+    let jgt = and(is_pos, control_bits_j[2]);
+
+    let jle = or(jlt, jeq);
+    or(jle, jgt)
 }
 
 mod test {
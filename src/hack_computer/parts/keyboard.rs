@@ -32,14 +32,16 @@ impl Keyboard {
         }
     }
 
-    pub fn write(&mut self, input: [bool; 16], clock: bool) {
-        self.values = self.register_nbit_clocked::<16>(input, true, clock)
+    /// Register 16 bit, that is loaded from the keyboard on every clock cycle.
+    /// The CPU can't write into it.
+    /// Rwister count: 1
+    pub fn keyboard(&mut self, input: [bool; 16], clock: bool) -> [bool; 16] {
+        self.values = self.register_nbit_clocked::<16>(input, true, clock);
+        self.values
     }
 
-    /// Register 16 bit
-    /// Rwister count: 1
-    pub fn read(&mut self, clock: bool) -> [bool; 16] {
-        self.register_nbit_clocked::<16>(self.values, false, clock)
+    pub fn get_debug_info(&self) -> [bool; 16] {
+        self.values
     }
 
     fn register_nbit_clocked<const N: usize>(
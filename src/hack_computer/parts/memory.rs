@@ -1,34 +1,81 @@
 use crate::{
-    emulated_parts::ram16k_emulated::Ram16kEmulated,
-    hack_computer::{
-        gates::{
-            gates_b1::or,
-            gates_mw::{demux4way, mux4way16},
-        },
-        ram::ram16k::Ram16k,
+    hack_computer::gates::{
+        gates_b1::or,
+        gates_mw::{demux4way, mux4way16},
     },
+    machine::{
+        config::MachineConfig,
+        device::{Device, DeviceMap},
+        KEYBOARD_ADDRESS, SCREEN_ADDRESS,
+    },
+    utils::bit_manipulation::{bits_from_i16, i16_from_bits},
 };
 
-use super::{keyboard::Keyboard, screen::Screen};
+use super::mixed::{KeyboardPart, Ram, ScreenPart};
 
 pub struct Memory {
-    ram: Ram16kEmulated,
-    screen: Screen,
-    keyboard: Keyboard,
+    ram: Ram,
+    screen: ScreenPart,
+    keyboard: KeyboardPart,
+
+    // key code from the keyboard device, latched by the keyboard register on the clock
+    keyboard_bus: [bool; 16],
+
+    // memory-mapped peripherals after the keyboard, outside of the chip
+    devices: DeviceMap,
+
+    // a device requested an interrupt on the last rising edge
+    interrupt_request: bool,
 }
 
 impl Memory {
     pub fn power_on() -> Self {
+        Self::power_on_with_config(MachineConfig::default())
+    }
+
+    pub fn power_on_with_config(config: MachineConfig) -> Self {
         Self {
-            ram: Ram16kEmulated::power_on(),
-            screen: Screen::power_on(),
-            keyboard: Keyboard::power_on(),
+            ram: Ram::power_on(config.ram),
+            screen: ScreenPart::power_on(config.screen),
+            keyboard: KeyboardPart::power_on(config.keyboard),
+            keyboard_bus: [false; 16],
+            devices: DeviceMap::default(),
+            interrupt_request: false,
         }
     }
 
+    /// Starts again like at power on. The devices stay attached.
+    pub fn power_cycle(&mut self, config: MachineConfig) {
+        let devices = std::mem::take(&mut self.devices);
+        *self = Self {
+            devices,
+            ..Self::power_on_with_config(config)
+        };
+    }
+
+    pub fn attach_device(&mut self, base: usize, device: Box<dyn Device>) -> Result<(), String> {
+        self.devices.attach(base, device)
+    }
+
+    /// Tells the device at the address, that the CPU takes the word in this cycle.
+    /// Called before the rising edge, that ticks the devices.
+    pub fn read_strobe(&mut self, address: [bool; 15]) {
+        self.devices.read_strobe(i16_from_bits(address) as usize);
+    }
+
+    /// Whether a device has requested an interrupt since the last call.
+    pub fn take_interrupt_request(&mut self) -> bool {
+        std::mem::take(&mut self.interrupt_request)
+    }
+
+    /// Whether a device has requested an interrupt, or may still request one.
+    pub fn may_interrupt(&self) -> bool {
+        self.interrupt_request || self.devices.may_interrupt()
+    }
+
     // Input events
-    pub fn write_from_io_driver(&mut self, input: [bool; 16], clock: bool) {
-        self.keyboard.write(input, clock);
+    pub fn write_from_io_driver(&mut self, input: [bool; 16]) {
+        self.keyboard_bus = input;
     }
 
     pub fn memory(
@@ -43,7 +90,7 @@ impl Memory {
             load_ram1,   // ram
             load_ram2,   // ram
             load_screen, // screen
-            _,           // keyboard, does not have input
+            load_io,     // keyboard does not have input, but the devices after it may have
         ) = demux4way(load, cb);
         let load_ram = or(load_ram1, load_ram2);
 
@@ -65,6 +112,7 @@ impl Memory {
             address[13],
         ];
 
+        // 8192 words: RAM[16384..24576)
         let screen_address = [
             address[0],
             address[1],
@@ -78,20 +126,62 @@ impl Memory {
             address[9],
             address[10],
             address[11],
+            address[12],
         ];
 
-        let ram_out = self
-            .ram
-            .ram16k_emulated::<14>(input, load_ram, ram_address, clock);
+        let ram_out = self.ram.ram16k(input, load_ram, ram_address, clock);
         let screen_out = self
             .screen
             .screen(input, load_screen, screen_address, clock);
-        let keyboard_out = self.keyboard.read(clock); // one word does not require address
+        let keyboard_out = self.keyboard.keyboard(self.keyboard_bus, clock); // one word does not require address
+
+        // The devices are not built from gates. They are written on the rising edge,
+        // like the registers, and they tick once per cycle.
+        let io_address = i16_from_bits(address) as usize;
+        if clock {
+            if load_io {
+                self.devices.write(io_address, i16_from_bits(input));
+            }
+            self.interrupt_request |= self.devices.tick();
+        }
+        // the keyboard is the first word of the quadrant, and the unmapped words read as 0
+        let io_out = match self.devices.read(io_address) {
+            Some(word) => bits_from_i16(word),
+            None if io_address == KEYBOARD_ADDRESS => keyboard_out,
+            None => [false; 16],
+        };
 
-        mux4way16(ram_out, ram_out, screen_out, keyboard_out, cb)
+        mux4way16(ram_out, ram_out, screen_out, io_out, cb)
     }
 
     pub fn get_ram(&self, start: usize, end: usize) -> Vec<(usize, i16)> {
-        self.ram.get_ram(start, end)
+        (start..end.min(SCREEN_ADDRESS))
+            .map(|address| (address, self.get_word(address)))
+            .collect()
+    }
+
+    /// Reads one word from any part of the memory map without ticking the clock.
+    pub fn get_word(&self, address: usize) -> i16 {
+        let word = if address < SCREEN_ADDRESS {
+            self.ram.get_debug_info(address)
+        } else if address < KEYBOARD_ADDRESS {
+            self.screen.get_debug_info(address - SCREEN_ADDRESS)
+        } else if address == KEYBOARD_ADDRESS {
+            self.keyboard.get_debug_info()
+        } else {
+            return self.devices.read(address).unwrap_or(0);
+        };
+
+        i16_from_bits(word)
+    }
+
+    /// Stores one word into the RAM or the screen without ticking the clock of the others.
+    pub fn set_word(&mut self, address: usize, value: i16) {
+        if address < SCREEN_ADDRESS {
+            self.ram.set_debug_info(address, bits_from_i16(value));
+        } else if address < KEYBOARD_ADDRESS {
+            self.screen
+                .set_debug_info(address - SCREEN_ADDRESS, bits_from_i16(value));
+        }
     }
 }
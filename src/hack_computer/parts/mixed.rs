@@ -0,0 +1,256 @@
+use crate::{
+    emulated_parts::{
+        alu_emulated::alu_emulated, keyboard_emulated::KeyboardEmulated,
+        program_counter_emulated::ProgramCounterEmulated, ram16k_emulated::Ram16kEmulated,
+        register_16bit_emulated::Register16BitEmulated, screen_emulated::ScreenEmulated,
+    },
+    hack_computer::{
+        chips::alu::alu,
+        ram::ram16k::Ram16k,
+        registers::{program_counter::ProgramCounter, register_16bit::Register16Bit},
+    },
+    machine::config::Fidelity,
+    utils::bit_manipulation::{bits_from_i16, i16_from_bits},
+};
+
+use super::{keyboard::Keyboard, screen::Screen};
+
+// Parts of the computer, that can be either built from gates or emulated.
+// The enums have the same interface as the parts themselves,
+// so `Cpu` and `Memory` do not need to know which one they are using.
+
+/// ALU. `control_bits` are zx, nx, zy, ny, f and no.
+pub fn alu_part(
+    fidelity: Fidelity,
+    x: [bool; 16],
+    y: [bool; 16],
+    control_bits: [bool; 6],
+) -> ([bool; 16], bool, bool) {
+    match fidelity {
+        Fidelity::Gate => alu(
+            x,
+            y,
+            control_bits[0],
+            control_bits[1],
+            control_bits[2],
+            control_bits[3],
+            control_bits[4],
+            control_bits[5],
+        ),
+        Fidelity::Emulated => {
+            let control = control_bits
+                .iter()
+                .fold(0, |control, bit| control << 1 | *bit as u16);
+            let (out, zr, ng) = alu_emulated(i16_from_bits(x), i16_from_bits(y), control);
+
+            (bits_from_i16(out), zr, ng)
+        }
+    }
+}
+
+pub enum Register {
+    Gate(Register16Bit),
+    Emulated(Register16BitEmulated),
+}
+
+impl Register {
+    pub fn power_on(fidelity: Fidelity) -> Self {
+        match fidelity {
+            Fidelity::Gate => Register::Gate(Register16Bit::power_on()),
+            Fidelity::Emulated => Register::Emulated(Register16BitEmulated::power_on()),
+        }
+    }
+
+    pub fn register_16bit_clocked(
+        &mut self,
+        input: [bool; 16],
+        load: bool,
+        clock: bool,
+    ) -> [bool; 16] {
+        match self {
+            Register::Gate(register) => register.register_16bit_clocked(input, load, clock),
+            Register::Emulated(register) => register.register_16bit_clocked(input, load, clock),
+        }
+    }
+
+    pub fn get_debug_info(&self) -> [bool; 16] {
+        match self {
+            Register::Gate(register) => register.get_debug_info(),
+            Register::Emulated(register) => register.get_debug_info(),
+        }
+    }
+
+    pub fn set_debug_info(&mut self, value: [bool; 16]) {
+        match self {
+            Register::Gate(register) => register.set_debug_info(value),
+            Register::Emulated(register) => register.set_debug_info(value),
+        }
+    }
+}
+
+pub enum Counter {
+    Gate(ProgramCounter),
+    Emulated(ProgramCounterEmulated),
+}
+
+impl Counter {
+    pub fn power_on(fidelity: Fidelity) -> Self {
+        match fidelity {
+            Fidelity::Gate => Counter::Gate(ProgramCounter::power_on()),
+            Fidelity::Emulated => Counter::Emulated(ProgramCounterEmulated::power_on()),
+        }
+    }
+
+    pub fn program_counter_clocked(
+        &mut self,
+        input: [bool; 16],
+        load: bool,
+        inc: bool,
+        reset: bool,
+        clock: bool,
+    ) -> [bool; 16] {
+        match self {
+            Counter::Gate(pc) => pc.program_counter_clocked(input, load, inc, reset, clock),
+            Counter::Emulated(pc) => pc.program_counter_clocked(input, load, inc, reset, clock),
+        }
+    }
+
+    pub fn get_debug_info(&self) -> [bool; 16] {
+        match self {
+            Counter::Gate(pc) => pc.get_debug_info(),
+            Counter::Emulated(pc) => pc.get_debug_info(),
+        }
+    }
+}
+
+pub enum Ram {
+    Gate(Box<Ram16k>),
+    Emulated(Ram16kEmulated),
+}
+
+impl Ram {
+    pub fn power_on(fidelity: Fidelity) -> Self {
+        match fidelity {
+            Fidelity::Gate => Ram::Gate(Box::new(Ram16k::power_on())),
+            Fidelity::Emulated => Ram::Emulated(Ram16kEmulated::power_on()),
+        }
+    }
+
+    pub fn ram16k(
+        &mut self,
+        input: [bool; 16],
+        load: bool,
+        address: [bool; 14],
+        clock: bool,
+    ) -> [bool; 16] {
+        match self {
+            Ram::Gate(ram) => ram.ram16k(input, load, address, clock),
+            Ram::Emulated(ram) => ram.ram16k(input, load, address, clock),
+        }
+    }
+
+    pub fn get_debug_info(&self, address: usize) -> [bool; 16] {
+        match self {
+            Ram::Gate(ram) => ram.get_debug_info(address),
+            Ram::Emulated(ram) => ram.get_debug_info(address),
+        }
+    }
+
+    pub fn set_debug_info(&mut self, address: usize, value: [bool; 16]) {
+        match self {
+            Ram::Gate(ram) => ram.set_debug_info(address, value),
+            Ram::Emulated(ram) => ram.set_debug_info(address, value),
+        }
+    }
+}
+
+pub enum ScreenPart {
+    Gate(Box<Screen>),
+    Emulated(ScreenEmulated),
+}
+
+impl ScreenPart {
+    pub fn power_on(fidelity: Fidelity) -> Self {
+        match fidelity {
+            Fidelity::Gate => ScreenPart::Gate(Box::new(Screen::power_on())),
+            Fidelity::Emulated => ScreenPart::Emulated(ScreenEmulated::power_on()),
+        }
+    }
+
+    pub fn screen(
+        &mut self,
+        input: [bool; 16],
+        load: bool,
+        address: [bool; 13],
+        clock: bool,
+    ) -> [bool; 16] {
+        match self {
+            ScreenPart::Gate(screen) => screen.screen(input, load, address, clock),
+            ScreenPart::Emulated(screen) => screen.screen(input, load, address, clock),
+        }
+    }
+
+    pub fn get_debug_info(&self, address: usize) -> [bool; 16] {
+        match self {
+            ScreenPart::Gate(screen) => screen.get_debug_info(address),
+            ScreenPart::Emulated(screen) => screen.get_debug_info(address),
+        }
+    }
+
+    pub fn set_debug_info(&mut self, address: usize, value: [bool; 16]) {
+        match self {
+            ScreenPart::Gate(screen) => screen.set_debug_info(address, value),
+            ScreenPart::Emulated(screen) => screen.set_debug_info(address, value),
+        }
+    }
+}
+
+pub enum KeyboardPart {
+    Gate(Keyboard),
+    Emulated(KeyboardEmulated),
+}
+
+impl KeyboardPart {
+    pub fn power_on(fidelity: Fidelity) -> Self {
+        match fidelity {
+            Fidelity::Gate => KeyboardPart::Gate(Keyboard::power_on()),
+            Fidelity::Emulated => KeyboardPart::Emulated(KeyboardEmulated::power_on()),
+        }
+    }
+
+    pub fn keyboard(&mut self, input: [bool; 16], clock: bool) -> [bool; 16] {
+        match self {
+            KeyboardPart::Gate(keyboard) => keyboard.keyboard(input, clock),
+            KeyboardPart::Emulated(keyboard) => keyboard.keyboard(input, clock),
+        }
+    }
+
+    pub fn get_debug_info(&self) -> [bool; 16] {
+        match self {
+            KeyboardPart::Gate(keyboard) => keyboard.get_debug_info(),
+            KeyboardPart::Emulated(keyboard) => keyboard.get_debug_info(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn test_alu_part_fidelities_agree() {
+        use super::*;
+
+        let x = bits_from_i16(1234);
+        let y = bits_from_i16(-77);
+
+        for control in 0..64u16 {
+            let control_bits = std::array::from_fn(|i| control >> (5 - i) & 1 == 1);
+
+            assert_eq!(
+                alu_part(Fidelity::Gate, x, y, control_bits),
+                alu_part(Fidelity::Emulated, x, y, control_bits),
+                "control: {:06b}",
+                control
+            );
+        }
+    }
+}
@@ -1,5 +1,6 @@
 pub mod cpu;
+pub mod keyboard;
 pub mod memory;
+pub mod mixed;
+pub mod rom;
 pub mod screen;
-// pub mod rom;
-pub mod keyboard;
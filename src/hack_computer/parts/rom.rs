@@ -0,0 +1,183 @@
+use crate::{hack_computer::gates::gates_mw::mux8way16, utils::convert_16b::from_i16};
+
+// ROM is built like the RAM, but the registers are replaced with fuses:
+// the words are burned in once at the power on, and after that they can only be read.
+// Because nothing is ever written, there are no load bits, demuxes or clock.
+
+fn mux8way16_array(words: [[bool; 16]; 8], s: [bool; 3]) -> [bool; 16] {
+    mux8way16(
+        words[0], words[1], words[2], words[3], words[4], words[5], words[6], words[7], s,
+    )
+}
+
+/// Burns the words of the disk. Missing words are zeros.
+fn burn(disk: &[i16], offset: usize) -> [bool; 16] {
+    match disk.get(offset) {
+        Some(word) => from_i16(*word).unwrap().as_array_b16,
+        None => [false; 16],
+    }
+}
+
+/// ROM that fits 8 words.
+pub struct Rom8 {
+    fuses: [[bool; 16]; 8],
+}
+
+impl Rom8 {
+    pub fn power_on(disk: &[i16]) -> Self {
+        Self {
+            fuses: std::array::from_fn(|i| burn(disk, i)),
+        }
+    }
+
+    pub fn rom8(&self, address: [bool; 3]) -> [bool; 16] {
+        mux8way16_array(self.fuses, address)
+    }
+}
+
+/// ROM that fits 64 words.
+pub struct Rom64 {
+    child_parts: [Rom8; 8],
+}
+
+impl Rom64 {
+    pub fn power_on(disk: &[i16]) -> Self {
+        Self {
+            child_parts: std::array::from_fn(|i| Rom8::power_on(disk.get(i * 8..).unwrap_or(&[]))),
+        }
+    }
+
+    pub fn rom64(&self, address: [bool; 6]) -> [bool; 16] {
+        let low = [address[0], address[1], address[2]];
+        let outputs = std::array::from_fn(|i| self.child_parts[i].rom8(low));
+
+        mux8way16_array(outputs, [address[3], address[4], address[5]])
+    }
+}
+
+/// ROM that fits 512 words.
+pub struct Rom512 {
+    child_parts: [Rom64; 8],
+}
+
+impl Rom512 {
+    pub fn power_on(disk: &[i16]) -> Self {
+        Self {
+            child_parts: std::array::from_fn(|i| {
+                Rom64::power_on(disk.get(i * 64..).unwrap_or(&[]))
+            }),
+        }
+    }
+
+    pub fn rom512(&self, address: [bool; 9]) -> [bool; 16] {
+        let low = [
+            address[0], address[1], address[2], address[3], address[4], address[5],
+        ];
+        let outputs = std::array::from_fn(|i| self.child_parts[i].rom64(low));
+
+        mux8way16_array(outputs, [address[6], address[7], address[8]])
+    }
+}
+
+/// ROM that fits 4096 words.
+pub struct Rom4k {
+    child_parts: [Rom512; 8],
+}
+
+impl Rom4k {
+    pub fn power_on(disk: &[i16]) -> Self {
+        Self {
+            child_parts: std::array::from_fn(|i| {
+                Rom512::power_on(disk.get(i * 512..).unwrap_or(&[]))
+            }),
+        }
+    }
+
+    pub fn rom4k(&self, address: [bool; 12]) -> [bool; 16] {
+        let low = [
+            address[0], address[1], address[2], address[3], address[4], address[5], address[6],
+            address[7], address[8],
+        ];
+        let outputs = std::array::from_fn(|i| self.child_parts[i].rom512(low));
+
+        mux8way16_array(outputs, [address[9], address[10], address[11]])
+    }
+}
+
+/// ROM that fits 32768 words. The instruction memory of the computer.
+///
+/// The ROM has 8 sockets for 4K chips. Only the sockets that the disk reaches are populated,
+/// the empty sockets read as zeros, like a burned chip full of zeros would.
+pub struct Rom32k {
+    sockets: [Option<Box<Rom4k>>; 8],
+}
+
+impl Rom32k {
+    pub fn power_on(disk: Vec<i16>) -> Self {
+        Self {
+            sockets: std::array::from_fn(|i| {
+                disk.get(i * 4096..)
+                    .filter(|chip| !chip.is_empty())
+                    .map(|chip| Box::new(Rom4k::power_on(chip)))
+            }),
+        }
+    }
+
+    // receives the instruction address bus
+    // returns the instruction for the CPU
+    pub fn rom(&self, address: [bool; 16]) -> [bool; 16] {
+        let low = [
+            address[0],
+            address[1],
+            address[2],
+            address[3],
+            address[4],
+            address[5],
+            address[6],
+            address[7],
+            address[8],
+            address[9],
+            address[10],
+            address[11],
+        ];
+        let outputs = std::array::from_fn(|i| match &self.sockets[i] {
+            Some(chip) => chip.rom4k(low),
+            None => [false; 16],
+        });
+
+        mux8way16_array(outputs, [address[12], address[13], address[14]])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn test_rom32k() {
+        use super::*;
+        use crate::utils::convert_16b::from_b16;
+
+        let mut disk = vec![0; 4100];
+        disk[0] = 6;
+        disk[7] = -5104;
+        disk[100] = 1234;
+        disk[4095] = -1;
+        disk[4099] = 42;
+
+        let rom = Rom32k::power_on(disk);
+        let read = |address: i16| {
+            let word = rom.rom(from_i16(address).unwrap().as_array_b16);
+            from_b16(word).unwrap().as_integer
+        };
+
+        assert_eq!(read(0), 6);
+        assert_eq!(read(7), -5104);
+        assert_eq!(read(100), 1234);
+        assert_eq!(read(101), 0);
+        assert_eq!(read(4095), -1);
+        assert_eq!(read(4099), 42);
+
+        // beyond the disk, and in an empty socket
+        assert_eq!(read(4100), 0);
+        assert_eq!(read(20000), 0);
+    }
+}
@@ -1,5 +1,8 @@
 use crate::hack_computer::{
-    gates::{gates_b1::not, gates_b16::mux16},
+    gates::{
+        gates_b1::{and, not},
+        gates_b16::mux16,
+    },
     ram::ram4k::Ram4k,
 };
 
@@ -48,21 +51,85 @@ impl Screen {
     //
     // set col%16 bit of w to 1 or to 0
 
-    // TODO: Test
-    /// RAM 4K
-    /// Rwister count: 4096
+    /// Screen memory map: 2 x RAM 4K
+    /// Register count: 8192
     pub fn screen(
         &mut self,
         input: [bool; 16],
         load: bool,
-        address: [bool; 12],
+        address: [bool; 13],
         clock: bool,
     ) -> [bool; 16] {
+        // the highest bit selects the chip, the rest is the address inside of it
+        let ram_address = [
+            address[0],
+            address[1],
+            address[2],
+            address[3],
+            address[4],
+            address[5],
+            address[6],
+            address[7],
+            address[8],
+            address[9],
+            address[10],
+            address[11],
+        ];
+
         //demux
-        let out1 = self.ram1.ram4k(input, not(address[11]), address, clock);
-        let out2 = self.ram2.ram4k(input, address[11], address, clock);
+        let out1 = self
+            .ram1
+            .ram4k(input, and(load, not(address[12])), ram_address, clock);
+        let out2 = self
+            .ram2
+            .ram4k(input, and(load, address[12]), ram_address, clock);
 
         //mux
-        mux16(out1, out2, address[11])
+        mux16(out1, out2, address[12])
+    }
+
+    /// Reads the word at the screen address without ticking the clock.
+    pub fn get_debug_info(&self, address: usize) -> [bool; 16] {
+        // same selection as in `screen`
+        if address & 0x1000 == 0 {
+            self.ram1.get_debug_info(address & 0xFFF)
+        } else {
+            self.ram2.get_debug_info(address & 0xFFF)
+        }
+    }
+
+    /// Stores a word at the screen address without ticking the clock of the other words.
+    pub fn set_debug_info(&mut self, address: usize, value: [bool; 16]) {
+        if address & 0x1000 == 0 {
+            self.ram1.set_debug_info(address & 0xFFF, value);
+        } else {
+            self.ram2.set_debug_info(address & 0xFFF, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn test_screen_halves_are_separate() {
+        use super::*;
+        use crate::utils::bit_manipulation::{bits_from_i16, i16_from_bits};
+
+        let mut screen = Screen::power_on();
+        let addresses: [i16; 4] = [0, 2048, 4096, 8191];
+
+        for address in addresses {
+            let address_bits = bits_from_i16(address);
+            let address_bits = std::array::from_fn(|i| address_bits[i]);
+            let input = bits_from_i16(address + 1);
+
+            screen.screen(input, true, address_bits, false);
+            screen.screen(input, true, address_bits, true);
+        }
+
+        for address in addresses {
+            let word = i16_from_bits(screen.get_debug_info(address as usize));
+            assert_eq!(word, address + 1, "screen[{}]", address);
+        }
     }
 }
@@ -1,10 +1,4 @@
-use crate::{
-    emulated_parts::register_16bit_emulated::Register16BitEmulated,
-    hack_computer::{
-        gates::gates_mw::{dmux8way, mux4way16, mux8way16},
-        registers::{self, register_16bit::Register16Bit, register_1bit::Register1Bit},
-    },
-};
+use crate::hack_computer::gates::gates_mw::{demux4way, mux4way16};
 
 use super::ram4k::Ram4k;
 
@@ -38,7 +32,8 @@ impl Ram16k {
         address: [bool; 14],
         clock: bool,
     ) -> [bool; 16] {
-        let dmux_out = dmux8way(load, [address[11], address[12], address[13]]);
+        // 4 chips of 4K: the two highest bits select the chip
+        let dmux_out = demux4way(load, [address[12], address[13]]);
 
         let a = self.child_parts[0].ram4k(
             input,
@@ -120,6 +115,16 @@ impl Ram16k {
 
         mux4way16(a, b, c, d, [address[12], address[13]])
     }
+
+    /// Reads a register without ticking the clock.
+    pub fn get_debug_info(&self, address: usize) -> [bool; 16] {
+        self.child_parts[(address >> 12) & 0b11].get_debug_info(address)
+    }
+
+    /// Stores a value into the register at the address. The other registers are not clocked.
+    pub fn set_debug_info(&mut self, address: usize, value: [bool; 16]) {
+        self.child_parts[(address >> 12) & 0b11].set_debug_info(address, value);
+    }
 }
 
 mod test {
@@ -154,4 +159,27 @@ mod test {
         println!("RIGHT = EXPECTED");
         assert_eq!(conv.to_string(), expect.to_string());
     }
+
+    #[test]
+    fn test_ram16k_every_chip_is_addressable() {
+        let mut ram16k = super::Ram16k::power_on();
+
+        // one address from each 4K chip, and the neighbour that used to alias it
+        let addresses: [usize; 7] = [0, 2048, 4096, 6144, 8192, 12288, 16383];
+        for (i, address) in addresses.iter().enumerate() {
+            let input = from_i16(i as i16 + 1).unwrap().as_array_b16;
+            let address = from_i16(*address as i16).unwrap().as_array_b16;
+            let address: [bool; 14] = address[0..14].try_into().unwrap();
+
+            ram16k.ram16k(input, true, address, false);
+            ram16k.ram16k(input, true, address, true);
+        }
+
+        for (i, address) in addresses.iter().enumerate() {
+            let value = from_b16(ram16k.get_debug_info(*address))
+                .unwrap()
+                .as_integer;
+            assert_eq!(value, i as i16 + 1, "RAM[{}]", address);
+        }
+    }
 }
@@ -118,4 +118,14 @@ impl Ram4k {
             [address[9], address[10], address[11]],
         )
     }
+
+    /// Reads the register at the address without ticking the clock.
+    pub fn get_debug_info(&self, address: usize) -> [bool; 16] {
+        self.child_parts[(address >> 9) & 0b111].get_debug_info(address)
+    }
+
+    /// Stores a value into the register at the address. The other registers are not clocked.
+    pub fn set_debug_info(&mut self, address: usize, value: [bool; 16]) {
+        self.child_parts[(address >> 9) & 0b111].set_debug_info(address, value);
+    }
 }
@@ -84,4 +84,14 @@ impl Ram64 {
 
         mux8way16(a, b, c, d, e, f, g, h, [address[3], address[4], address[5]])
     }
+
+    /// Reads the register at the address without ticking the clock.
+    pub fn get_debug_info(&self, address: usize) -> [bool; 16] {
+        self.child_parts[(address >> 3) & 0b111].get_debug_info(address)
+    }
+
+    /// Stores a value into the register at the address. The other registers are not clocked.
+    pub fn set_debug_info(&mut self, address: usize, value: [bool; 16]) {
+        self.child_parts[(address >> 3) & 0b111].set_debug_info(address, value);
+    }
 }
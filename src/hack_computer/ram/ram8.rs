@@ -47,4 +47,14 @@ impl Ram8 {
             out_reg0, out_reg1, out_reg2, out_reg3, out_reg4, out_reg5, out_reg6, out_reg7, address,
         )
     }
+
+    /// Reads the register at the address without ticking the clock.
+    pub fn get_debug_info(&self, address: usize) -> [bool; 16] {
+        self.child_circuits[address & 0b111].get_debug_info()
+    }
+
+    /// Stores a value into the register at the address. The other registers are not clocked.
+    pub fn set_debug_info(&mut self, address: usize, value: [bool; 16]) {
+        self.child_circuits[address & 0b111].set_debug_info(value);
+    }
 }
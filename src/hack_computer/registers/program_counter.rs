@@ -1,25 +1,20 @@
-use crate::{
-    emulated_parts::register_16bit_emulated::Register16BitEmulated,
-    hack_computer::{
-        chips::adder::inc16,
-        gates::{gates_b1::or, gates_b16::mux16, gates_mw::mux4way16},
-    },
-};
+use crate::hack_computer::{chips::adder::inc16, gates::gates_b16::mux16};
 
 use super::register_16bit::Register16Bit;
 
 pub struct ProgramCounter {
-    base_circuit: Register16BitEmulated,
+    base_circuit: Register16Bit,
     feedback_out: [bool; 16],
 }
 
 impl ProgramCounter {
     pub fn power_on() -> Self {
         Self {
-            base_circuit: Register16BitEmulated::power_on(),
+            base_circuit: Register16Bit::power_on(),
             feedback_out: [false; 16],
         }
     }
+
     pub fn program_counter_clocked(
         &mut self,
         input: [bool; 16],
@@ -28,24 +23,20 @@ impl ProgramCounter {
         reset: bool, // should emit zero from the register on next cycle
         clock: bool,
     ) -> [bool; 16] {
-        // https://stackoverflow.com/questions/15034037/trying-to-build-a-pc-counter-for-the-nand2tetris-book-but-im-having-some-tro
-        let reset_out = mux16(input, [false; 16], reset);
-        let load_or_reset = or(load, reset);
-
-        let reg_in = mux16(self.feedback_out, reset_out, load_or_reset);
+        // priority: reset > load > inc > keep
+        let inc_out = mux16(self.feedback_out, inc16(self.feedback_out), inc);
+        let load_out = mux16(inc_out, input, load);
+        let reg_in = mux16(load_out, [false; 16], reset);
 
-        let reg_load = or(load, reset);
-        let reg_out = self
+        // the register is always loaded, the muxes above decide what is kept
+        self.feedback_out = self
             .base_circuit
-            .register_16bit_clocked(reg_in, reg_load, clock);
-
-        let inc_out = inc16(reg_out);
-        self.feedback_out = mux16(reg_out, inc_out, inc);
+            .register_16bit_clocked(reg_in, true, clock);
 
         self.feedback_out
     }
 
-    pub fn get_debug_info(&mut self) -> [bool; 16] {
+    pub fn get_debug_info(&self) -> [bool; 16] {
         self.base_circuit.get_debug_info()
     }
 }
@@ -80,4 +71,24 @@ pub mod test {
 
         assert_eq!(output, [false; 16], "tick tock 2");
     }
+
+    #[test]
+    fn test_program_counter_inc_load_reset() {
+        use crate::utils::convert_16b::{from_b16, from_i16};
+
+        let mut pc = ProgramCounter::power_on();
+        let mut tick = |input: i16, load: bool, inc: bool, reset: bool| {
+            let input = from_i16(input).unwrap().as_array_b16;
+            pc.program_counter_clocked(input, load, inc, reset, false);
+            let out = pc.program_counter_clocked(input, load, inc, reset, true);
+            from_b16(out).unwrap().as_integer
+        };
+
+        assert_eq!(tick(0, false, true, false), 1, "inc");
+        assert_eq!(tick(0, false, true, false), 2, "inc");
+        assert_eq!(tick(0, false, false, false), 2, "keep");
+        assert_eq!(tick(100, true, true, false), 100, "load wins over inc");
+        assert_eq!(tick(0, false, true, false), 101, "inc after load");
+        assert_eq!(tick(100, true, true, true), 0, "reset wins over load");
+    }
 }
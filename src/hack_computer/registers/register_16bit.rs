@@ -64,7 +64,13 @@ impl Register16Bit {
         return self.feedback_out;
     }
 
-    pub fn get_debug_info(&mut self) -> [bool; 16] {
+    /// Stores the value with one full clock cycle.
+    pub fn set_debug_info(&mut self, value: [bool; 16]) {
+        self.register_16bit_clocked(value, true, false);
+        self.register_16bit_clocked(value, true, true);
+    }
+
+    pub fn get_debug_info(&self) -> [bool; 16] {
         let mut res = [false; 16];
         for i in 0..16 {
             res[i] = self.child_circuits[i].current_value;
@@ -94,6 +100,13 @@ pub mod test {
         clock = true;
         output = register.register_16bit_clocked(input, load, clock);
 
-        assert_eq!(output, [false; 16], "tick tock 2");
+        assert_eq!(output, input, "tick tock 2");
+
+        // without load, the value survives the next clock cycle
+        register.register_16bit_clocked([false; 16], false, false);
+        output = register.register_16bit_clocked([false; 16], false, true);
+
+        assert_eq!(output, input, "tick tock 3");
+        assert_eq!(register.get_debug_info(), input);
     }
 }
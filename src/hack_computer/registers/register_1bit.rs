@@ -31,13 +31,30 @@ impl Register1Bit {
         // (uninuitive), use not(clock) as store indicator for the first latch
         // you could think this latch as current event,
         // that holds the bit either from input or from previous state
-        let (queue_out, _) = self.child_circuit[0].d_latch(selected_data, not(clock));
+        let queue_out = Self::settle(&mut self.child_circuit[0], selected_data, not(clock));
 
         // gets the bit either from "queue" or from the clock pulse.
-        let (out, _) = self.child_circuit[1].d_latch(queue_out, clock);
+        let out = Self::settle(&mut self.child_circuit[1], queue_out, clock);
+
+        // the output is fed back to the mux on the next call
+        self.current_value = out;
 
         out
     }
+
+    // One call of the latch is one pass through the NAND gates.
+    // In the real circuit the feedback loop runs until the outputs are stable,
+    // so the latch is called again until nothing changes.
+    fn settle(latch: &mut Latch, data: bool, store: bool) -> bool {
+        let mut prev = (latch.prev_q_high, latch.prev_q_low);
+        loop {
+            let next = latch.d_latch(data, store);
+            if next == prev {
+                return next.0;
+            }
+            prev = next;
+        }
+    }
 }
 
 mod test {
@@ -104,31 +121,51 @@ mod test {
                 data: true,
                 clock: true,
                 store: true,
-                expect: false,
+                expect: true,
                 test_name: "test 7 - clock triggered",
             },
             TestCase {
                 data: true,
                 clock: false,
                 store: true,
-                expect: false,
+                expect: true,
                 test_name: "test 7.1 - clock untriggered",
             },
             TestCase {
                 data: true,
                 clock: false,
                 store: true,
-                expect: false,
-                // note that the state change depnds of low_q:s of other circuits
-                test_name: "test 8 - TODO: Verify that is this correct",
+                expect: true,
+                test_name: "test 8 - value is kept while the clock is off",
             },
             TestCase {
                 data: false,
                 clock: false,
                 store: false,
-                expect: false,
+                expect: true,
                 test_name: "test 9 - get old value",
             },
+            TestCase {
+                data: false,
+                clock: true,
+                store: false,
+                expect: true,
+                test_name: "test 10 - clock triggered without store keeps the old value",
+            },
+            TestCase {
+                data: false,
+                clock: false,
+                store: true,
+                expect: true,
+                test_name: "test 11 - store new value for the next clock cycle",
+            },
+            TestCase {
+                data: false,
+                clock: true,
+                store: true,
+                expect: false,
+                test_name: "test 12 - clock triggered",
+            },
         ];
 
         for test in test_cases {
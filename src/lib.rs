@@ -4,4 +4,5 @@ pub mod assembler;
 pub mod emulated_parts;
 pub mod gui;
 pub mod hack_computer;
+pub mod machine;
 pub mod utils;
@@ -0,0 +1,451 @@
+// Configuration of the computer.
+//
+// Every component can be simulated either from gates (`hack_computer`) or on words (`emulated_parts`).
+// Mixing them keeps the interesting part visible at gate level, while the rest stays fast.
+//
+// The memory map can be changed for variant exercises, e.g. more RAM or a smaller screen.
+// Only the emulated computer follows it, the gates are wired for the Hack memory map.
+//
+// With bank switching, the upper half of the ROM and the upper half of the RAM are windows:
+// two registers at the end of the data address space select, which bank they show.
+// The lower halves are always the same, the code that switches the ROM bank must run there.
+// The program is one long image: bank b of the ROM window starts at rom_size / 2 * (b + 1).
+// Return addresses do not hold a bank: `RETI` and `RET` jump into the bank that is selected then,
+// so an interrupt handler or a routine, that switches the ROM bank, switches it back before it returns.
+//
+// In the Von Neumann mode, the instruction addresses after the ROM fetch from the data memory,
+// at the same address. A loader in the ROM copies the program into the RAM, and jumps to it.
+// The program can also write its own instructions.
+//
+// Extensions add features to the CPU, that the Hack computer from the book does not have.
+// Both computers support them, and without them they behave exactly like the book describes.
+//
+// Real memory does not start with zeros. With a power-on seed, the registers and the memories
+// start with garbage: A, D and SP first, then the RAM from address 0, then the screen,
+// then the RAM banks after the first one. The same seed gives the same garbage in both computers.
+
+use crate::utils::random::Random;
+
+use super::{
+    device::ADDRESS_SPACE,
+    framebuffer::{SCREEN_HEIGHT, SCREEN_WIDTH},
+    KEYBOARD_ADDRESS, ROM_SIZE, SCREEN_ADDRESS,
+};
+
+/// How a component is simulated.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum Fidelity {
+    /// Built from logic gates.
+    #[default]
+    Gate,
+
+    /// Calculated on words.
+    Emulated,
+}
+
+impl Fidelity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Fidelity::Gate => "Gate",
+            Fidelity::Emulated => "Emulated",
+        }
+    }
+}
+
+/// Fidelity of each component of the computer. By default everything is built from gates.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct MachineConfig {
+    pub alu: Fidelity,
+
+    /// A and D registers
+    pub registers: Fidelity,
+
+    /// program counter
+    pub pc: Fidelity,
+    pub ram: Fidelity,
+    pub screen: Fidelity,
+    pub keyboard: Fidelity,
+
+    pub memory: MemoryMap,
+
+    pub extensions: Extensions,
+
+    /// Fills the registers and the memories with garbage from this seed at power on.
+    /// `None` starts with zeros.
+    #[serde(default)]
+    pub power_on_seed: Option<u64>,
+}
+
+impl MachineConfig {
+    /// Same fidelity for every component.
+    pub fn all(fidelity: Fidelity) -> Self {
+        Self {
+            alu: fidelity,
+            registers: fidelity,
+            pc: fidelity,
+            ram: fidelity,
+            screen: fidelity,
+            keyboard: fidelity,
+            memory: MemoryMap::default(),
+            extensions: Extensions::default(),
+            power_on_seed: None,
+        }
+    }
+
+    /// The words, that the registers and the memories have at power on, in the order above.
+    pub fn power_on_values(&self) -> impl FnMut() -> i16 {
+        let mut random = self.power_on_seed.map(Random::new);
+        move || random.as_mut().map_or(0, Random::next_i16)
+    }
+
+    /// Components by name, for listing them in the GUI.
+    pub fn components_mut(&mut self) -> [(&'static str, &mut Fidelity); 6] {
+        [
+            ("ALU", &mut self.alu),
+            ("Registers", &mut self.registers),
+            ("PC", &mut self.pc),
+            ("RAM", &mut self.ram),
+            ("Screen", &mut self.screen),
+            ("Keyboard", &mut self.keyboard),
+        ]
+    }
+}
+
+/// Optional features of the CPU. By default there are none.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct Extensions {
+    /// ROM address of the interrupt handler. `None` disables the interrupts.
+    ///
+    /// A request from the keyboard or a device is taken at the end of the current instruction:
+    /// the address of the next instruction goes into a shadow register, and the CPU jumps here.
+    /// `RETI` jumps back. Requests wait, while the handler runs.
+    pub interrupt_vector: Option<u16>,
+
+    /// Shifts by one bit: `D<<`, `A>>`, `M<<` and so on, see `instruction::SHIFT_TABLE`.
+    /// Multiplying with shift and add takes 16 rounds instead of up to 32767 additions.
+    pub shifts: bool,
+
+    /// Stack pointer register, with `PUSH`, `POP`, `CALL`, `RET`, `SP=` and `=SP`,
+    /// see `instruction`. The stack grows upwards, like the one of the VM.
+    /// SP is 0 at power on, so the program sets it first, e.g. `@256 D=A SP=D`.
+    pub stack: bool,
+}
+
+impl Extensions {
+    pub fn interrupts(&self) -> bool {
+        self.interrupt_vector.is_some()
+    }
+
+    pub fn validate(&self, memory_map: &MemoryMap) -> Result<(), String> {
+        match self.interrupt_vector {
+            Some(vector) if vector as usize >= memory_map.rom_size => Err(format!(
+                "The interrupt vector {} is outside of the ROM",
+                vector
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Part of the data memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    Ram,
+    Screen,
+    Keyboard,
+
+    /// ROM and RAM bank registers, with bank switching
+    BankSelect,
+
+    /// free for devices
+    Unmapped,
+}
+
+/// ROM bank register, RAM bank register at the next address.
+/// The banks are numbered from 0, and a number after the last bank wraps around.
+pub const BANK_SELECT_ADDRESS: usize = ADDRESS_SPACE - 2;
+
+/// Sizes and addresses of the memories. The default is the Hack memory map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct MemoryMap {
+    /// RAM[0..ram_size)
+    pub ram_size: usize,
+
+    /// first word of the screen
+    pub screen_address: usize,
+
+    /// in pixels, a multiple of 16
+    pub screen_width: usize,
+    pub screen_height: usize,
+
+    pub keyboard_address: usize,
+
+    /// number of words in the instruction memory
+    pub rom_size: usize,
+
+    /// number of banks in the upper half of the ROM and of the RAM, 1 without bank switching
+    pub rom_banks: usize,
+    pub ram_banks: usize,
+
+    /// Von Neumann mode: the instructions from `rom_size` on are fetched from the data memory
+    pub execute_ram: bool,
+}
+
+impl Default for MemoryMap {
+    fn default() -> Self {
+        Self {
+            ram_size: SCREEN_ADDRESS,
+            screen_address: SCREEN_ADDRESS,
+            screen_width: SCREEN_WIDTH,
+            screen_height: SCREEN_HEIGHT,
+            keyboard_address: KEYBOARD_ADDRESS,
+            rom_size: ROM_SIZE,
+            rom_banks: 1,
+            ram_banks: 1,
+            execute_ram: false,
+        }
+    }
+}
+
+impl MemoryMap {
+    /// Number of words in the screen memory map.
+    pub fn screen_words(&self) -> usize {
+        self.screen_width * self.screen_height / 16
+    }
+
+    /// Number of words up to the last one of RAM, screen or keyboard.
+    pub fn size(&self) -> usize {
+        self.ram_size
+            .max(self.screen_address + self.screen_words())
+            .max(self.keyboard_address + 1)
+    }
+
+    pub fn is_banked(&self) -> bool {
+        self.rom_banks > 1 || self.ram_banks > 1
+    }
+
+    /// First address of the ROM window. The words before it are never switched.
+    pub fn rom_window(&self) -> usize {
+        self.rom_size / 2
+    }
+
+    /// First address of the RAM window.
+    pub fn ram_window(&self) -> usize {
+        self.ram_size / 2
+    }
+
+    /// Number of words in all the ROM banks, the longest program.
+    pub fn rom_capacity(&self) -> usize {
+        self.rom_window() + (self.rom_size - self.rom_window()) * self.rom_banks
+    }
+
+    /// Position in the program of the ROM address, when the bank is selected.
+    pub fn rom_image_address(&self, bank: usize, address: usize) -> usize {
+        if address < self.rom_window() {
+            address
+        } else {
+            address + (bank % self.rom_banks) * (self.rom_size - self.rom_window())
+        }
+    }
+
+    /// ROM bank and address of a position in the program, the inverse of `rom_image_address`.
+    /// The words before the window are in every bank, they are given as bank 0.
+    pub fn rom_bank_address(&self, image_address: usize) -> (usize, usize) {
+        let window = self.rom_window();
+        if image_address < window {
+            return (0, image_address);
+        }
+
+        let length = self.rom_size - window;
+        (
+            (image_address - window) / length,
+            window + (image_address - window) % length,
+        )
+    }
+
+    /// Whether the instruction at the address is fetched from the data memory.
+    pub fn fetches_from_ram(&self, address: usize) -> bool {
+        self.execute_ram && address >= self.rom_size
+    }
+
+    pub fn region(&self, address: usize) -> Region {
+        if self.is_banked() && address >= BANK_SELECT_ADDRESS {
+            Region::BankSelect
+        } else if address < self.ram_size {
+            Region::Ram
+        } else if (self.screen_address..self.screen_address + self.screen_words())
+            .contains(&address)
+        {
+            Region::Screen
+        } else if address == self.keyboard_address {
+            Region::Keyboard
+        } else {
+            Region::Unmapped
+        }
+    }
+
+    /// Everything must fit into the 15-bit address spaces, and the regions must not overlap.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.ram_size < 16 {
+            return Err("RAM must have at least 16 words for R0..R15".to_owned());
+        }
+        if self.screen_width == 0 || self.screen_width % 16 != 0 || self.screen_height == 0 {
+            return Err(format!(
+                "Screen {} x {}: the width must be a positive multiple of 16, the height positive",
+                self.screen_width, self.screen_height
+            ));
+        }
+        if !(1..=ROM_SIZE).contains(&self.rom_size) {
+            return Err(format!("ROM size must be 1..={}", ROM_SIZE));
+        }
+        if !(1..=256).contains(&self.rom_banks) || !(1..=256).contains(&self.ram_banks) {
+            return Err("There must be 1..=256 ROM banks and RAM banks".to_owned());
+        }
+        if self.execute_ram && (self.is_banked() || self.rom_size >= self.ram_size) {
+            return Err(
+                "Executing from RAM needs a ROM smaller than the RAM, and no bank switching"
+                    .to_owned(),
+            );
+        }
+        if self.is_banked() && self.size() > BANK_SELECT_ADDRESS {
+            return Err(format!(
+                "The bank registers at {} overlap the memory map",
+                BANK_SELECT_ADDRESS
+            ));
+        }
+        if self.size() > ADDRESS_SPACE {
+            return Err(format!(
+                "The memory map needs {} words, but the address space has {}",
+                self.size(),
+                ADDRESS_SPACE
+            ));
+        }
+
+        let screen = self.screen_address..self.screen_address + self.screen_words();
+        if self.screen_address < self.ram_size {
+            return Err("The screen overlaps the RAM".to_owned());
+        }
+        if self.keyboard_address < self.ram_size || screen.contains(&self.keyboard_address) {
+            return Err("The keyboard overlaps the RAM or the screen".to_owned());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn test_memory_map() {
+        use super::*;
+
+        let hack = MemoryMap::default();
+        assert_eq!(hack.validate(), Ok(()));
+        assert_eq!(hack.screen_words(), 8192);
+        assert_eq!(hack.size(), 24577);
+        assert_eq!(hack.region(16383), Region::Ram);
+        assert_eq!(hack.region(24575), Region::Screen);
+        assert_eq!(hack.region(24576), Region::Keyboard);
+        assert_eq!(hack.region(24577), Region::Unmapped);
+
+        // more RAM, and a 128 x 64 screen right after it
+        let small_screen = MemoryMap {
+            ram_size: 20000,
+            screen_address: 20000,
+            screen_width: 128,
+            screen_height: 64,
+            keyboard_address: 20512,
+            rom_size: 1024,
+            ..hack
+        };
+        assert_eq!(small_screen.validate(), Ok(()));
+        assert_eq!(small_screen.region(20511), Region::Screen);
+        assert_eq!(small_screen.region(20513), Region::Unmapped);
+
+        assert!(MemoryMap {
+            screen_address: 16000,
+            ..hack
+        }
+        .validate()
+        .is_err());
+        assert!(MemoryMap {
+            keyboard_address: 30000,
+            screen_width: 1000,
+            ..hack
+        }
+        .validate()
+        .is_err());
+        assert!(MemoryMap {
+            keyboard_address: 32768,
+            ..hack
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn test_memory_map_banks() {
+        use super::*;
+
+        let banked = MemoryMap {
+            rom_banks: 4,
+            ram_banks: 2,
+            ..MemoryMap::default()
+        };
+        assert_eq!(banked.validate(), Ok(()));
+        assert_eq!(banked.rom_capacity(), 16384 * 5);
+        assert_eq!(banked.rom_image_address(3, 100), 100);
+        assert_eq!(banked.rom_image_address(0, 16384), 16384);
+        assert_eq!(banked.rom_image_address(3, 16384), 65536);
+        assert_eq!(banked.rom_image_address(5, 16385), 16384 * 2 + 1);
+        assert_eq!(banked.rom_bank_address(100), (0, 100));
+        assert_eq!(banked.rom_bank_address(65536), (3, 16384));
+        assert_eq!(banked.rom_bank_address(16384 * 3 - 1), (1, 32767));
+        assert_eq!(banked.region(32765), Region::Unmapped);
+        assert_eq!(banked.region(32766), Region::BankSelect);
+        assert_eq!(MemoryMap::default().region(32767), Region::Unmapped);
+
+        assert!(MemoryMap {
+            rom_banks: 0,
+            ..banked
+        }
+        .validate()
+        .is_err());
+        assert!(MemoryMap {
+            keyboard_address: 32767,
+            ..banked
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn test_memory_map_execute_ram() {
+        use super::*;
+
+        let von_neumann = MemoryMap {
+            rom_size: 1024,
+            execute_ram: true,
+            ..MemoryMap::default()
+        };
+        assert_eq!(von_neumann.validate(), Ok(()));
+        assert!(!von_neumann.fetches_from_ram(1023));
+        assert!(von_neumann.fetches_from_ram(1024));
+        assert!(!MemoryMap::default().fetches_from_ram(1024));
+
+        // nothing left to execute in the RAM
+        assert!(MemoryMap {
+            rom_size: 16384,
+            ..von_neumann
+        }
+        .validate()
+        .is_err());
+        assert!(MemoryMap {
+            rom_banks: 2,
+            ..von_neumann
+        }
+        .validate()
+        .is_err());
+    }
+}
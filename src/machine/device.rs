@@ -0,0 +1,234 @@
+// Memory-mapped devices
+//
+// The Hack memory map ends with the keyboard register at 24576,
+// the addresses 24577..32767 are free. Extra peripherals can be attached there
+// without changing `Memory` or `ComputerEmulated`: both of them ask their `DeviceMap`
+// for the addresses, that are not RAM, screen or keyboard.
+// With another `MemoryMap`, the devices can use any address outside of its regions.
+
+use std::{cell::RefCell, rc::Rc};
+
+use super::config::{MemoryMap, Region};
+
+/// Size of the data address space. The data address bus is 15 bits wide.
+pub const ADDRESS_SPACE: usize = 32768;
+
+/// A peripheral, that occupies `size()` words of the data memory.
+/// The offsets are relative to the address, where the device is attached.
+pub trait Device {
+    fn size(&self) -> usize;
+
+    /// Reads a word. It must not change the device: the gate-level computer reads
+    /// its memory many times during one clock cycle, while the buses settle.
+    fn read(&self, offset: usize) -> i16;
+
+    /// Writes a word. Called once per instruction, that writes into the device.
+    fn write(&mut self, offset: usize, value: i16);
+
+    /// The CPU has taken the word at `offset`: called once per instruction, that reads M
+    /// from the device, before `tick`. Unlike `read`, it may change the device.
+    fn read_strobe(&mut self, _offset: usize) {}
+
+    /// Called once at the end of every clock cycle.
+    fn tick(&mut self) {}
+
+    /// Whether the device has requested an interrupt since the last call. Called after `tick`.
+    /// Only a CPU with the interrupt extension reacts to it.
+    fn take_interrupt(&mut self) -> bool {
+        false
+    }
+
+    /// Whether the device may still request an interrupt, e.g. a timer with a running countdown.
+    /// A program in its end loop, that waits for interrupts, is halted when no device may.
+    fn may_interrupt(&self) -> bool {
+        false
+    }
+}
+
+/// Lets the host keep a handle to a device, that is attached to a machine.
+impl<D: Device> Device for Rc<RefCell<D>> {
+    fn size(&self) -> usize {
+        self.borrow().size()
+    }
+
+    fn read(&self, offset: usize) -> i16 {
+        self.borrow().read(offset)
+    }
+
+    fn write(&mut self, offset: usize, value: i16) {
+        self.borrow_mut().write(offset, value);
+    }
+
+    fn read_strobe(&mut self, offset: usize) {
+        self.borrow_mut().read_strobe(offset);
+    }
+
+    fn tick(&mut self) {
+        self.borrow_mut().tick();
+    }
+
+    fn take_interrupt(&mut self) -> bool {
+        self.borrow_mut().take_interrupt()
+    }
+
+    fn may_interrupt(&self) -> bool {
+        self.borrow().may_interrupt()
+    }
+}
+
+/// Devices by their base address.
+#[derive(Default)]
+pub struct DeviceMap {
+    /// RAM, screen and keyboard, where the devices cannot be attached
+    memory_map: MemoryMap,
+    devices: Vec<(usize, Box<dyn Device>)>,
+}
+
+impl DeviceMap {
+    pub fn new(memory_map: MemoryMap) -> Self {
+        Self {
+            memory_map,
+            devices: Vec::new(),
+        }
+    }
+
+    /// Attaches the device at `base..base + size`.
+    /// The range must be in the unmapped addresses, and must not overlap another device.
+    pub fn attach(&mut self, base: usize, device: Box<dyn Device>) -> Result<(), String> {
+        let end = base + device.size();
+        if end > ADDRESS_SPACE
+            || (base..end).any(|address| self.memory_map.region(address) != Region::Unmapped)
+        {
+            return Err(format!(
+                "Device at {}..{} is outside of the unmapped addresses",
+                base, end
+            ));
+        }
+
+        if let Some((other, _)) = self
+            .devices
+            .iter()
+            .find(|(other, device)| base < other + device.size() && *other < end)
+        {
+            return Err(format!(
+                "Device at {}..{} overlaps the device at {}",
+                base, end, other
+            ));
+        }
+
+        self.devices.push((base, device));
+        Ok(())
+    }
+
+    fn find(&self, address: usize) -> Option<usize> {
+        self.devices
+            .iter()
+            .position(|(base, device)| (*base..base + device.size()).contains(&address))
+    }
+
+    /// `None` when no device is attached at the address.
+    pub fn read(&self, address: usize) -> Option<i16> {
+        let index = self.find(address)?;
+        let (base, device) = &self.devices[index];
+
+        Some(device.read(address - base))
+    }
+
+    /// Returns false when no device is attached at the address.
+    pub fn write(&mut self, address: usize, value: i16) -> bool {
+        match self.find(address) {
+            Some(index) => {
+                let (base, device) = &mut self.devices[index];
+                device.write(address - *base, value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Tells the device at the address, that the CPU has read it. See `Device::read_strobe`.
+    pub fn read_strobe(&mut self, address: usize) {
+        if let Some(index) = self.find(address) {
+            let (base, device) = &mut self.devices[index];
+            device.read_strobe(address - *base);
+        }
+    }
+
+    /// Ticks every device. Returns true, when any of them requests an interrupt.
+    pub fn tick(&mut self) -> bool {
+        let mut interrupt = false;
+        for (_, device) in self.devices.iter_mut() {
+            device.tick();
+            interrupt |= device.take_interrupt();
+        }
+
+        interrupt
+    }
+
+    /// Whether any device may still request an interrupt. See `Device::may_interrupt`.
+    pub fn may_interrupt(&self) -> bool {
+        self.devices
+            .iter()
+            .any(|(_, device)| device.may_interrupt())
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    /// Counts the clock cycles, and remembers the last written word and the read words.
+    #[derive(Default)]
+    pub struct TestDevice {
+        pub cycles: i16,
+        pub last_write: Option<(usize, i16)>,
+        pub strobes: Vec<usize>,
+    }
+
+    impl Device for TestDevice {
+        fn size(&self) -> usize {
+            2
+        }
+
+        fn read(&self, offset: usize) -> i16 {
+            match offset {
+                0 => self.cycles,
+                _ => self.last_write.map_or(0, |(_, value)| value),
+            }
+        }
+
+        fn write(&mut self, offset: usize, value: i16) {
+            self.last_write = Some((offset, value));
+        }
+
+        fn read_strobe(&mut self, offset: usize) {
+            self.strobes.push(offset);
+        }
+
+        fn tick(&mut self) {
+            self.cycles += 1;
+        }
+    }
+
+    #[test]
+    fn test_device_map() {
+        let mut devices = DeviceMap::default();
+        assert!(devices.attach(24576, Box::<TestDevice>::default()).is_err());
+        assert!(devices.attach(32767, Box::<TestDevice>::default()).is_err());
+        assert!(devices.attach(24577, Box::<TestDevice>::default()).is_ok());
+        assert!(devices.attach(24578, Box::<TestDevice>::default()).is_err());
+
+        let device = Rc::new(RefCell::new(TestDevice::default()));
+        assert!(devices.attach(30000, Box::new(device.clone())).is_ok());
+
+        assert!(devices.write(30001, 7));
+        assert!(!devices.write(30002, 7));
+        devices.tick();
+
+        assert_eq!(device.borrow().last_write, Some((1, 7)));
+        assert_eq!(devices.read(30000), Some(1));
+        assert_eq!(devices.read(30001), Some(7));
+        assert_eq!(devices.read(24577), Some(1));
+        assert_eq!(devices.read(24579), None);
+    }
+}
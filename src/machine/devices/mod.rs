@@ -0,0 +1,10 @@
+// Peripherals for the free addresses after the keyboard. See `machine::device`.
+//
+// Each device has a default address, so that Hack programs can find it,
+// and the host attaches it with `Machine::attach_device`.
+pub mod multiplier;
+pub mod sound;
+pub mod tape;
+pub mod test_port;
+pub mod timer;
+pub mod tty;
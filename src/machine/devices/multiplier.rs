@@ -0,0 +1,291 @@
+// Multiply/divide coprocessor
+//
+// Multiplies and divides signed 16-bit numbers, while the CPU goes on with other instructions.
+// Write x and then y: writing y starts the calculation. After `latency` cycles the results
+// are valid, until then the status is busy, and the result registers keep the previous values.
+//
+// Words, relative to the base address:
+//   0  x
+//   1  y, writing it starts the calculation
+//   2  status: bit 0 busy, bit 1 the last division was by zero (read only)
+//   3  product, the low 16 bits (read only)
+//   4  quotient, truncated towards zero (read only)
+//   5  remainder, with the sign of x (read only)
+//
+// Division by zero gives the quotient 0 and the remainder x.
+//
+// The behavioural version calculates with Rust integers. The gate-level one runs the
+// shift-and-add datapath of `hack_computer::chips::multiplier`, as many steps per cycle
+// as it needs to finish in time. Both give the same results after the same number of cycles.
+
+use crate::{
+    hack_computer::chips::multiplier::{MultiplierDivider, STEPS},
+    machine::{config::Fidelity, device::Device},
+    utils::bit_manipulation::{bits_from_i16, i16_from_bits},
+};
+
+/// Default base address: after the tone generator.
+pub const MULTIPLIER_ADDRESS: usize = 24590;
+
+pub const X: usize = 0;
+pub const Y: usize = 1;
+pub const STATUS: usize = 2;
+pub const PRODUCT: usize = 3;
+pub const QUOTIENT: usize = 4;
+pub const REMAINDER: usize = 5;
+
+pub const STATUS_BUSY: i16 = 0b01;
+pub const STATUS_DIVIDE_BY_ZERO: i16 = 0b10;
+
+/// One bit per cycle, like a simple sequential multiplier.
+pub const DEFAULT_LATENCY: usize = STEPS;
+
+pub struct Multiplier {
+    fidelity: Fidelity,
+    latency: usize,
+
+    x: i16,
+    y: i16,
+
+    /// cycles until the results are valid, 0 when idle
+    remaining: usize,
+
+    /// product, quotient and remainder, that the program can read
+    results: [i16; 3],
+    divide_by_zero: bool,
+
+    /// behavioural: the results, that become valid when the time is up
+    pending: [i16; 3],
+
+    /// gate level
+    datapath: MultiplierDivider,
+}
+
+impl Multiplier {
+    /// `latency` 0 makes the results valid right after writing y.
+    pub fn new(fidelity: Fidelity, latency: usize) -> Self {
+        Self {
+            fidelity,
+            latency,
+            x: 0,
+            y: 0,
+            remaining: 0,
+            results: [0; 3],
+            divide_by_zero: false,
+            pending: [0; 3],
+            datapath: MultiplierDivider::default(),
+        }
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.remaining > 0
+    }
+
+    /// Datapath steps per cycle, so that all 16 steps fit into the latency.
+    fn steps_per_cycle(&self) -> usize {
+        match self.latency {
+            0 => STEPS,
+            latency => (STEPS + latency - 1) / latency,
+        }
+    }
+
+    fn start(&mut self) {
+        match self.fidelity {
+            Fidelity::Emulated => {
+                self.pending = match self.y {
+                    0 => [0, 0, self.x],
+                    y => [
+                        self.x.wrapping_mul(y),
+                        self.x.wrapping_div(y),
+                        self.x.wrapping_rem(y),
+                    ],
+                };
+            }
+            Fidelity::Gate => self
+                .datapath
+                .start(bits_from_i16(self.x), bits_from_i16(self.y)),
+        }
+
+        self.remaining = self.latency;
+        if self.latency == 0 {
+            self.run_datapath();
+            self.finish();
+        }
+    }
+
+    fn run_datapath(&mut self) {
+        if self.fidelity == Fidelity::Gate {
+            for _ in 0..self.steps_per_cycle() {
+                self.datapath.step();
+            }
+        }
+    }
+
+    fn finish(&mut self) {
+        self.results = match self.fidelity {
+            Fidelity::Emulated => self.pending,
+            Fidelity::Gate => {
+                let (product, quotient, remainder) = self.datapath.results();
+                [
+                    i16_from_bits(product),
+                    i16_from_bits(quotient),
+                    i16_from_bits(remainder),
+                ]
+            }
+        };
+        self.divide_by_zero = self.y == 0;
+    }
+}
+
+impl Default for Multiplier {
+    fn default() -> Self {
+        Self::new(Fidelity::Emulated, DEFAULT_LATENCY)
+    }
+}
+
+impl Device for Multiplier {
+    fn size(&self) -> usize {
+        6
+    }
+
+    fn read(&self, offset: usize) -> i16 {
+        match offset {
+            X => self.x,
+            Y => self.y,
+            STATUS => {
+                let mut status = 0;
+                if self.is_busy() {
+                    status |= STATUS_BUSY;
+                }
+                if self.divide_by_zero {
+                    status |= STATUS_DIVIDE_BY_ZERO;
+                }
+                status
+            }
+            PRODUCT => self.results[0],
+            QUOTIENT => self.results[1],
+            REMAINDER => self.results[2],
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, offset: usize, value: i16) {
+        match offset {
+            X => self.x = value,
+            Y => {
+                self.y = value;
+                self.start();
+            }
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self) {
+        if self.remaining == 0 {
+            return;
+        }
+
+        self.run_datapath();
+        self.remaining -= 1;
+        if self.remaining == 0 {
+            self.finish();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::random::Random;
+
+    /// Cycles until the results are valid, and the results.
+    fn calculate(multiplier: &mut Multiplier, x: i16, y: i16) -> (usize, [i16; 4]) {
+        multiplier.write(X, x);
+        multiplier.write(Y, y);
+
+        let mut cycles = 0;
+        while multiplier.read(STATUS) & STATUS_BUSY != 0 {
+            multiplier.tick();
+            cycles += 1;
+        }
+
+        let results = [STATUS, PRODUCT, QUOTIENT, REMAINDER].map(|offset| multiplier.read(offset));
+        (cycles, results)
+    }
+
+    #[test]
+    fn test_multiplier_latency() {
+        for fidelity in [Fidelity::Emulated, Fidelity::Gate] {
+            for latency in [0, 1, 5, 16, 40] {
+                let mut multiplier = Multiplier::new(fidelity, latency);
+                assert_eq!(
+                    calculate(&mut multiplier, 100, -7),
+                    (latency, [0, -700, -14, 2]),
+                    "{:?}, latency {}",
+                    fidelity,
+                    latency
+                );
+                assert_eq!(
+                    calculate(&mut multiplier, 9, 0),
+                    (latency, [STATUS_DIVIDE_BY_ZERO, 0, 0, 9])
+                );
+            }
+        }
+
+        // the previous results stay, until the new ones are ready
+        let mut multiplier = Multiplier::default();
+        calculate(&mut multiplier, 6, 7);
+        multiplier.write(Y, 8);
+        multiplier.tick();
+        assert_eq!(multiplier.read(STATUS), STATUS_BUSY);
+        assert_eq!(multiplier.read(PRODUCT), 42);
+    }
+
+    #[test]
+    fn test_multiplier_implementations_agree() {
+        let mut behavioural = Multiplier::new(Fidelity::Emulated, 3);
+        let mut gate_level = Multiplier::new(Fidelity::Gate, 3);
+
+        let mut random = Random::new(4343);
+        for _ in 0..100 {
+            let (x, y) = (random.next_i16(), random.next_i16() >> random.below(16));
+            assert_eq!(
+                calculate(&mut behavioural, x, y),
+                calculate(&mut gate_level, x, y),
+                "{} and {}",
+                x,
+                y
+            );
+        }
+    }
+
+    #[test]
+    fn test_multiplier_in_machine() {
+        use crate::{
+            assembler::asm_to_binary,
+            machine::{power_on, Backend},
+        };
+        use std::{cell::RefCell, rc::Rc};
+
+        // R0 = 1234 * 5, R1 = 1234 / 5, R2 = 1234 % 5
+        let program = asm_to_binary(
+            "@1234\nD=A\n@24590\nM=D\n@5\nD=A\n@24591\nM=D\n\
+             (WAIT)\n@24592\nD=M\n@WAIT\nD;JNE\n\
+             @24593\nD=M\n@R0\nM=D\n@24594\nD=M\n@R1\nM=D\n@24595\nD=M\n@R2\nM=D\n\
+             (END)\n@END\n0;JMP",
+        )
+        .unwrap();
+
+        for backend in [Backend::GateLevel, Backend::Emulated] {
+            let multiplier = Rc::new(RefCell::new(Multiplier::new(Fidelity::Gate, 8)));
+            let mut machine = power_on(backend, program.clone());
+            machine
+                .attach_device(MULTIPLIER_ADDRESS, Box::new(multiplier.clone()))
+                .unwrap();
+            machine.run(50);
+
+            let results: Vec<i16> = (0..3).map(|address| machine.read_memory(address)).collect();
+            assert_eq!(results, vec![6170, 246, 4], "{}", backend.name());
+        }
+    }
+}
@@ -0,0 +1,270 @@
+// Tone generator
+//
+// A square wave and a noise channel, mixed into 16-bit mono PCM samples.
+// The samples are made at a fixed rate relative to the clock cycles,
+// so the same program always produces the same buffer.
+//
+// Words, relative to the base address:
+//   0  square wave frequency in Hz, 0 is silent
+//   1  square wave volume, 0..=255
+//   2  noise frequency in Hz: how often the noise changes, 0 is silent
+//   3  noise volume, 0..=255
+
+use crate::machine::device::Device;
+
+/// Default base address: after the test port.
+pub const SOUND_ADDRESS: usize = 24586;
+
+pub const SQUARE_FREQUENCY: usize = 0;
+pub const SQUARE_VOLUME: usize = 1;
+pub const NOISE_FREQUENCY: usize = 2;
+pub const NOISE_VOLUME: usize = 3;
+
+pub const DEFAULT_SAMPLE_RATE: u32 = 8000;
+
+/// Same clock as the timer: 1 MHz.
+pub const DEFAULT_CYCLES_PER_SECOND: u64 = 1_000_000;
+
+/// Amplitude of one channel at volume 1. Two channels at full volume still fit into i16.
+const VOLUME_STEP: i32 = 64;
+
+/// Oscillator with a 32-bit phase, that counts the wraps.
+#[derive(Debug, Default, Clone, Copy)]
+struct Phase {
+    phase: u64,
+}
+
+impl Phase {
+    /// Advances by one sample, and returns the number of full periods.
+    fn advance(&mut self, frequency: u16, sample_rate: u32) -> u64 {
+        self.phase += ((frequency as u64) << 32) / sample_rate as u64;
+        let wraps = self.phase >> 32;
+        self.phase &= 0xFFFF_FFFF;
+
+        wraps
+    }
+
+    fn first_half(&self) -> bool {
+        self.phase < 1 << 31
+    }
+}
+
+pub struct Sound {
+    sample_rate: u32,
+    cycles_per_second: u64,
+
+    /// counts up by the sample rate every cycle, a sample is due at `cycles_per_second`
+    sample_clock: u64,
+
+    registers: [i16; 4],
+    square: Phase,
+    noise: Phase,
+
+    /// 15-bit linear feedback shift register
+    lfsr: u16,
+
+    samples: Vec<i16>,
+}
+
+impl Sound {
+    pub fn new(sample_rate: u32, cycles_per_second: u64) -> Self {
+        Self {
+            sample_rate: sample_rate.max(1),
+            cycles_per_second: cycles_per_second.max(1),
+            sample_clock: 0,
+            registers: [0; 4],
+            square: Phase::default(),
+            noise: Phase::default(),
+            lfsr: 1,
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Samples made since the last call.
+    pub fn take_samples(&mut self) -> Vec<i16> {
+        std::mem::take(&mut self.samples)
+    }
+
+    pub fn samples(&self) -> &[i16] {
+        &self.samples
+    }
+
+    fn volume(&self, register: usize) -> i32 {
+        (self.registers[register] as u16).min(255) as i32 * VOLUME_STEP
+    }
+
+    fn next_sample(&mut self) -> i16 {
+        let mut sample = 0;
+
+        let frequency = self.registers[SQUARE_FREQUENCY] as u16;
+        self.square.advance(frequency, self.sample_rate);
+        if frequency != 0 {
+            let volume = self.volume(SQUARE_VOLUME);
+            sample += if self.square.first_half() {
+                volume
+            } else {
+                -volume
+            };
+        }
+
+        let frequency = self.registers[NOISE_FREQUENCY] as u16;
+        for _ in 0..self.noise.advance(frequency, self.sample_rate) {
+            let feedback = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+            self.lfsr = (self.lfsr >> 1) | (feedback << 14);
+        }
+        if frequency != 0 {
+            let volume = self.volume(NOISE_VOLUME);
+            sample += if self.lfsr & 1 == 1 { volume } else { -volume };
+        }
+
+        sample as i16
+    }
+}
+
+impl Default for Sound {
+    fn default() -> Self {
+        Self::new(DEFAULT_SAMPLE_RATE, DEFAULT_CYCLES_PER_SECOND)
+    }
+}
+
+impl Device for Sound {
+    fn size(&self) -> usize {
+        4
+    }
+
+    fn read(&self, offset: usize) -> i16 {
+        self.registers.get(offset).copied().unwrap_or(0)
+    }
+
+    fn write(&mut self, offset: usize, value: i16) {
+        if let Some(register) = self.registers.get_mut(offset) {
+            *register = value;
+        }
+    }
+
+    fn tick(&mut self) {
+        self.sample_clock += self.sample_rate as u64;
+        if self.sample_clock >= self.cycles_per_second {
+            self.sample_clock -= self.cycles_per_second;
+            let sample = self.next_sample();
+            self.samples.push(sample);
+        }
+    }
+}
+
+/// Encodes the samples as a 16-bit mono WAV file.
+pub fn wav(samples: &[i16], sample_rate: u32) -> Vec<u8> {
+    let data_size = samples.len() as u32 * 2;
+    let mut bytes = Vec::with_capacity(44 + data_size as usize);
+
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data_size).to_le_bytes());
+    bytes.extend_from_slice(b"WAVE");
+
+    bytes.extend_from_slice(b"fmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes()); // size of the format chunk
+    bytes.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    bytes.extend_from_slice(&1u16.to_le_bytes()); // mono
+    bytes.extend_from_slice(&sample_rate.to_le_bytes());
+    bytes.extend_from_slice(&(sample_rate * 2).to_le_bytes()); // bytes per second
+    bytes.extend_from_slice(&2u16.to_le_bytes()); // bytes per sample
+    bytes.extend_from_slice(&16u16.to_le_bytes()); // bits per sample
+
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data_size.to_le_bytes());
+    for sample in samples {
+        bytes.extend_from_slice(&sample.to_le_bytes());
+    }
+
+    bytes
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn render(sound: &mut Sound, cycles: usize) -> Vec<i16> {
+        for _ in 0..cycles {
+            sound.tick();
+        }
+
+        sound.take_samples()
+    }
+
+    #[test]
+    fn test_sound_square_wave() {
+        // 1 sample per 10 cycles, 1 kHz is 4 samples up and 4 down
+        let mut sound = Sound::new(8000, 80_000);
+        assert_eq!(render(&mut sound, 30), vec![0; 3]);
+
+        sound.write(SQUARE_FREQUENCY, 1000);
+        sound.write(SQUARE_VOLUME, 2);
+        let samples = render(&mut sound, 160);
+
+        assert_eq!(samples.len(), 16);
+        assert_eq!(&samples[..8], &[128, 128, 128, -128, -128, -128, -128, 128]);
+        assert_eq!(&samples[..8], &samples[8..]);
+    }
+
+    #[test]
+    fn test_sound_noise_is_deterministic() {
+        let noise = || {
+            let mut sound = Sound::default();
+            sound.write(NOISE_FREQUENCY, 3000);
+            sound.write(NOISE_VOLUME, 255);
+            sound.write(SQUARE_FREQUENCY, 440);
+            sound.write(SQUARE_VOLUME, 255);
+            render(&mut sound, 100_000)
+        };
+
+        let samples = noise();
+        assert_eq!(samples.len(), 800);
+        assert_eq!(samples, noise());
+        assert!(samples.contains(&0));
+        assert!(samples.contains(&(255 * 2 * 64)));
+    }
+
+    #[test]
+    fn test_wav_header() {
+        let bytes = wav(&[1, -1], 8000);
+
+        assert_eq!(bytes.len(), 48);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(&bytes[4..8], &40u32.to_le_bytes());
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(&bytes[24..28], &8000u32.to_le_bytes());
+        assert_eq!(&bytes[36..44], b"data\x04\0\0\0");
+        assert_eq!(&bytes[44..], &[1, 0, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn test_sound_in_machine() {
+        use crate::{
+            assembler::asm_to_binary,
+            machine::{power_on, Backend},
+        };
+        use std::{cell::RefCell, rc::Rc};
+
+        // 500 Hz at volume 100
+        let program =
+            asm_to_binary("@500\nD=A\n@24586\nM=D\n@100\nD=A\n@24587\nM=D\n(END)\n@END\n0;JMP")
+                .unwrap();
+
+        let sound = Rc::new(RefCell::new(Sound::default()));
+        let mut machine = power_on(Backend::Emulated, program);
+        machine
+            .attach_device(SOUND_ADDRESS, Box::new(sound.clone()))
+            .unwrap();
+        machine.run(10_000);
+
+        // 8 kHz from 1 MHz: one sample per 125 cycles
+        let samples = sound.borrow_mut().take_samples();
+        assert_eq!(samples.len(), 80);
+        assert_eq!(samples[0], 6400);
+        assert_eq!(samples[8], -6400);
+    }
+}
@@ -0,0 +1,134 @@
+// Tape reader
+//
+// Feeds a program, or any other words, into the Hack computer. With the loader
+// (`specs/examples/loader.asm`) in the ROM, the Von Neumann mode runs programs from the tape:
+// the loader copies them into the RAM at `LOAD_ADDRESS`, and jumps there.
+//
+// Words, relative to the base address:
+//   0  data: the word under the head, or 0 when the tape has ended.
+//      Write anything to move to the next word, e.g. `D=M` and then `M=0`.
+//   1  the number of words left, at most 32767 (read only)
+
+use std::collections::VecDeque;
+
+use crate::machine::device::Device;
+
+/// Default base address: after the multiplier.
+pub const TAPE_ADDRESS: usize = 24596;
+
+pub const DATA: usize = 0;
+pub const REMAINING: usize = 1;
+
+/// Where the loader puts the program. The ROM ends here in the Von Neumann mode.
+pub const LOAD_ADDRESS: usize = 1024;
+
+#[derive(Debug, Default)]
+pub struct Tape {
+    words: VecDeque<i16>,
+}
+
+impl Tape {
+    pub fn new(words: Vec<i16>) -> Self {
+        Self {
+            words: words.into(),
+        }
+    }
+
+    /// Appends words to the end of the tape.
+    pub fn push(&mut self, words: &[i16]) {
+        self.words.extend(words);
+    }
+
+    /// Number of words, that the program has not read yet.
+    pub fn remaining(&self) -> usize {
+        self.words.len()
+    }
+}
+
+impl Device for Tape {
+    fn size(&self) -> usize {
+        2
+    }
+
+    fn read(&self, offset: usize) -> i16 {
+        match offset {
+            DATA => self.words.front().copied().unwrap_or(0),
+            REMAINING => self.words.len().min(i16::MAX as usize) as i16,
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, offset: usize, _value: i16) {
+        if offset == DATA {
+            self.words.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tape_reads_in_order() {
+        let mut tape = Tape::new(vec![7, -1]);
+        assert_eq!((tape.read(DATA), tape.read(REMAINING)), (7, 2));
+        tape.write(DATA, 0);
+        assert_eq!((tape.read(DATA), tape.read(REMAINING)), (-1, 1));
+        tape.write(DATA, 0);
+        assert_eq!((tape.read(DATA), tape.read(REMAINING)), (0, 0));
+        tape.write(DATA, 0);
+        assert_eq!(tape.remaining(), 0);
+    }
+
+    #[test]
+    fn test_loader_runs_program_from_ram() {
+        use crate::{
+            assembler::asm_to_binary,
+            machine::{
+                config::{MachineConfig, MemoryMap},
+                power_on_with_config,
+                runner::{IllegalPolicy, Runner},
+                Backend,
+            },
+        };
+        use std::{cell::RefCell, rc::Rc};
+
+        let loader = asm_to_binary(include_str!("../../../specs/examples/loader.asm")).unwrap();
+
+        // adds to R0 three times, and after the first round it rewrites its own `@5` into `@4`
+        let program = asm_to_binary(
+            ".origin 1024\n\
+             @PATCH\nD=A\n@R2\nM=D\n\
+             @R0\nM=0\n@3\nD=A\n@R1\nM=D\n\
+             (LOOP)\n(PATCH)\n@5\nD=A\n@R0\nM=D+M\n\
+             @4\nD=A\n@PATCH\nM=D\n\
+             @R1\nMD=M-1\n@LOOP\nD;JGT\n\
+             (END)\n@END\n0;JMP",
+        )
+        .unwrap();
+
+        let tape = Rc::new(RefCell::new(Tape::new(program)));
+        let config = MachineConfig {
+            memory: MemoryMap {
+                rom_size: LOAD_ADDRESS,
+                execute_ram: true,
+                ..MemoryMap::default()
+            },
+            ..MachineConfig::default()
+        };
+        let mut machine = power_on_with_config(Backend::Emulated, config, loader.clone()).unwrap();
+        machine
+            .attach_device(TAPE_ADDRESS, Box::new(tape.clone()))
+            .unwrap();
+
+        let mut runner = Runner::new(machine, loader.len(), IllegalPolicy::Trap);
+        runner.run(1000).unwrap();
+
+        assert!(runner.is_halted());
+        assert_eq!(tape.borrow().remaining(), 0);
+        assert_eq!(runner.machine.read_memory(0), 5 + 4 + 4);
+        assert_eq!(runner.machine.read_memory(2), 1034);
+        assert_eq!(runner.machine.read_rom(1034), 4);
+    }
+}
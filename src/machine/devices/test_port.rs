@@ -0,0 +1,254 @@
+// Test-control port
+//
+// Lets a Hack program check itself, and tell the host whether it passed.
+// The host does not need to know, which RAM cells hold the results.
+//
+// Words, relative to the base address:
+//   0  exit: write the exit code to end the run, 0 means success
+//   1  assert: write D (`M=D`) to report an assertion failure with that value
+//
+// `run_test` runs a program with the port attached, and turns the writes into a `TestResult`.
+// `run_steps` is its step loop, for hosts that attach the port themselves, like the CLI.
+
+use std::{cell::RefCell, fmt, rc::Rc};
+
+use crate::machine::{
+    device::Device,
+    runner::{IllegalPolicy, Runner, Trap},
+    Machine,
+};
+
+/// Default base address: after the serial console.
+pub const TEST_PORT_ADDRESS: usize = 24584;
+
+pub const EXIT: usize = 0;
+pub const ASSERT: usize = 1;
+
+#[derive(Debug, Default)]
+pub struct TestPort {
+    exit_code: Option<i16>,
+
+    /// values of the failed assertions, in the order they were written
+    assertions: Vec<i16>,
+}
+
+impl TestPort {
+    pub fn exit_code(&self) -> Option<i16> {
+        self.exit_code
+    }
+
+    pub fn failed_assertions(&self) -> &[i16] {
+        &self.assertions
+    }
+
+    /// The result of the run, once the program has exited or failed an assertion.
+    /// `address` is the instruction, that wrote into the port last.
+    pub fn outcome(&self, address: usize) -> Option<TestOutcome> {
+        match (self.assertions.first(), self.exit_code) {
+            (Some(value), _) => Some(TestOutcome::AssertionFailed {
+                address,
+                value: *value,
+            }),
+            (None, Some(0)) => Some(TestOutcome::Passed),
+            (None, Some(exit_code)) => Some(TestOutcome::Failed { exit_code }),
+            (None, None) => None,
+        }
+    }
+}
+
+impl Device for TestPort {
+    fn size(&self) -> usize {
+        2
+    }
+
+    fn read(&self, _offset: usize) -> i16 {
+        0
+    }
+
+    fn write(&mut self, offset: usize, value: i16) {
+        match offset {
+            EXIT => self.exit_code = Some(value),
+            ASSERT => self.assertions.push(value),
+            _ => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestOutcome {
+    /// The program wrote the exit code 0.
+    Passed,
+
+    /// The program wrote a non-zero exit code.
+    Failed {
+        exit_code: i16,
+    },
+
+    /// The instruction at `address` wrote `value` into the assert port.
+    AssertionFailed {
+        address: usize,
+        value: i16,
+    },
+
+    /// The program halted in its end loop without an exit code.
+    Halted,
+
+    /// The steps ran out.
+    Timeout,
+
+    Trapped(Trap),
+}
+
+impl fmt::Display for TestOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TestOutcome::Passed => write!(f, "Passed"),
+            TestOutcome::Failed { exit_code } => write!(f, "Failed with exit code {}", exit_code),
+            TestOutcome::AssertionFailed { address, value } => {
+                write!(f, "Assertion failed at ROM[{}]: D = {}", address, value)
+            }
+            TestOutcome::Halted => write!(f, "Halted without an exit code"),
+            TestOutcome::Timeout => write!(f, "Did not exit"),
+            TestOutcome::Trapped(trap) => write!(f, "{}", trap),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TestResult {
+    pub outcome: TestOutcome,
+
+    /// executed instructions
+    pub steps: usize,
+}
+
+impl TestResult {
+    pub fn passed(&self) -> bool {
+        self.outcome == TestOutcome::Passed
+    }
+}
+
+/// Runs a self-checking program for at most `max_steps` instructions.
+/// The test port is attached at `TEST_PORT_ADDRESS`, and illegal instructions trap.
+pub fn run_test(
+    mut machine: Box<dyn Machine>,
+    program_length: usize,
+    max_steps: usize,
+) -> Result<TestResult, String> {
+    let port = Rc::new(RefCell::new(TestPort::default()));
+    machine.attach_device(TEST_PORT_ADDRESS, Box::new(port.clone()))?;
+    let mut runner = Runner::new(machine, program_length, IllegalPolicy::Trap);
+
+    Ok(run_steps(&mut runner, &port, max_steps))
+}
+
+/// Steps the program, until it exits through `port`, halts in its end loop or traps,
+/// for at most `max_steps` instructions. The outcome is `Timeout` when they ran out,
+/// so a host can call it again for the next slice of steps.
+pub fn run_steps(runner: &mut Runner, port: &RefCell<TestPort>, max_steps: usize) -> TestResult {
+    let mut steps = 0;
+    loop {
+        if runner.is_halted() {
+            return TestResult {
+                outcome: TestOutcome::Halted,
+                steps,
+            };
+        }
+        if steps == max_steps {
+            return TestResult {
+                outcome: TestOutcome::Timeout,
+                steps,
+            };
+        }
+
+        let outcome = match runner.step() {
+            Err(trap) => Some(TestOutcome::Trapped(trap)),
+            Ok(info) => port.borrow().outcome(info.pc as u16 as usize),
+        };
+        steps += 1;
+
+        if let Some(outcome) = outcome {
+            return TestResult { outcome, steps };
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        assembler::asm_to_binary,
+        machine::{power_on, Backend},
+    };
+
+    fn run(source: &str) -> TestResult {
+        let program = asm_to_binary(source).unwrap();
+        let length = program.len();
+
+        run_test(power_on(Backend::Emulated, program), length, 1000).unwrap()
+    }
+
+    #[test]
+    fn test_port_outcomes() {
+        // 6 + 7 == 13, then exit 0
+        let passing =
+            "@6\nD=A\n@7\nD=D+A\n@13\nD=D-A\n@FAIL\nD;JNE\n@24584\nM=0\n(FAIL)\n@24585\nM=D";
+        assert_eq!(
+            run(passing),
+            TestResult {
+                outcome: TestOutcome::Passed,
+                steps: 10
+            }
+        );
+
+        // 6 + 8 != 13, the difference is reported
+        let failing = passing.replace("@7", "@8");
+        assert_eq!(
+            run(&failing).outcome,
+            TestOutcome::AssertionFailed {
+                address: 11,
+                value: 1
+            }
+        );
+        assert_eq!(
+            run(&failing).outcome.to_string(),
+            "Assertion failed at ROM[11]: D = 1"
+        );
+
+        assert_eq!(
+            run("@3\nD=A\n@24584\nM=D").outcome,
+            TestOutcome::Failed { exit_code: 3 }
+        );
+        assert_eq!(run("(END)\n@END\n0;JMP").outcome, TestOutcome::Halted);
+        assert_eq!(
+            run("(LOOP)\n@LOOP\nD=D+1;JMP").outcome,
+            TestOutcome::Timeout
+        );
+    }
+
+    #[test]
+    fn test_port_run_steps_in_slices() {
+        // counts down from 5, then exits 0
+        let program =
+            asm_to_binary("@5\nD=A\n(LOOP)\nD=D-1\n@LOOP\nD;JNE\n@24584\nM=0\n(END)\n@END\n0;JMP")
+                .unwrap();
+        let length = program.len();
+        let mut machine = power_on(Backend::Emulated, program);
+        let port = Rc::new(RefCell::new(TestPort::default()));
+        machine
+            .attach_device(TEST_PORT_ADDRESS, Box::new(port.clone()))
+            .unwrap();
+        let mut runner = Runner::new(machine, length, IllegalPolicy::Trap);
+
+        let mut steps = 0;
+        let outcome = loop {
+            let result = run_steps(&mut runner, &port, 4);
+            steps += result.steps;
+            if result.outcome != TestOutcome::Timeout {
+                break result.outcome;
+            }
+            assert_eq!(result.steps, 4);
+        };
+        assert_eq!((outcome, steps), (TestOutcome::Passed, 19));
+    }
+}
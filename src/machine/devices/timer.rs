@@ -0,0 +1,219 @@
+// Timer
+//
+// Time for Hack programs, counted in clock cycles, so that it's the same on every run.
+// The emulated clock frequency only decides, how many cycles make one millisecond.
+//
+// Words, relative to the base address:
+//   0  cycles since power on, low 16 bits (read only)
+//   1  cycles since power on, high 16 bits (read only), latched when the program reads word 0
+//      (`D=M`, not just `@24577`), so that the two words belong to the same count,
+//      even when the low word rolls over in between. Read the low word first.
+//   2  milliseconds since power on, 16 bits, wraps around (read only)
+//   3  countdown: write milliseconds, read the milliseconds left, 0 when it has expired.
+//      Requests an interrupt, when it expires.
+//   4  random number, changes on every cycle. Write a seed to restart the sequence.
+
+use crate::{machine::device::Device, utils::random::Random};
+
+/// Default base address: right after the keyboard.
+pub const TIMER_ADDRESS: usize = 24577;
+
+pub const CYCLES_LOW: usize = 0;
+pub const CYCLES_HIGH: usize = 1;
+pub const MILLISECONDS: usize = 2;
+pub const COUNTDOWN: usize = 3;
+pub const RANDOM: usize = 4;
+
+/// 1 MHz
+pub const DEFAULT_CYCLES_PER_MS: u64 = 1000;
+
+pub struct Timer {
+    cycles_per_ms: u64,
+    cycles: u64,
+
+    /// high word of the cycles, when the program read the low word
+    latched_high: i16,
+
+    /// cycle, when the countdown reaches zero
+    deadline: u64,
+    interrupt: bool,
+
+    rng: Random,
+    random: i16,
+}
+
+impl Timer {
+    pub fn new(cycles_per_ms: u64, seed: u64) -> Self {
+        let mut rng = Random::new(seed);
+        let random = rng.next_i16();
+
+        Self {
+            cycles_per_ms: cycles_per_ms.max(1),
+            cycles: 0,
+            latched_high: 0,
+            deadline: 0,
+            interrupt: false,
+            rng,
+            random,
+        }
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+}
+
+impl Default for Timer {
+    fn default() -> Self {
+        Self::new(DEFAULT_CYCLES_PER_MS, 0)
+    }
+}
+
+impl Device for Timer {
+    fn size(&self) -> usize {
+        5
+    }
+
+    fn read(&self, offset: usize) -> i16 {
+        match offset {
+            CYCLES_LOW => self.cycles as i16,
+            CYCLES_HIGH => self.latched_high,
+            MILLISECONDS => (self.cycles / self.cycles_per_ms) as i16,
+            COUNTDOWN => {
+                let left = self.deadline.saturating_sub(self.cycles);
+                ((left + self.cycles_per_ms - 1) / self.cycles_per_ms) as i16
+            }
+            RANDOM => self.random,
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, offset: usize, value: i16) {
+        match offset {
+            COUNTDOWN => {
+                self.deadline = self.cycles + value.max(0) as u64 * self.cycles_per_ms;
+            }
+            RANDOM => {
+                self.rng = Random::new(value as u16 as u64);
+                self.random = self.rng.next_i16();
+            }
+            _ => {}
+        }
+    }
+
+    fn read_strobe(&mut self, offset: usize) {
+        if offset == CYCLES_LOW {
+            self.latched_high = (self.cycles >> 16) as i16;
+        }
+    }
+
+    fn tick(&mut self) {
+        self.cycles += 1;
+        self.random = self.rng.next_i16();
+        if self.cycles == self.deadline {
+            self.interrupt = true;
+        }
+    }
+
+    fn take_interrupt(&mut self) -> bool {
+        std::mem::take(&mut self.interrupt)
+    }
+
+    fn may_interrupt(&self) -> bool {
+        self.interrupt || self.deadline > self.cycles
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_timer_counts_cycles() {
+        let mut timer = Timer::new(10, 1);
+        for _ in 0..70_005 {
+            timer.tick();
+        }
+
+        assert_eq!(timer.read(CYCLES_LOW), (70_005 & 0xFFFF) as i16);
+        timer.read_strobe(CYCLES_LOW);
+        assert_eq!(timer.read(CYCLES_HIGH), 1);
+        assert_eq!(timer.read(MILLISECONDS), 7000);
+
+        timer.write(COUNTDOWN, 3);
+        assert_eq!(timer.read(COUNTDOWN), 3);
+        for _ in 0..21 {
+            timer.tick();
+        }
+        assert_eq!(timer.read(COUNTDOWN), 1);
+        for _ in 0..8 {
+            timer.tick();
+        }
+        assert!(!timer.take_interrupt());
+        timer.tick();
+        assert_eq!(timer.read(COUNTDOWN), 0);
+        assert!(timer.take_interrupt());
+        assert!(!timer.take_interrupt());
+    }
+
+    #[test]
+    fn test_timer_latches_high_word() {
+        let mut timer = Timer::default();
+        for _ in 0..0xFFFF {
+            timer.tick();
+        }
+
+        // the low word rolls over between the two reads
+        timer.read_strobe(CYCLES_LOW);
+        let low = timer.read(CYCLES_LOW);
+        timer.tick();
+        assert_eq!((low, timer.read(CYCLES_HIGH)), (-1, 0));
+
+        timer.read_strobe(CYCLES_LOW);
+        assert_eq!((timer.read(CYCLES_LOW), timer.read(CYCLES_HIGH)), (0, 1));
+    }
+
+    #[test]
+    fn test_timer_random_is_seeded() {
+        let sequence = |timer: &mut Timer| -> Vec<i16> {
+            (0..8)
+                .map(|_| {
+                    timer.tick();
+                    timer.read(RANDOM)
+                })
+                .collect()
+        };
+
+        let mut a = Timer::default();
+        let mut b = Timer::default();
+        a.write(RANDOM, 1234);
+        b.write(RANDOM, 1234);
+        let first = sequence(&mut a);
+
+        assert_eq!(first, sequence(&mut b));
+        assert_ne!(first, sequence(&mut a));
+    }
+
+    #[test]
+    fn test_timer_in_machine() {
+        use crate::{
+            assembler::asm_to_binary,
+            machine::{power_on, Backend},
+        };
+
+        // waits for a 1 ms countdown, and stores the cycle count into R0
+        let program = asm_to_binary(
+            "@1\nD=A\n@24580\nM=D\n(WAIT)\n@24580\nD=M\n@WAIT\nD;JNE\n@24577\nD=M\n@R0\nM=D",
+        )
+        .unwrap();
+
+        let mut machine = power_on(Backend::Emulated, program);
+        machine
+            .attach_device(TIMER_ADDRESS, Box::<Timer>::default())
+            .unwrap();
+        machine.run(3000);
+
+        let cycles = machine.read_memory(0);
+        assert!((1000..1010).contains(&cycles), "cycles: {}", cycles);
+    }
+}
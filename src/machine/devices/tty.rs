@@ -0,0 +1,167 @@
+// Serial console
+//
+// Text in and out of the Hack program, without drawing glyphs on the screen.
+// The host keeps a handle to the device (`Rc<RefCell<Tty>>`), fills the input
+// and takes the output, e.g. from stdin/stdout in the CLI or from the console panel in the GUI.
+//
+// Words, relative to the base address:
+//   0  output: write a character code to print it
+//   1  input: the next character code, or 0 when nothing is waiting.
+//      Write anything to take the character out, e.g. `D=M` and then `M=0`.
+//      After the host closed the input (e.g. stdin hit its end) and the program took
+//      the last character, it reads -1, so a loop that waits for a character can stop.
+//
+// The characters are ASCII. A newline is the Hack newline key (128),
+// and 10 is accepted on the output too.
+
+use std::collections::VecDeque;
+
+use crate::machine::{device::Device, key::Key};
+
+/// Default base address: after the timer.
+pub const TTY_ADDRESS: usize = 24582;
+
+pub const OUTPUT: usize = 0;
+pub const INPUT: usize = 1;
+
+/// The input word, when the input is closed and empty.
+pub const END_OF_INPUT: i16 = -1;
+
+#[derive(Debug, Default)]
+pub struct Tty {
+    output: String,
+    input: VecDeque<i16>,
+    closed: bool,
+    polls_after_end: usize,
+}
+
+impl Tty {
+    /// Queues text for the program. Characters that Hack cannot show are dropped.
+    pub fn push_input(&mut self, text: &str) {
+        let codes = text
+            .chars()
+            .filter_map(Key::from_char)
+            .filter_map(|key| key.code());
+        self.input.extend(codes);
+    }
+
+    /// No more input after the waiting characters.
+    pub fn close_input(&mut self) {
+        self.closed = true;
+    }
+
+    /// Number of times the program read `END_OF_INPUT`. A program, that keeps reading
+    /// after the end of the input, does not notice it and would wait forever.
+    pub fn polls_after_end(&self) -> usize {
+        self.polls_after_end
+    }
+
+    /// Everything printed since the last call.
+    pub fn take_output(&mut self) -> String {
+        std::mem::take(&mut self.output)
+    }
+
+    /// Everything printed and not taken yet.
+    pub fn output(&self) -> &str {
+        &self.output
+    }
+
+    /// Number of characters, that the program has not read yet.
+    pub fn pending_input(&self) -> usize {
+        self.input.len()
+    }
+}
+
+impl Device for Tty {
+    fn size(&self) -> usize {
+        2
+    }
+
+    fn read(&self, offset: usize) -> i16 {
+        match offset {
+            INPUT => match self.input.front() {
+                Some(&code) => code,
+                None if self.closed => END_OF_INPUT,
+                None => 0,
+            },
+            _ => 0,
+        }
+    }
+
+    fn read_strobe(&mut self, offset: usize) {
+        if offset == INPUT && self.closed && self.input.is_empty() {
+            self.polls_after_end += 1;
+        }
+    }
+
+    fn write(&mut self, offset: usize, value: i16) {
+        match offset {
+            OUTPUT => match Key::from_code(value) {
+                Some(Key::Char(c)) => self.output.push(c),
+                Some(Key::Newline) => self.output.push('\n'),
+                _ if value == 10 => self.output.push('\n'),
+                _ => {}
+            },
+            INPUT => {
+                self.input.pop_front();
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tty_echo() {
+        use crate::{
+            assembler::asm_to_binary,
+            machine::{power_on, Backend},
+        };
+        use std::{cell::RefCell, rc::Rc};
+
+        // copies the input to the output, until the input is empty
+        let program = asm_to_binary(
+            "(LOOP)\n@24583\nD=M\nM=0\n@END\nD;JEQ\n@24582\nM=D\n@LOOP\n0;JMP\n(END)\n@END\n0;JMP",
+        )
+        .unwrap();
+
+        for backend in [Backend::GateLevel, Backend::Emulated] {
+            let tty = Rc::new(RefCell::new(Tty::default()));
+            tty.borrow_mut().push_input("Hi!\n\tok");
+
+            let mut machine = power_on(backend, program.clone());
+            machine
+                .attach_device(TTY_ADDRESS, Box::new(tty.clone()))
+                .unwrap();
+            machine.run(80);
+
+            assert_eq!(
+                tty.borrow_mut().take_output(),
+                "Hi!\nok",
+                "{}",
+                backend.name()
+            );
+            assert_eq!(tty.borrow().pending_input(), 0);
+            assert_eq!(tty.borrow().output(), "");
+        }
+    }
+
+    #[test]
+    fn test_tty_end_of_input() {
+        let mut tty = Tty::default();
+        tty.push_input("a");
+        tty.close_input();
+        assert_eq!(tty.read(INPUT), 97);
+        tty.read_strobe(INPUT);
+        tty.write(INPUT, 0);
+        assert_eq!(tty.polls_after_end(), 0);
+
+        assert_eq!(tty.read(INPUT), END_OF_INPUT);
+        tty.read_strobe(INPUT);
+        tty.read_strobe(INPUT);
+        assert_eq!(tty.polls_after_end(), 2);
+    }
+}
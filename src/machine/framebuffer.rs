@@ -0,0 +1,125 @@
+use super::config::MemoryMap;
+
+/// Width of the screen in pixels.
+pub const SCREEN_WIDTH: usize = 512;
+
+/// Height of the screen in pixels.
+pub const SCREEN_HEIGHT: usize = 256;
+
+/// Number of words in the screen memory map. One word holds 16 pixels of a row.
+pub const SCREEN_WORDS: usize = SCREEN_WIDTH * SCREEN_HEIGHT / 16;
+
+/// The screen as a monochrome bitmap, decoded from the screen memory map.
+///
+/// Pixel (row, col) is the bit `col % 16` of the word `SCREEN + row * 32 + col / 16`.
+/// With another resolution, a row has `width / 16` words instead of 32.
+/// `true` is a black pixel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+
+    /// rows from top to bottom, each row from left to right
+    pixels: Vec<bool>,
+}
+
+impl Framebuffer {
+    /// Decodes the screen of the Hack memory map.
+    /// `read_memory` returns the word at a data memory address.
+    pub fn from_memory(read_memory: impl Fn(usize) -> i16) -> Self {
+        Self::from_memory_map(&MemoryMap::default(), read_memory)
+    }
+
+    /// Decodes the screen, that is described by the memory map.
+    pub fn from_memory_map(memory_map: &MemoryMap, read_memory: impl Fn(usize) -> i16) -> Self {
+        let width = memory_map.screen_width;
+        let height = memory_map.screen_height;
+        let mut pixels = vec![false; width * height];
+
+        for word_index in 0..memory_map.screen_words() {
+            let word = read_memory(memory_map.screen_address + word_index);
+            if word == 0 {
+                continue;
+            }
+
+            for bit in 0..16 {
+                pixels[word_index * 16 + bit] = (word >> bit) & 1 == 1;
+            }
+        }
+
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn pixel(&self, row: usize, col: usize) -> bool {
+        self.pixels[row * self.width + col]
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[bool] {
+        &self.pixels
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn test_framebuffer_bit_order() {
+        use super::*;
+        use crate::machine::SCREEN_ADDRESS;
+
+        // row 0: the first word has the bits 0 and 15 on
+        // row 255: the last word has the bit 1 on
+        let framebuffer = Framebuffer::from_memory(|address| match address - SCREEN_ADDRESS {
+            0 => 0b1000_0000_0000_0001u16 as i16,
+            8191 => 0b10,
+            _ => 0,
+        });
+
+        assert!(framebuffer.pixel(0, 0));
+        assert!(!framebuffer.pixel(0, 1));
+        assert!(framebuffer.pixel(0, 15));
+        assert!(!framebuffer.pixel(0, 16));
+        assert!(framebuffer.pixel(255, 497));
+        assert_eq!(
+            framebuffer.pixels().iter().filter(|pixel| **pixel).count(),
+            3
+        );
+    }
+
+    #[test]
+    fn test_framebuffer_resolution() {
+        use super::*;
+
+        // 32 x 2 pixels at 100: two words per row
+        let memory_map = MemoryMap {
+            screen_address: 100,
+            screen_width: 32,
+            screen_height: 2,
+            ..MemoryMap::default()
+        };
+        let framebuffer = Framebuffer::from_memory_map(&memory_map, |address| match address {
+            101 => 1,
+            102 => -1,
+            _ => 0,
+        });
+
+        assert_eq!((framebuffer.width(), framebuffer.height()), (32, 2));
+        assert!(framebuffer.pixel(0, 16) && !framebuffer.pixel(0, 17));
+        assert!((0..16).all(|col| framebuffer.pixel(1, col)));
+        assert_eq!(
+            framebuffer.pixels().iter().filter(|pixel| **pixel).count(),
+            17
+        );
+    }
+}
@@ -0,0 +1,262 @@
+use std::fmt;
+
+// Hack machine language
+//
+// A-instruction: 0 v v v v v v v v v v v v v v v
+// C-instruction: 1 x x a c1 c2 c3 c4 c5 c6 d1 d2 d3 j1 j2 j3
+//
+// The Hack CPU ignores the x bits. The extensions use them:
+//
+// RETI:          1 0 0 x x x x x x x x x x x x x    (interrupts)
+// shift:         1 0 1 a l s 0 0 0 0 d1 d2 d3 j1 j2 j3    (shifts)
+// stack:         1 1 0 0 c1 c2 c3 c4 c5 c6 d1 d2 d3 o1 o2 o3    (stack)
+//
+// The shift source is M when a = 1, otherwise D when s = 1, otherwise A.
+// l = 1 shifts left, l = 0 shifts right.
+//
+// The stack operation is in the o bits, where the jump bits are:
+//   000  PUSH comp   RAM[SP] = comp, SP = SP + 1
+//   001  POP dest    dest = RAM[SP - 1], SP = SP - 1
+//   010  CALL        RAM[SP] = PC + 1, SP = SP + 1, jump to A
+//   011  RET         jump to RAM[SP - 1], SP = SP - 1
+//   100  SP=comp
+//   101  dest=SP
+// comp is only used by PUSH and SP=, dest only by POP and dest=SP, and only A and D.
+//
+// See ./specs/README.md for the tables.
+
+/// Destination bit: A register
+pub const DEST_A: u16 = 0b100;
+
+/// Destination bit: D register
+pub const DEST_D: u16 = 0b010;
+
+/// Destination bit: RAM[A]
+pub const DEST_M: u16 = 0b001;
+
+/// Jump if the ALU output is negative
+pub const JUMP_LT: u16 = 0b100;
+
+/// Jump if the ALU output is zero
+pub const JUMP_EQ: u16 = 0b010;
+
+/// Jump if the ALU output is positive
+pub const JUMP_GT: u16 = 0b001;
+
+/// Every `comp` of the Hack table. The value is `a c1 c2 c3 c4 c5 c6`.
+pub const COMP_TABLE: [(&str, u16); 28] = [
+    ("0", 0b0101010),
+    ("1", 0b0111111),
+    ("-1", 0b0111010),
+    ("D", 0b0001100),
+    ("A", 0b0110000),
+    ("!D", 0b0001101),
+    ("!A", 0b0110001),
+    ("-D", 0b0001111),
+    ("-A", 0b0110011),
+    ("D+1", 0b0011111),
+    ("A+1", 0b0110111),
+    ("D-1", 0b0001110),
+    ("A-1", 0b0110010),
+    ("D+A", 0b0000010),
+    ("D-A", 0b0010011),
+    ("A-D", 0b0000111),
+    ("D&A", 0b0000000),
+    ("D|A", 0b0010101),
+    ("M", 0b1110000),
+    ("!M", 0b1110001),
+    ("-M", 0b1110011),
+    ("M+1", 0b1110111),
+    ("M-1", 0b1110010),
+    ("D+M", 0b1000010),
+    ("D-M", 0b1010011),
+    ("M-D", 0b1000111),
+    ("D&M", 0b1000000),
+    ("D|M", 0b1010101),
+];
+
+pub const DEST_TABLE: [&str; 8] = ["", "M", "D", "MD", "A", "AM", "AD", "AMD"];
+
+pub const JUMP_TABLE: [&str; 8] = ["", "JGT", "JEQ", "JGE", "JLT", "JNE", "JLE", "JMP"];
+
+/// Every shift of the extended instruction set. The value is `a l s 0 0 0 0`.
+pub const SHIFT_TABLE: [(&str, u16); 6] = [
+    ("A<<", 0b0100000),
+    ("D<<", 0b0110000),
+    ("M<<", 0b1100000),
+    ("A>>", 0b0000000),
+    ("D>>", 0b0010000),
+    ("M>>", 0b1000000),
+];
+
+pub const STACK_PUSH: u16 = 0b000;
+pub const STACK_POP: u16 = 0b001;
+pub const STACK_CALL: u16 = 0b010;
+pub const STACK_RET: u16 = 0b011;
+pub const STACK_SET_SP: u16 = 0b100;
+pub const STACK_GET_SP: u16 = 0b101;
+
+/// Return from interrupt, see `config::Extensions`.
+pub const RETI: i16 = 0x8000u16 as i16;
+
+/// Whether the word is `RETI` on a CPU with the interrupt extension.
+/// Any value of the other bits is accepted.
+pub fn is_reti(word: i16) -> bool {
+    word as u16 & 0xE000 == 0x8000
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    /// @value
+    A(i16),
+
+    /// dest=comp;jump
+    C { comp: u16, dest: u16, jump: u16 },
+
+    /// dest=shift;jump, e.g. `D=D<<`. `comp` is the value in `SHIFT_TABLE`.
+    Shift { comp: u16, dest: u16, jump: u16 },
+
+    /// return from interrupt
+    Reti,
+
+    /// PUSH, POP, CALL and so on. `op` is one of the `STACK_*` values.
+    Stack { op: u16, comp: u16, dest: u16 },
+}
+
+impl Instruction {
+    pub fn decode(word: i16) -> Self {
+        let word = word as u16;
+        let comp = (word >> 6) & 0x7F;
+        let dest = (word >> 3) & 0x7;
+        let jump = word & 0x7;
+
+        match word >> 13 {
+            0b000..=0b011 => Instruction::A(word as i16),
+            0b100 => Instruction::Reti,
+            0b101 => Instruction::Shift { comp, dest, jump },
+            0b110 => Instruction::Stack {
+                op: jump,
+                comp,
+                dest,
+            },
+            _ => Instruction::C { comp, dest, jump },
+        }
+    }
+
+    pub fn encode(&self) -> i16 {
+        match *self {
+            Instruction::A(value) => value & 0x7FFF,
+            Instruction::C { comp, dest, jump } => {
+                (0xE000 | (comp & 0x7F) << 6 | (dest & 0x7) << 3 | (jump & 0x7)) as i16
+            }
+            Instruction::Shift { comp, dest, jump } => {
+                (0xA000 | (comp & 0x7F) << 6 | (dest & 0x7) << 3 | (jump & 0x7)) as i16
+            }
+            Instruction::Reti => RETI,
+            Instruction::Stack { op, comp, dest } => {
+                (0xC000 | (comp & 0x7F) << 6 | (dest & 0x7) << 3 | (op & 0x7)) as i16
+            }
+        }
+    }
+}
+
+pub fn comp_mnemonic(comp: u16) -> Option<&'static str> {
+    COMP_TABLE
+        .iter()
+        .find(|(_, bits)| *bits == comp)
+        .map(|(mnemonic, _)| *mnemonic)
+}
+
+pub fn shift_mnemonic(comp: u16) -> Option<&'static str> {
+    SHIFT_TABLE
+        .iter()
+        .find(|(_, bits)| *bits == comp)
+        .map(|(mnemonic, _)| *mnemonic)
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Instruction::A(value) => write!(f, "@{}", value),
+            Instruction::C { comp, dest, jump } => {
+                write_computation(f, comp_mnemonic(comp), comp, dest, jump)
+            }
+            Instruction::Shift { comp, dest, jump } => {
+                write_computation(f, shift_mnemonic(comp), comp, dest, jump)
+            }
+            Instruction::Reti => write!(f, "RETI"),
+            Instruction::Stack { op, comp, dest } => {
+                let dest = DEST_TABLE[dest as usize];
+                let comp = match comp_mnemonic(comp) {
+                    Some(mnemonic) => mnemonic.to_owned(),
+                    None => format!("?{:07b}", comp),
+                };
+                match op {
+                    STACK_PUSH => write!(f, "PUSH {}", comp),
+                    STACK_POP if dest.is_empty() => write!(f, "POP"),
+                    STACK_POP => write!(f, "POP {}", dest),
+                    STACK_CALL => write!(f, "CALL"),
+                    STACK_RET => write!(f, "RET"),
+                    STACK_SET_SP => write!(f, "SP={}", comp),
+                    STACK_GET_SP => write!(f, "{}=SP", dest),
+                    _ => write!(f, "?stack{:03b}", op),
+                }
+            }
+        }
+    }
+}
+
+fn write_computation(
+    f: &mut fmt::Formatter<'_>,
+    mnemonic: Option<&str>,
+    comp: u16,
+    dest: u16,
+    jump: u16,
+) -> fmt::Result {
+    if dest != 0 {
+        write!(f, "{}=", DEST_TABLE[dest as usize])?;
+    }
+    match mnemonic {
+        Some(mnemonic) => write!(f, "{}", mnemonic)?,
+        None => write!(f, "?{:07b}", comp)?,
+    }
+    if jump != 0 {
+        write!(f, ";{}", JUMP_TABLE[jump as usize])?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn test_decode_and_display() {
+        use super::Instruction;
+
+        // instructions from the 6 * 7 script
+        let cases = [
+            (6, "@6"),
+            (-5104, "D=A"),
+            (-7416, "M=D"),
+            (-2864, "D=D-M"),
+            (-7422, "D;JEQ"),
+            (-568, "M=M+1"),
+            (-5497, "0;JMP"),
+            // extended instruction set
+            (-21488, "D=D<<"),
+            (-24568, "M=A>>"),
+            (-32768, "RETI"),
+            (-15616, "PUSH D"),
+            (-16335, "POP AD"),
+            (-16382, "CALL"),
+            (-16381, "RET"),
+            (-13308, "SP=A"),
+            (-16363, "D=SP"),
+        ];
+
+        for (word, text) in cases {
+            let instruction = Instruction::decode(word);
+            assert_eq!(instruction.to_string(), text);
+            assert_eq!(instruction.encode(), word);
+        }
+    }
+}
@@ -0,0 +1,193 @@
+// Hack keyboard
+//
+// The keyboard register (RAM[24576]) holds the code of the key, that is currently pressed,
+// or 0 when no key is pressed. Printable characters use their ASCII codes,
+// the other keys use the codes from 128 upwards.
+
+use std::collections::VecDeque;
+
+/// A key of the Hack keyboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    /// Printable ASCII character, from ' ' to '~'.
+    Char(char),
+    Newline,
+    Backspace,
+    Left,
+    Up,
+    Right,
+    Down,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Insert,
+    Delete,
+    Esc,
+
+    /// Function key F1..F12.
+    F(u8),
+}
+
+impl Key {
+    /// Printable ASCII characters only.
+    pub fn from_char(c: char) -> Option<Key> {
+        if (' '..='~').contains(&c) {
+            Some(Key::Char(c))
+        } else if c == '\n' {
+            Some(Key::Newline)
+        } else {
+            None
+        }
+    }
+
+    /// Code of the key in the keyboard register.
+    /// `None` for the keys, that the Hack keyboard does not have:
+    /// characters other than printable ASCII, and function keys other than F1..F12.
+    pub fn code(&self) -> Option<i16> {
+        let code = match *self {
+            Key::Char(c) if (' '..='~').contains(&c) => c as i16,
+            Key::Char(_) => return None,
+            Key::Newline => 128,
+            Key::Backspace => 129,
+            Key::Left => 130,
+            Key::Up => 131,
+            Key::Right => 132,
+            Key::Down => 133,
+            Key::Home => 134,
+            Key::End => 135,
+            Key::PageUp => 136,
+            Key::PageDown => 137,
+            Key::Insert => 138,
+            Key::Delete => 139,
+            Key::Esc => 140,
+            Key::F(n @ 1..=12) => 140 + n as i16,
+            Key::F(_) => return None,
+        };
+
+        Some(code)
+    }
+
+    pub fn from_code(code: i16) -> Option<Key> {
+        let key = match code {
+            32..=126 => Key::Char(code as u8 as char),
+            128 => Key::Newline,
+            129 => Key::Backspace,
+            130 => Key::Left,
+            131 => Key::Up,
+            132 => Key::Right,
+            133 => Key::Down,
+            134 => Key::Home,
+            135 => Key::End,
+            136 => Key::PageUp,
+            137 => Key::PageDown,
+            138 => Key::Insert,
+            139 => Key::Delete,
+            140 => Key::Esc,
+            141..=152 => Key::F((code - 140) as u8),
+            _ => return None,
+        };
+
+        Some(key)
+    }
+}
+
+/// Key presses and releases, waiting for the clock.
+///
+/// The host can press and release a key between two instructions.
+/// The events are applied one per clock cycle,
+/// so that the program sees every key for at least one instruction.
+#[derive(Debug, Default, Clone)]
+pub struct KeyQueue {
+    /// key and whether it was pressed (true) or released (false)
+    events: VecDeque<(Key, bool)>,
+
+    /// keys held down, in the order they were pressed
+    pressed: Vec<Key>,
+}
+
+impl KeyQueue {
+    /// Keys without a Hack code are ignored.
+    pub fn key_down(&mut self, key: Key) {
+        if key.code().is_some() {
+            self.events.push_back((key, true));
+        }
+    }
+
+    pub fn key_up(&mut self, key: Key) {
+        if key.code().is_some() {
+            self.events.push_back((key, false));
+        }
+    }
+
+    /// Applies the next event. Returns the new value of the keyboard register,
+    /// or `None` if there was nothing to apply.
+    ///
+    /// When many keys are held, the register shows the last pressed one.
+    pub fn next_code(&mut self) -> Option<i16> {
+        let (key, down) = self.events.pop_front()?;
+
+        self.pressed.retain(|pressed| *pressed != key);
+        if down {
+            self.pressed.push(key);
+        }
+
+        Some(self.pressed.last().and_then(Key::code).unwrap_or(0))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn test_key_codes() {
+        use super::Key;
+
+        assert_eq!(Key::from_char('A').unwrap().code(), Some(65));
+        assert_eq!(Key::from_char('\n'), Some(Key::Newline));
+        assert_eq!(Key::from_char('\t'), None);
+        assert_eq!(Key::Backspace.code(), Some(129));
+        assert_eq!(Key::Down.code(), Some(133));
+        assert_eq!(Key::Esc.code(), Some(140));
+        assert_eq!(Key::F(1).code(), Some(141));
+        assert_eq!(Key::F(12).code(), Some(152));
+
+        // no codes, that would collide with other keys
+        assert_eq!(Key::F(0).code(), None);
+        assert_eq!(Key::F(13).code(), None);
+        assert_eq!(Key::Char('é').code(), None);
+        assert_eq!(Key::Char('\t').code(), None);
+
+        for code in 0..200 {
+            if let Some(key) = Key::from_code(code) {
+                assert_eq!(key.code(), Some(code));
+            }
+        }
+    }
+
+    #[test]
+    fn test_key_queue() {
+        use super::{Key, KeyQueue};
+
+        let mut queue = KeyQueue::default();
+        assert_eq!(queue.next_code(), None);
+
+        // a quick tap between two instructions is still seen
+        queue.key_down(Key::Char('A'));
+        queue.key_up(Key::Char('A'));
+        assert_eq!(queue.next_code(), Some(65));
+        assert_eq!(queue.next_code(), Some(0));
+
+        // the last pressed key wins, and the older one comes back when it's released
+        queue.key_down(Key::Left);
+        queue.key_down(Key::Char('B'));
+        queue.key_up(Key::Char('B'));
+        assert_eq!(queue.next_code(), Some(130));
+        assert_eq!(queue.next_code(), Some(66));
+        assert_eq!(queue.next_code(), Some(130));
+        assert_eq!(queue.next_code(), None);
+
+        // keys without a code never reach the register
+        queue.key_down(Key::F(13));
+        assert_eq!(queue.next_code(), None);
+    }
+}
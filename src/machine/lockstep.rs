@@ -0,0 +1,553 @@
+// Lockstep differential testing
+//
+// Runs two machines side by side on the same ROM and compares them after every instruction.
+// The emulated computer is the reference, because it's a direct translation of the spec.
+// The gate-level computer is the one under test: every change to the CPU, ALU or registers
+// should keep it in lockstep with the reference.
+
+use std::fmt;
+
+use crate::{
+    emulated_parts::computer_emulated::ComputerEmulated, hack_computer::computer::Computer,
+    utils::random::Random,
+};
+
+use super::{
+    config::MachineConfig,
+    instruction::{Instruction, COMP_TABLE},
+    CpuState, Machine, StepInfo,
+};
+
+/// The first point where the machines did not agree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// number of the instruction, starting from 0
+    pub step: usize,
+    pub pc: i16,
+    pub instruction: i16,
+
+    pub expected: (CpuState, Option<(usize, i16)>),
+    pub actual: (CpuState, Option<(usize, i16)>),
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (expected_cpu, expected_write) = self.expected;
+        let (actual_cpu, actual_write) = self.actual;
+
+        writeln!(
+            f,
+            "Divergence at step {} (ROM[{}] = {:016b} `{}`)",
+            self.step,
+            self.pc,
+            self.instruction as u16,
+            Instruction::decode(self.instruction)
+        )?;
+        writeln!(
+            f,
+            "  expected: A: {}, D: {}, PC: {}, write: {:?}",
+            expected_cpu.a, expected_cpu.d, expected_cpu.pc, expected_write
+        )?;
+        write!(
+            f,
+            "  actual:   A: {}, D: {}, PC: {}, write: {:?}",
+            actual_cpu.a, actual_cpu.d, actual_cpu.pc, actual_write
+        )
+    }
+}
+
+/// Steps both machines `steps` times, and stops at the first divergence.
+pub fn run_lockstep(
+    reference: &mut dyn Machine,
+    under_test: &mut dyn Machine,
+    steps: usize,
+) -> Result<(), Divergence> {
+    for step in 0..steps {
+        let expected_info: StepInfo = reference.step();
+        let actual_info: StepInfo = under_test.step();
+
+        let expected = (reference.cpu_state(), expected_info.memory_write);
+        let actual = (under_test.cpu_state(), actual_info.memory_write);
+
+        if expected != actual || expected_info.pc != actual_info.pc {
+            return Err(Divergence {
+                step,
+                pc: expected_info.pc,
+                instruction: expected_info.instruction,
+                expected,
+                actual,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs the gate-level computer against the emulated reference.
+pub fn run_lockstep_gate_level(rom_disk: Vec<i16>, steps: usize) -> Result<(), Divergence> {
+    run_lockstep_with_config(rom_disk, MachineConfig::default(), steps)
+}
+
+/// Runs the gate-level computer with the chosen parts against the emulated reference.
+pub fn run_lockstep_with_config(
+    rom_disk: Vec<i16>,
+    config: MachineConfig,
+    steps: usize,
+) -> Result<(), Divergence> {
+    let mut reference = ComputerEmulated::power_on_with_config(rom_disk.clone(), config);
+    let mut under_test = Computer::power_on_with_config(rom_disk, config);
+
+    run_lockstep(&mut reference, &mut under_test, steps)
+}
+
+/// Generates a random, but valid, instruction sequence.
+///
+/// A-instructions load mostly small addresses: either a RAM variable (0..32),
+/// or an address inside the program, so that the jumps stay in the program.
+pub fn random_program(random: &mut Random, length: usize) -> Vec<i16> {
+    (0..length)
+        .map(|_| {
+            let instruction = if random.chance(50) {
+                let value = if random.chance(60) {
+                    random.below(length)
+                } else {
+                    random.below(32)
+                };
+                Instruction::A(value as i16)
+            } else {
+                let jump = if random.chance(70) {
+                    0
+                } else {
+                    random.below(8) as u16
+                };
+                Instruction::C {
+                    comp: COMP_TABLE[random.below(COMP_TABLE.len())].1,
+                    dest: random.below(8) as u16,
+                    jump,
+                }
+            };
+
+            instruction.encode()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn test_lockstep_random_programs() {
+        use super::*;
+
+        let mut random = Random::new(2023);
+
+        // the gate-level computer is slow, so the programs are kept short
+        for _ in 0..8 {
+            let program = random_program(&mut random, 24);
+            let result = run_lockstep_gate_level(program.clone(), 40);
+
+            if let Err(divergence) = result {
+                panic!("{}\nprogram: {:?}", divergence, program);
+            }
+        }
+    }
+
+    #[test]
+    fn test_lockstep_reports_divergence() {
+        use super::*;
+
+        // @5, D=A, @7 and @5, D=A, @8
+        let mut reference = ComputerEmulated::power_on(vec![5, -5104, 7]);
+        let mut under_test = ComputerEmulated::power_on(vec![5, -5104, 8]);
+
+        let divergence = run_lockstep(&mut reference, &mut under_test, 10).unwrap_err();
+
+        assert_eq!(divergence.step, 2);
+        assert_eq!(divergence.pc, 2);
+        assert_eq!(divergence.expected.0.a, 7);
+        assert_eq!(divergence.actual.0.a, 8);
+        assert!(divergence.to_string().contains("`@7`"));
+    }
+
+    #[test]
+    fn test_lockstep_asm6times7() {
+        use super::*;
+
+        let program = vec![
+            6, -5104, 0, -7416, 7, -5104, 1, -7416, 0, -5104, 2, -7416, 0, -5104, 3, -7416, 3,
+            -1008, 1, -2864, 31, -7422, 0, -1008, 2, -3952, -7416, 3, -568, 16, -5497, 2, -1008,
+            31, -5497,
+        ];
+
+        let mut reference = ComputerEmulated::power_on(program.clone());
+        let mut under_test = Computer::power_on(program);
+
+        if let Err(divergence) = run_lockstep(&mut reference, &mut under_test, 150) {
+            panic!("{}", divergence);
+        }
+        assert_eq!(under_test.cpu_state().d, 42);
+        assert_eq!(under_test.read_memory(2), 42);
+    }
+
+    #[test]
+    fn test_lockstep_specs_examples() {
+        use super::*;
+        use crate::assembler::asm_to_binary;
+
+        let examples = [
+            (
+                "example1",
+                include_str!("../../specs/examples/example1.asm"),
+            ),
+            (
+                "example2",
+                include_str!("../../specs/examples/example2.asm"),
+            ),
+            (
+                "example3_pointers",
+                include_str!("../../specs/examples/example3_pointers.asm"),
+            ),
+            (
+                "example4_io",
+                include_str!("../../specs/examples/example4_io.asm"),
+            ),
+            ("task_a", include_str!("../../specs/project 4/task_a.asm")),
+            ("task_b", include_str!("../../specs/project 4/task_b.asm")),
+        ];
+
+        for (name, source) in examples {
+            let program = asm_to_binary(source).unwrap();
+
+            // task_b fills the whole screen, so only the beginning of it is compared
+            if let Err(divergence) = run_lockstep_gate_level(program, 100) {
+                panic!("{}: {}", name, divergence);
+            }
+        }
+    }
+
+    #[test]
+    fn test_lockstep_mixed_fidelity() {
+        use super::*;
+        use crate::machine::config::Fidelity;
+
+        let emulated = MachineConfig::all(Fidelity::Emulated);
+        let configs = [
+            emulated,
+            MachineConfig {
+                alu: Fidelity::Gate,
+                ..emulated
+            },
+            MachineConfig {
+                registers: Fidelity::Gate,
+                pc: Fidelity::Gate,
+                ..emulated
+            },
+            MachineConfig {
+                ram: Fidelity::Emulated,
+                screen: Fidelity::Emulated,
+                ..MachineConfig::default()
+            },
+        ];
+
+        let mut random = Random::new(2029);
+        for config in configs {
+            let program = random_program(&mut random, 24);
+            if let Err(divergence) = run_lockstep_with_config(program.clone(), config, 60) {
+                panic!("{:?}\n{}\nprogram: {:?}", config, divergence, program);
+            }
+        }
+    }
+
+    #[test]
+    fn test_lockstep_keyboard_events() {
+        use super::*;
+        use crate::{
+            assembler::asm_to_binary,
+            machine::{config::Fidelity, key::Key},
+        };
+
+        let program = asm_to_binary("(LOOP)\n@KBD\nD=M\n@LOOP\n0;JMP").unwrap();
+        let config = MachineConfig {
+            ram: Fidelity::Emulated,
+            ..MachineConfig::default()
+        };
+        let mut reference = ComputerEmulated::power_on(program.clone());
+        let mut under_test = Computer::power_on_with_config(program, config);
+
+        let mut seen = Vec::new();
+        for step in 0..24 {
+            for machine in [&mut reference as &mut dyn Machine, &mut under_test] {
+                match step {
+                    2 => machine.key_down(Key::Char('A')),
+                    7 => machine.key_up(Key::Char('A')),
+                    10 => machine.key_down(Key::F(1)),
+                    17 => machine.key_up(Key::F(1)),
+                    _ => {}
+                }
+            }
+
+            if let Err(divergence) = run_lockstep(&mut reference, &mut under_test, 1) {
+                panic!("step {}: {}", step, divergence);
+            }
+            seen.push(under_test.cpu_state().d);
+        }
+
+        assert!(seen.contains(&65));
+        assert!(seen.contains(&141));
+        assert_eq!(seen.last(), Some(&0));
+    }
+
+    #[test]
+    fn test_lockstep_unmapped_reads() {
+        use super::*;
+        use crate::{assembler::asm_to_binary, machine::key::Key};
+
+        // the addresses after the keyboard, that no device uses, read as 0 even while a key is held
+        let program =
+            asm_to_binary("(LOOP)\n@24600\nD=M\n@32767\nD=D+M\n@KBD\nD=D+M\n@LOOP\n0;JMP").unwrap();
+        let mut reference = ComputerEmulated::power_on(program.clone());
+        let mut under_test = Computer::power_on(program);
+        for machine in [&mut reference as &mut dyn Machine, &mut under_test] {
+            machine.key_down(Key::Char('A'));
+        }
+
+        if let Err(divergence) = run_lockstep(&mut reference, &mut under_test, 16) {
+            panic!("{}", divergence);
+        }
+        assert_eq!(under_test.cpu_state().d, 65);
+    }
+
+    #[test]
+    fn test_lockstep_shifts() {
+        use super::*;
+        use crate::{
+            assembler::asm_to_binary,
+            machine::config::{Extensions, Fidelity},
+        };
+
+        // R2 = R0 * R1 with shift and add
+        let program = asm_to_binary(
+            "@13\nD=A\n@R0\nM=D\n@11\nD=A\n@R1\nM=D\n@R2\nM=0\n\
+             (LOOP)\n@R1\nD=M\n@END\nD;JEQ\n@1\nD=D&A\n@SKIP\nD;JEQ\n@R0\nD=M\n@R2\nM=D+M\n\
+             (SKIP)\n@R0\nM=M<<\n@R1\nM=M>>\n@LOOP\n0;JMP\n(END)\n@END\n0;JMP",
+        )
+        .unwrap();
+        let config = MachineConfig {
+            ram: Fidelity::Emulated,
+            extensions: Extensions {
+                shifts: true,
+                ..Extensions::default()
+            },
+            ..MachineConfig::default()
+        };
+        let mut reference = ComputerEmulated::power_on_with_config(program.clone(), config);
+        let mut under_test = Computer::power_on_with_config(program, config);
+
+        if let Err(divergence) = run_lockstep(&mut reference, &mut under_test, 90) {
+            panic!("{}", divergence);
+        }
+        assert_eq!(under_test.read_memory(2), 143);
+        assert_eq!(under_test.read_memory(1), 0);
+
+        // D>> keeps the sign, and the jump goes to the A of the previous instruction
+        let program = asm_to_binary("@6\nD=-A\nD=D>>\nA=D<<;JLT").unwrap();
+        let mut reference = ComputerEmulated::power_on_with_config(program.clone(), config);
+        let mut under_test = Computer::power_on_with_config(program, config);
+        if let Err(divergence) = run_lockstep(&mut reference, &mut under_test, 4) {
+            panic!("{}", divergence);
+        }
+        assert_eq!(
+            under_test.cpu_state(),
+            CpuState {
+                a: -6,
+                d: -3,
+                pc: 6
+            }
+        );
+    }
+
+    #[test]
+    fn test_lockstep_stack() {
+        use super::*;
+        use crate::{
+            assembler::asm_to_binary,
+            machine::config::{Extensions, Fidelity},
+        };
+
+        // R0 = 5 + 4 + 3 + 2 + 1 with a recursive function, R1 = SP at the end
+        let program = asm_to_binary(
+            "@256\nD=A\nSP=D\n@5\nD=A\n@SUM\nCALL\n@R0\nM=D\nD=SP\n@R1\nM=D\n\
+             (END)\n@END\n0;JMP\n\
+             (SUM)\n@BASE\nD;JEQ\nPUSH D\nD=D-1\n@SUM\nCALL\nPOP A\nD=D+A\nRET\n\
+             (BASE)\nRET",
+        )
+        .unwrap();
+        let config = MachineConfig {
+            ram: Fidelity::Emulated,
+            extensions: Extensions {
+                stack: true,
+                ..Extensions::default()
+            },
+            ..MachineConfig::default()
+        };
+        let mut reference = ComputerEmulated::power_on_with_config(program.clone(), config);
+        let mut under_test = Computer::power_on_with_config(program, config);
+
+        let mut deepest = 0;
+        for step in 0..80 {
+            if let Err(divergence) = run_lockstep(&mut reference, &mut under_test, 1) {
+                panic!("step {}: {}", step, divergence);
+            }
+            assert_eq!(
+                reference.stack_pointer(),
+                under_test.stack_pointer(),
+                "step {}",
+                step
+            );
+            deepest = deepest.max(under_test.stack_pointer().unwrap());
+        }
+
+        assert_eq!(under_test.read_memory(0), 15);
+        assert_eq!(under_test.read_memory(1), 256);
+        // 5 calls, and 5 pushes of n in between
+        assert_eq!(deepest, 256 + 11);
+        // the return address of the first call
+        assert_eq!(under_test.read_memory(256), 7);
+    }
+
+    #[test]
+    fn test_lockstep_interrupts() {
+        use super::*;
+        use crate::{
+            assembler::asm_to_binary,
+            machine::{
+                config::{Extensions, Fidelity},
+                devices::timer::{Timer, TIMER_ADDRESS},
+                key::Key,
+                InterruptState,
+            },
+        };
+
+        // the handler at 2 counts the interrupts in R0 and copies the keyboard into R1,
+        // the main loop starts a 2 ms countdown, and counts in R2
+        let program = asm_to_binary(
+            "@MAIN\n0;JMP\n@R0\nM=M+1\n@KBD\nD=M\n@R1\nM=D\nRETI\n\
+             (MAIN)\n@2\nD=A\n@24580\nM=D\n(LOOP)\n@R2\nM=M+1\n@LOOP\n0;JMP",
+        )
+        .unwrap();
+        let config = MachineConfig {
+            ram: Fidelity::Emulated,
+            extensions: Extensions {
+                interrupt_vector: Some(2),
+                ..Extensions::default()
+            },
+            ..MachineConfig::default()
+        };
+        let mut reference = ComputerEmulated::power_on_with_config(program.clone(), config);
+        let mut under_test = Computer::power_on_with_config(program, config);
+
+        let mut handled = Vec::new();
+        for step in 0..80 {
+            for machine in [&mut reference as &mut dyn Machine, &mut under_test] {
+                match step {
+                    0 => machine
+                        .attach_device(TIMER_ADDRESS, Box::new(Timer::new(10, 0)))
+                        .unwrap(),
+                    5 => machine.key_down(Key::Char('A')),
+                    50 => machine.key_up(Key::Char('A')),
+                    _ => {}
+                }
+            }
+
+            if let Err(divergence) = run_lockstep(&mut reference, &mut under_test, 1) {
+                panic!("step {}: {}", step, divergence);
+            }
+            assert_eq!(
+                reference.interrupt_state(),
+                under_test.interrupt_state(),
+                "step {}",
+                step
+            );
+            if under_test.cpu_state().pc == 2 {
+                handled.push((step, under_test.interrupt_state().return_address));
+            }
+        }
+
+        // the key press, the countdown and the key release
+        assert_eq!(under_test.read_memory(0), 3, "handled: {:?}", handled);
+        assert_eq!(under_test.read_memory(1), 0);
+        assert!(
+            under_test.read_memory(2) > 5,
+            "{}",
+            under_test.read_memory(2)
+        );
+        assert!(!under_test.interrupt_state().in_handler);
+
+        // a warm reset clears the whole interrupt state, also the shadow register
+        assert_ne!(under_test.interrupt_state().return_address, 0);
+        reference.reset();
+        under_test.reset();
+        assert_eq!(under_test.interrupt_state(), InterruptState::default());
+        assert_eq!(reference.interrupt_state(), under_test.interrupt_state());
+    }
+
+    #[test]
+    fn test_lockstep_power_on_seed_and_resets() {
+        use super::*;
+        use crate::{assembler::asm_to_binary, machine::config::Extensions};
+
+        // forgets to initialise x: R0 = x + 1 counts from the garbage
+        let program = asm_to_binary("@x\nMD=M+1\n@R0\nM=D\n(END)\n@END\n0;JMP").unwrap();
+        let config = MachineConfig {
+            extensions: Extensions {
+                stack: true,
+                ..Extensions::default()
+            },
+            power_on_seed: Some(48),
+            ..MachineConfig::default()
+        };
+        let mut reference = ComputerEmulated::power_on_with_config(program.clone(), config);
+        let mut under_test = Computer::power_on_with_config(program, config);
+
+        let snapshot = |machine: &dyn Machine| {
+            let memory: Vec<i16> = (0..24576)
+                .map(|address| machine.read_memory(address))
+                .collect();
+            (machine.cpu_state(), machine.stack_pointer(), memory)
+        };
+        let power_on = snapshot(&reference);
+        assert_eq!(power_on, snapshot(&under_test));
+        assert_eq!(power_on.0.pc, 0);
+        assert_ne!(power_on.0.a, 0);
+        assert!(power_on.2.iter().filter(|word| **word == 0).count() < 10);
+        let x = power_on.2[16];
+
+        if let Err(divergence) = run_lockstep(&mut reference, &mut under_test, 6) {
+            panic!("{}", divergence);
+        }
+        assert_eq!(under_test.read_memory(0), x.wrapping_add(1));
+
+        // the warm reset runs the program again on the same memory
+        for machine in [&mut reference as &mut dyn Machine, &mut under_test] {
+            machine.reset();
+            assert_eq!(machine.cpu_state().pc, 0);
+            assert_eq!(machine.read_memory(16), x.wrapping_add(1));
+        }
+        if let Err(divergence) = run_lockstep(&mut reference, &mut under_test, 6) {
+            panic!("after reset: {}", divergence);
+        }
+        assert_eq!(under_test.read_memory(0), x.wrapping_add(2));
+
+        // the same garbage again after the power cycle
+        for machine in [&mut reference as &mut dyn Machine, &mut under_test] {
+            machine.power_cycle();
+            assert_eq!(snapshot(machine), power_on);
+        }
+        if let Err(divergence) = run_lockstep(&mut reference, &mut under_test, 6) {
+            panic!("after power cycle: {}", divergence);
+        }
+
+        // without a seed, everything starts from zero
+        let zeros = Computer::power_on_with_config(vec![], MachineConfig::default());
+        assert_eq!(snapshot(&zeros).2, vec![0; 24576]);
+    }
+}
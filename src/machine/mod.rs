@@ -0,0 +1,293 @@
+// Common interface for the different implementations of the Hack computer.
+//
+// The gate-level `hack_computer::computer::Computer` shows how the computer is built,
+// but it's far too slow for interactive programs.
+// `emulated_parts::computer_emulated::ComputerEmulated` runs the same programs on words.
+// GUI and tests can choose either one through the `Machine` trait.
+
+pub mod config;
+pub mod device;
+pub mod devices;
+pub mod framebuffer;
+pub mod instruction;
+pub mod key;
+pub mod lockstep;
+pub mod profiler;
+pub mod protection;
+pub mod runner;
+pub mod shadow;
+
+use crate::{
+    emulated_parts::computer_emulated::ComputerEmulated, hack_computer::computer::Computer,
+};
+
+use self::{
+    config::{Extensions, MachineConfig, MemoryMap},
+    device::Device,
+    framebuffer::Framebuffer,
+    key::Key,
+};
+
+// The Hack memory map. `config::MemoryMap` can describe other ones.
+
+/// Number of words in the instruction memory.
+pub const ROM_SIZE: usize = 32768;
+
+/// First address of the screen memory map.
+pub const SCREEN_ADDRESS: usize = 16384;
+
+/// Address of the keyboard register.
+pub const KEYBOARD_ADDRESS: usize = 24576;
+
+/// Number of words in the data memory: RAM, screen and keyboard.
+pub const MEMORY_SIZE: usize = KEYBOARD_ADDRESS + 1;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CpuState {
+    /// address register
+    pub a: i16,
+
+    /// data register
+    pub d: i16,
+
+    /// program counter
+    pub pc: i16,
+}
+
+/// State of the interrupt extension. See `config::Extensions`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct InterruptState {
+    /// a request is waiting to be taken
+    pub pending: bool,
+
+    /// the handler is running, and has not returned with `RETI` yet
+    pub in_handler: bool,
+
+    /// where `RETI` continues, from the shadow register
+    pub return_address: i16,
+}
+
+/// What happened during one instruction.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StepInfo {
+    /// address of the executed instruction
+    pub pc: i16,
+    pub instruction: i16,
+
+    /// address and value, if the instruction wrote into the data memory
+    pub memory_write: Option<(usize, i16)>,
+}
+
+pub trait Machine {
+    /// Executes one instruction.
+    fn step(&mut self) -> StepInfo;
+
+    fn cpu_state(&self) -> CpuState;
+
+    /// Reads one word from the data memory. Does not tick the clock.
+    fn read_memory(&self, address: usize) -> i16;
+
+    /// Reads one word from the instruction memory, as the CPU fetches it.
+    fn read_rom(&self, address: usize) -> i16;
+
+    /// Queues a key press. The keyboard register shows it from the next instruction on.
+    fn key_down(&mut self, key: Key);
+
+    /// Queues a key release.
+    fn key_up(&mut self, key: Key);
+
+    /// Maps the device into the data memory at `base`. See `device::DeviceMap::attach`.
+    fn attach_device(&mut self, base: usize, device: Box<dyn Device>) -> Result<(), String>;
+
+    /// Where the RAM, screen and keyboard are.
+    fn memory_map(&self) -> MemoryMap {
+        MemoryMap::default()
+    }
+
+    fn extensions(&self) -> Extensions {
+        Extensions::default()
+    }
+
+    /// Always the default, when the interrupts are not enabled.
+    fn interrupt_state(&self) -> InterruptState {
+        InterruptState::default()
+    }
+
+    /// Whether an interrupt is pending, or an attached device may still request one.
+    /// Always false, when the interrupts are not enabled. Key presses are not counted:
+    /// only the host knows, whether more will come.
+    fn may_interrupt(&self) -> bool {
+        false
+    }
+
+    /// `None` without the stack extension.
+    fn stack_pointer(&self) -> Option<i16> {
+        None
+    }
+
+    /// Warm reset: the program starts again from ROM[0], and outside of an interrupt handler.
+    /// The memory and the other registers keep their values.
+    fn reset(&mut self);
+
+    /// Cold reset: the registers and the memories start again like at power on,
+    /// see `MachineConfig::power_on_seed`. The ROM and the attached devices stay.
+    fn power_cycle(&mut self);
+
+    fn run(&mut self, steps: usize) {
+        for _ in 0..steps {
+            self.step();
+        }
+    }
+
+    fn get_ram(&self, start: usize, end: usize) -> Vec<(usize, i16)> {
+        (start..end.min(self.memory_map().size()))
+            .map(|address| (address, self.read_memory(address)))
+            .collect()
+    }
+
+    /// The screen as 512 x 256 pixels, or in the resolution of the memory map.
+    fn framebuffer(&self) -> Framebuffer {
+        Framebuffer::from_memory_map(&self.memory_map(), |address| self.read_memory(address))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum Backend {
+    /// Built from logic gates. Slow, but shows every signal.
+    GateLevel,
+
+    /// Executes the instructions directly on words.
+    Emulated,
+}
+
+impl Backend {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backend::GateLevel => "Gate level",
+            Backend::Emulated => "Emulated",
+        }
+    }
+}
+
+pub fn power_on(backend: Backend, rom_disk: Vec<i16>) -> Box<dyn Machine> {
+    power_on_with_config(backend, MachineConfig::default(), rom_disk)
+        .expect("the Hack memory map is valid for every backend")
+}
+
+/// The config chooses the parts of the gate-level computer, and the memory map of the emulated one.
+/// The gate-level computer is wired for the Hack memory map, and does not accept any other.
+/// Both computers support the extensions.
+pub fn power_on_with_config(
+    backend: Backend,
+    config: MachineConfig,
+    rom_disk: Vec<i16>,
+) -> Result<Box<dyn Machine>, String> {
+    config.memory.validate()?;
+    config.extensions.validate(&config.memory)?;
+    if rom_disk.len() > config.memory.rom_capacity() {
+        return Err(format!(
+            "The program has {} words, but the ROM only {}",
+            rom_disk.len(),
+            config.memory.rom_capacity()
+        ));
+    }
+
+    let machine: Box<dyn Machine> = match backend {
+        Backend::GateLevel => {
+            if config.memory != MemoryMap::default() {
+                return Err("The gate-level computer supports only the Hack memory map".to_owned());
+            }
+            Box::new(Computer::power_on_with_config(rom_disk, config))
+        }
+        Backend::Emulated => Box::new(ComputerEmulated::power_on_with_config(rom_disk, config)),
+    };
+
+    Ok(machine)
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn test_backends_give_same_result() {
+        use super::*;
+
+        // @21, D=A, @2, D=D+A
+        let code = vec![21, -5104, 2, -8048];
+
+        let mut gate_level = power_on(Backend::GateLevel, code.clone());
+        let mut emulated = power_on(Backend::Emulated, code);
+
+        gate_level.run(4);
+        emulated.run(4);
+
+        assert_eq!(emulated.cpu_state().d, 23);
+        assert_eq!(gate_level.cpu_state(), emulated.cpu_state());
+    }
+
+    #[test]
+    fn test_power_on_with_memory_map() {
+        use super::*;
+        use crate::assembler::asm_to_binary_with_memory_map;
+
+        // 64 x 4 pixels, right after 1K words of RAM
+        let memory_map = MemoryMap {
+            ram_size: 1024,
+            screen_address: 1024,
+            screen_width: 64,
+            screen_height: 4,
+            keyboard_address: 1040,
+            rom_size: 64,
+            ..MemoryMap::default()
+        };
+        let config = MachineConfig {
+            memory: memory_map,
+            ..MachineConfig::all(config::Fidelity::Emulated)
+        };
+        let program =
+            asm_to_binary_with_memory_map("@SCREEN\nD=A\n@5\nD=D+A\nA=D\nM=1", &memory_map)
+                .unwrap();
+
+        assert!(power_on_with_config(Backend::GateLevel, config, program.clone()).is_err());
+        assert!(power_on_with_config(Backend::Emulated, config, vec![0; 65]).is_err());
+
+        let mut machine = power_on_with_config(Backend::Emulated, config, program).unwrap();
+        machine.run(6);
+
+        // SCREEN + 5 is the second word of row 1
+        let framebuffer = machine.framebuffer();
+        assert_eq!((framebuffer.width(), framebuffer.height()), (64, 4));
+        assert!(framebuffer.pixel(1, 16));
+        assert_eq!(machine.get_ram(1020, 2000).len(), 21);
+    }
+
+    #[test]
+    fn test_backends_share_devices() {
+        use super::*;
+        use crate::{assembler::asm_to_binary, machine::device::test::TestDevice};
+        use std::{cell::RefCell, rc::Rc};
+
+        let program = asm_to_binary("@24578\nM=-1\n@24577\nD=M\n@24579\nM=D").unwrap();
+
+        for backend in [Backend::GateLevel, Backend::Emulated] {
+            let device = Rc::new(RefCell::new(TestDevice::default()));
+            let mut machine = power_on(backend, program.clone());
+            machine
+                .attach_device(24577, Box::new(device.clone()))
+                .unwrap();
+            machine.run(6);
+
+            // the device counted 3 cycles before D=M, and the RAM after the keyboard is not writable
+            assert_eq!(machine.cpu_state().d, 3, "{}", backend.name());
+            assert_eq!(device.borrow().strobes, vec![0], "{}", backend.name());
+            assert_eq!(
+                device.borrow().last_write,
+                Some((1, -1)),
+                "{}",
+                backend.name()
+            );
+            assert_eq!(device.borrow().cycles, 6, "{}", backend.name());
+            assert_eq!(machine.read_memory(24577), 6, "{}", backend.name());
+            assert_eq!(machine.read_memory(24579), 0, "{}", backend.name());
+        }
+    }
+}
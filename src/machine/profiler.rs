@@ -0,0 +1,487 @@
+// Execution profiler
+//
+// Wraps the stepping of any `Machine` and counts what the instructions did.
+// Every Hack instruction takes exactly one clock cycle, so the cycles spent at a ROM address
+// are the same as the number of times the instruction there was executed.
+// With bank switching, the instructions are counted by their position in the program,
+// see `MemoryMap::rom_image_address`, so the banks do not mix.
+// The instructions are decoded like the CPU executes them, with the extensions of the machine.
+
+use std::fmt::{self, Write};
+
+use super::{
+    config::{Extensions, MemoryMap, Region, BANK_SELECT_ADDRESS},
+    instruction::{Instruction, STACK_CALL, STACK_POP, STACK_RET},
+    shadow, CpuState, Machine, StepInfo, ROM_SIZE,
+};
+
+/// Accesses per part of the data memory.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RegionCounts {
+    /// RAM[0..16384) in the Hack memory map
+    pub ram: u64,
+
+    /// RAM[16384..24576)
+    pub screen: u64,
+
+    /// RAM[24576]
+    pub keyboard: u64,
+
+    /// addresses after the keyboard: attached devices, the bank registers, or nothing
+    pub unmapped: u64,
+}
+
+impl RegionCounts {
+    fn count(&mut self, region: Region) {
+        match region {
+            Region::Ram => self.ram += 1,
+            Region::Screen => self.screen += 1,
+            Region::Keyboard => self.keyboard += 1,
+            Region::BankSelect | Region::Unmapped => self.unmapped += 1,
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.ram + self.screen + self.keyboard + self.unmapped
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Counters {
+    /// also the number of clock cycles
+    pub instructions: u64,
+    pub a_instructions: u64,
+    pub c_instructions: u64,
+
+    /// C-instructions with jump bits, that jumped
+    pub jumps_taken: u64,
+
+    /// C-instructions with jump bits, that did not jump
+    pub jumps_not_taken: u64,
+
+    /// reads of M
+    pub reads: RegionCounts,
+
+    /// writes into M
+    pub writes: RegionCounts,
+}
+
+/// A backward jump, and the instructions it repeats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoopStats {
+    /// first instruction of the loop, the jump target
+    pub start: usize,
+
+    /// the jump instruction, that closes the loop
+    pub end: usize,
+
+    /// how many times the backward jump was taken
+    pub iterations: u64,
+
+    /// cycles spent in the instructions start..=end
+    pub cycles: u64,
+}
+
+pub struct Profiler {
+    memory_map: MemoryMap,
+    extensions: Extensions,
+    counters: Counters,
+
+    /// executions per position in the program, or per RAM address in the Von Neumann mode
+    hits: Vec<u64>,
+
+    /// taken backward jumps: (from, to) -> count
+    back_jumps: Vec<((usize, usize), u64)>,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::with_memory_map(MemoryMap::default())
+    }
+
+    /// The memory map decides, which region a memory access is counted in.
+    pub fn with_memory_map(memory_map: MemoryMap) -> Self {
+        Self::with_config(memory_map, Extensions::default())
+    }
+
+    /// The extensions decide, how the instructions are decoded.
+    pub fn with_config(memory_map: MemoryMap, extensions: Extensions) -> Self {
+        Self {
+            memory_map,
+            extensions,
+            counters: Counters::default(),
+            hits: vec![0; memory_map.rom_capacity().max(ROM_SIZE)],
+            back_jumps: Vec::new(),
+        }
+    }
+
+    /// Steps the machine once, and records the instruction.
+    pub fn step(&mut self, machine: &mut dyn Machine) -> StepInfo {
+        let before = machine.cpu_state();
+        let rom_bank = match self.memory_map.rom_banks {
+            1 => 0,
+            _ => machine.read_memory(BANK_SELECT_ADDRESS) as u16 as usize,
+        };
+        let sp = machine.stack_pointer().unwrap_or(0);
+        let info = machine.step();
+        let after = machine.cpu_state();
+
+        self.record(before, sp, rom_bank, &info, after);
+
+        info
+    }
+
+    pub fn run(&mut self, machine: &mut dyn Machine, steps: usize) {
+        for _ in 0..steps {
+            self.step(machine);
+        }
+    }
+
+    /// Records one executed instruction.
+    /// `before` and `after` are the CPU states around the instruction, `sp` is the stack pointer
+    /// before it, and `rom_bank` was selected, when it was fetched.
+    pub fn record(
+        &mut self,
+        before: CpuState,
+        sp: i16,
+        rom_bank: usize,
+        info: &StepInfo,
+        after: CpuState,
+    ) {
+        let pc = self.program_address(rom_bank, info.pc);
+
+        self.counters.instructions += 1;
+        self.hits[pc] += 1;
+
+        let instruction = shadow::executed(info.instruction, self.extensions);
+        if shadow::reads_memory(info.instruction, self.extensions) {
+            // POP and RET take the top of the stack, everything else M
+            let address = match instruction {
+                Instruction::Stack {
+                    op: STACK_POP | STACK_RET,
+                    ..
+                } => sp.wrapping_sub(1),
+                _ => before.a,
+            };
+            let address = address as u16 as usize & 0x7FFF;
+            self.counters.reads.count(self.memory_map.region(address));
+        }
+
+        match instruction {
+            Instruction::A(_) => self.counters.a_instructions += 1,
+            Instruction::Reti => self.counters.c_instructions += 1,
+            Instruction::Stack { op, .. } => {
+                self.counters.c_instructions += 1;
+                if op == STACK_CALL || op == STACK_RET {
+                    self.count_taken_jump(pc, rom_bank, after);
+                }
+            }
+            Instruction::C { jump, .. } | Instruction::Shift { jump, .. } => {
+                self.counters.c_instructions += 1;
+
+                if jump != 0 {
+                    // A jump to the next instruction looks the same either way,
+                    // and it's counted as taken.
+                    if after.pc == before.a & 0x7FFF {
+                        self.count_taken_jump(pc, rom_bank, after);
+                    } else {
+                        self.counters.jumps_not_taken += 1;
+                    }
+                }
+            }
+        }
+
+        if let Some((address, _)) = info.memory_write {
+            self.counters.writes.count(self.memory_map.region(address));
+        }
+    }
+
+    /// Index into `hits` of the instruction address.
+    fn program_address(&self, rom_bank: usize, address: i16) -> usize {
+        let address = address as u16 as usize & 0x7FFF;
+        if address < self.memory_map.rom_size {
+            self.memory_map.rom_image_address(rom_bank, address)
+        } else {
+            address
+        }
+    }
+
+    fn count_taken_jump(&mut self, pc: usize, rom_bank: usize, after: CpuState) {
+        let target = self.program_address(rom_bank, after.pc);
+        self.counters.jumps_taken += 1;
+        if target <= pc {
+            self.count_back_jump(pc, target);
+        }
+    }
+
+    fn count_back_jump(&mut self, from: usize, to: usize) {
+        match self
+            .back_jumps
+            .iter_mut()
+            .find(|(jump, _)| *jump == (from, to))
+        {
+            Some((_, count)) => *count += 1,
+            None => self.back_jumps.push(((from, to), 1)),
+        }
+    }
+
+    pub fn counters(&self) -> Counters {
+        self.counters
+    }
+
+    /// Number of times the instruction at the position in the program was executed.
+    pub fn hits(&self, address: usize) -> u64 {
+        self.hits.get(address).copied().unwrap_or(0)
+    }
+
+    /// The instructions sorted by the cycles spent in them, most first.
+    pub fn hottest_addresses(&self, count: usize) -> Vec<(usize, u64)> {
+        let mut addresses: Vec<(usize, u64)> = self
+            .hits
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|(_, hits)| *hits > 0)
+            .collect();
+        addresses.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        addresses.truncate(count);
+
+        addresses
+    }
+
+    /// Loops sorted by the cycles spent in them, most first.
+    /// Nested loops are listed separately, so the cycles of an inner loop are also in the outer one.
+    pub fn hottest_loops(&self, count: usize) -> Vec<LoopStats> {
+        let mut loops: Vec<LoopStats> = self
+            .back_jumps
+            .iter()
+            .map(|((end, start), iterations)| LoopStats {
+                start: *start,
+                end: *end,
+                iterations: *iterations,
+                cycles: self.hits[*start..=*end].iter().sum(),
+            })
+            .collect();
+        loops.sort_by(|a, b| b.cycles.cmp(&a.cycles).then(a.start.cmp(&b.start)));
+        loops.truncate(count);
+
+        loops
+    }
+
+    /// Human readable summary. `rom` is used for showing the instructions.
+    pub fn report(&self, rom: &[i16], count: usize) -> String {
+        let mut report = String::new();
+        self.write_report(&mut report, rom, count)
+            .expect("writing into a string does not fail");
+
+        report
+    }
+
+    fn write_report(&self, f: &mut String, rom: &[i16], count: usize) -> fmt::Result {
+        let c = &self.counters;
+        let total = c.instructions.max(1);
+        let percent = |cycles: u64| cycles as f64 * 100.0 / total as f64;
+        let instruction = |address: usize| {
+            rom.get(address)
+                .map(|word| Instruction::decode(*word).to_string())
+                .unwrap_or_default()
+        };
+
+        writeln!(
+            f,
+            "Instructions: {} (A: {}, C: {})",
+            c.instructions, c.a_instructions, c.c_instructions
+        )?;
+        writeln!(
+            f,
+            "Jumps: {} taken, {} not taken",
+            c.jumps_taken, c.jumps_not_taken
+        )?;
+        for (name, regions) in [("Reads", c.reads), ("Writes", c.writes)] {
+            writeln!(
+                f,
+                "{}: RAM {}, screen {}, keyboard {}, unmapped {}",
+                name, regions.ram, regions.screen, regions.keyboard, regions.unmapped
+            )?;
+        }
+
+        writeln!(f, "\nHottest loops:")?;
+        for stats in self.hottest_loops(count) {
+            writeln!(
+                f,
+                "  ROM[{}..={}] `{}` ... `{}`: {} iterations, {} cycles ({:.1} %)",
+                stats.start,
+                stats.end,
+                instruction(stats.start),
+                instruction(stats.end),
+                stats.iterations,
+                stats.cycles,
+                percent(stats.cycles)
+            )?;
+        }
+
+        writeln!(f, "\nHottest instructions:")?;
+        for (address, hits) in self.hottest_addresses(count) {
+            writeln!(
+                f,
+                "  ROM[{}] `{}`: {} cycles ({:.1} %)",
+                address,
+                instruction(address),
+                hits,
+                percent(hits)
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn test_profile_screen_fill() {
+        use super::*;
+        use crate::{
+            assembler::asm_to_binary, emulated_parts::computer_emulated::ComputerEmulated,
+        };
+
+        let program = asm_to_binary(include_str!("../../specs/project 4/task_b.asm")).unwrap();
+        let mut machine = ComputerEmulated::power_on(program.clone());
+        let mut profiler = Profiler::new();
+        profiler.run(&mut machine, 20000);
+
+        let counters = profiler.counters();
+        assert_eq!(counters.instructions, 20000);
+        assert_eq!(
+            counters.a_instructions + counters.c_instructions,
+            counters.instructions
+        );
+
+        // the loop body writes one word of the screen per iteration
+        let loops = profiler.hottest_loops(3);
+        let fill = loops[0];
+        assert!(fill.iterations.abs_diff(counters.writes.screen) <= 1);
+        assert!(fill.cycles > 19000);
+        assert_eq!(counters.reads.keyboard, 0);
+
+        let report = profiler.report(&program, 3);
+        assert!(report.contains("Hottest loops:"));
+        assert!(report.contains(&format!("ROM[{}..={}]", fill.start, fill.end)));
+    }
+
+    #[test]
+    fn test_profile_counters() {
+        use super::*;
+        use crate::{
+            assembler::asm_to_binary, emulated_parts::computer_emulated::ComputerEmulated,
+        };
+
+        let program = asm_to_binary("@KBD\nD=M\n@5\nD;JNE\n@0\n0;JMP").unwrap();
+        let mut machine = ComputerEmulated::power_on(program);
+        let mut profiler = Profiler::new();
+        profiler.run(&mut machine, 12);
+
+        let counters = profiler.counters();
+        assert_eq!(counters.a_instructions, 6);
+        assert_eq!(counters.c_instructions, 6);
+        assert_eq!(counters.reads.keyboard, 2);
+        assert_eq!(counters.reads.total(), 2);
+        assert_eq!(counters.jumps_taken, 2);
+        assert_eq!(counters.jumps_not_taken, 2);
+        assert_eq!(profiler.hits(3), 2);
+        assert_eq!(
+            profiler.hottest_loops(5),
+            vec![LoopStats {
+                start: 0,
+                end: 5,
+                iterations: 2,
+                cycles: 12
+            }]
+        );
+    }
+
+    #[test]
+    fn test_profile_stack() {
+        use super::*;
+        use crate::{
+            assembler::asm_to_binary, emulated_parts::computer_emulated::ComputerEmulated,
+            machine::config::MachineConfig,
+        };
+
+        let program = asm_to_binary(
+            "@256\nD=A\nSP=D\n@SUB\nCALL\n(END)\n@END\n0;JMP\n(SUB)\nPUSH D\nPOP D\nRET",
+        )
+        .unwrap();
+        let config = MachineConfig {
+            extensions: Extensions {
+                stack: true,
+                ..Extensions::default()
+            },
+            ..MachineConfig::default()
+        };
+        let mut machine = ComputerEmulated::power_on_with_config(program, config);
+        let mut profiler = Profiler::with_config(config.memory, config.extensions);
+        profiler.run(&mut machine, 12);
+
+        // CALL and PUSH write the stack, POP and RET read it
+        let counters = profiler.counters();
+        assert_eq!(counters.writes.ram, 2);
+        assert_eq!(counters.reads.ram, 2);
+        // CALL, RET, and the end loop twice
+        assert_eq!(counters.jumps_taken, 4);
+        assert_eq!(counters.jumps_not_taken, 0);
+        let returns = profiler.hottest_loops(5);
+        assert!(returns
+            .iter()
+            .any(|stats| (stats.start, stats.end) == (5, 9)));
+
+        // without the extensions, the prefix 100 is a C-instruction: D=M reads the keyboard
+        let word = (0xFC10u16 & !0x6000) as i16;
+        let mut machine = ComputerEmulated::power_on(vec![24576, word]);
+        let mut profiler = Profiler::new();
+        profiler.run(&mut machine, 2);
+        assert_eq!(profiler.counters().reads.keyboard, 1);
+        assert_eq!(profiler.counters().c_instructions, 1);
+    }
+
+    #[test]
+    fn test_profile_rom_banks() {
+        use super::*;
+        use crate::{
+            assembler::asm_to_binary_with_memory_map,
+            emulated_parts::computer_emulated::ComputerEmulated, machine::config::MachineConfig,
+        };
+
+        // the ROM window is ROM[32..64), the code after the far jump runs in bank 1
+        let memory_map = MemoryMap {
+            rom_size: 64,
+            rom_banks: 2,
+            ..MemoryMap::default()
+        };
+        let program = asm_to_binary_with_memory_map(
+            "FARJMP FAR\n.bank 1\n(FAR)\n@R0\nM=1\n(END)\n@END\n0;JMP",
+            &memory_map,
+        )
+        .unwrap();
+        let config = MachineConfig {
+            memory: memory_map,
+            ..MachineConfig::default()
+        };
+        let mut machine = ComputerEmulated::power_on_with_config(program.clone(), config);
+        let mut profiler = Profiler::with_memory_map(memory_map);
+        profiler.run(&mut machine, 20);
+
+        // ROM[32] of bank 1 is at 64 in the program, bank 0 has nothing there
+        assert_eq!(profiler.hits(64), 1);
+        assert_eq!(profiler.hits(32), 0);
+        let end_loop = profiler.hottest_loops(1)[0];
+        assert_eq!((end_loop.start, end_loop.end), (66, 67));
+        assert!(profiler.report(&program, 1).contains("ROM[66..=67] `@34`"));
+    }
+}
@@ -0,0 +1,299 @@
+// Memory protection
+//
+// Ranges of the data memory, that the program may not write, execute or touch at all,
+// like the protection bits of an MMU. E.g. a guarded stack region 256-2047 catches a stack,
+// that runs into the variables, and a read-only region from the keyboard on catches
+// a pointer loop, that runs past the end of the screen.
+//
+// The runner checks every access of an instruction before it executes it, and traps on the first
+// forbidden one, so the memory still holds the old value. No-execute only matters
+// in the Von Neumann mode, where the CPU fetches instructions from the RAM.
+// Like the shadow bits, the regions follow the addresses, not the RAM banks.
+
+use std::fmt;
+
+use super::{
+    config::{Extensions, MemoryMap},
+    shadow::{self, Location},
+    CpuState,
+};
+
+/// What a region forbids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// Writes trap.
+    ReadOnly,
+
+    /// Fetching instructions traps.
+    NoExecute,
+
+    /// Every access traps: reads, writes and fetching instructions.
+    Guarded,
+}
+
+impl Access {
+    pub const ALL: [Access; 3] = [Access::ReadOnly, Access::NoExecute, Access::Guarded];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Access::ReadOnly => "read-only",
+            Access::NoExecute => "no-execute",
+            Access::Guarded => "guarded",
+        }
+    }
+
+    fn forbids(&self, operation: Operation) -> bool {
+        matches!(
+            (self, operation),
+            (Access::ReadOnly, Operation::Write)
+                | (Access::NoExecute, Operation::Execute)
+                | (Access::Guarded, _)
+        )
+    }
+}
+
+/// How an instruction accesses the data memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Read,
+    Write,
+    Execute,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::Read => write!(f, "reads"),
+            Operation::Write => write!(f, "writes"),
+            Operation::Execute => write!(f, "executes"),
+        }
+    }
+}
+
+/// The data addresses from `start` to `end`, both included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Protection {
+    pub start: usize,
+    pub end: usize,
+    pub access: Access,
+}
+
+impl Protection {
+    /// Parses `start-end:access`, e.g. `256-2047:guarded`.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let error = || format!("Invalid region '{}', expected e.g. 256-2047:guarded", text);
+
+        let (range, name) = text.split_once(':').ok_or_else(error)?;
+        let (start, end) = range.split_once('-').ok_or_else(error)?;
+        let start = start.trim().parse().map_err(|_| error())?;
+        let end = end.trim().parse().map_err(|_| error())?;
+        let access = Access::ALL
+            .into_iter()
+            .find(|access| access.name() == name.trim())
+            .ok_or_else(error)?;
+        if start > end {
+            return Err(error());
+        }
+
+        Ok(Self { start, end, access })
+    }
+
+    pub fn contains(&self, address: usize) -> bool {
+        (self.start..=self.end).contains(&address)
+    }
+}
+
+impl fmt::Display for Protection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}:{}", self.start, self.end, self.access.name())
+    }
+}
+
+/// An access, that a region forbids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fault {
+    pub operation: Operation,
+
+    /// data address of the access
+    pub target: usize,
+    pub protection: Protection,
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} RAM[{}], in the {} region {}-{}",
+            self.operation,
+            self.target,
+            self.protection.access.name(),
+            self.protection.start,
+            self.protection.end
+        )
+    }
+}
+
+/// Data memory accesses of the instruction at `pc`: its fetch, its reads, and its write.
+/// `sp` is the stack pointer before it.
+pub fn accesses(
+    pc: usize,
+    word: i16,
+    state: CpuState,
+    sp: i16,
+    extensions: Extensions,
+    memory_map: MemoryMap,
+) -> Vec<(Operation, usize)> {
+    let mut accesses = Vec::new();
+    if memory_map.fetches_from_ram(pc) {
+        accesses.push((Operation::Execute, pc));
+    }
+    for location in shadow::reads(word, state, sp, extensions) {
+        if let Location::Memory(address) = location {
+            accesses.push((Operation::Read, address));
+        }
+    }
+    if let Some(address) = shadow::writes(word, state, sp, extensions) {
+        accesses.push((Operation::Write, address));
+    }
+
+    accesses
+}
+
+/// The first access, that one of the regions forbids.
+pub fn find_fault(
+    protections: &[Protection],
+    accesses: impl IntoIterator<Item = (Operation, usize)>,
+) -> Option<Fault> {
+    accesses.into_iter().find_map(|(operation, target)| {
+        protections
+            .iter()
+            .find(|protection| protection.contains(target) && protection.access.forbids(operation))
+            .map(|&protection| Fault {
+                operation,
+                target,
+                protection,
+            })
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        assembler::asm_to_binary_with_memory_map,
+        emulated_parts::computer_emulated::ComputerEmulated,
+        machine::{config::MachineConfig, Machine},
+    };
+
+    #[test]
+    fn test_protection_parse() {
+        assert_eq!(
+            Protection::parse("256-2047:guarded"),
+            Ok(Protection {
+                start: 256,
+                end: 2047,
+                access: Access::Guarded
+            })
+        );
+        let keyboard = Protection::parse("24576-32767:read-only").unwrap();
+        assert_eq!(keyboard.to_string(), "24576-32767:read-only");
+        assert!(keyboard.contains(32767) && !keyboard.contains(24575));
+
+        for invalid in ["256-2047", "256:guarded", "2047-256:guarded", "0-1:secret"] {
+            assert!(Protection::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    /// The first fault, while the machine runs the program.
+    fn first_fault(
+        program: &str,
+        memory_map: MemoryMap,
+        protections: &[Protection],
+    ) -> Option<(usize, Fault)> {
+        let program = asm_to_binary_with_memory_map(program, &memory_map).unwrap();
+        let config = MachineConfig {
+            memory: memory_map,
+            ..MachineConfig::default()
+        };
+        let mut computer = ComputerEmulated::power_on_with_config(program, config);
+        for _ in 0..50 {
+            let state = computer.cpu_state();
+            let pc = state.pc as u16 as usize;
+            let word = computer.read_rom(pc);
+            let accesses = accesses(pc, word, state, 0, config.extensions, memory_map);
+            if let Some(fault) = find_fault(protections, accesses) {
+                return Some((pc, fault));
+            }
+            computer.step();
+        }
+
+        None
+    }
+
+    #[test]
+    fn test_protection_faults() {
+        let hack = MemoryMap::default();
+        let guarded = Protection::parse("256-2047:guarded").unwrap();
+        let read_only = Protection::parse("0-15:read-only").unwrap();
+        let regions = [guarded, read_only];
+
+        assert_eq!(
+            first_fault("@300\nD=M", hack, &regions),
+            Some((
+                1,
+                Fault {
+                    operation: Operation::Read,
+                    target: 300,
+                    protection: guarded
+                }
+            ))
+        );
+        assert_eq!(
+            first_fault("@R0\nD=M\n@R1\nM=D", hack, &regions),
+            Some((
+                3,
+                Fault {
+                    operation: Operation::Write,
+                    target: 1,
+                    protection: read_only
+                }
+            ))
+        );
+        // A=M only reads the pointer, not the address it points to
+        assert_eq!(first_fault("@16\nA=M\n@2048\nM=0", hack, &regions), None);
+
+        // the Von Neumann mode executes the RAM after the ROM
+        let von_neumann = MemoryMap {
+            rom_size: 1024,
+            execute_ram: true,
+            ..MemoryMap::default()
+        };
+        let no_execute = Protection::parse("1024-2047:no-execute").unwrap();
+        assert_eq!(
+            first_fault("@1024\n0;JMP", von_neumann, &[no_execute]),
+            Some((
+                1024,
+                Fault {
+                    operation: Operation::Execute,
+                    target: 1024,
+                    protection: no_execute
+                }
+            ))
+        );
+        assert_eq!(first_fault("@1024\n0;JMP", von_neumann, &[]), None);
+
+        // a guarded region may not be executed either
+        let guarded = Protection::parse("1024-2047:guarded").unwrap();
+        assert_eq!(
+            first_fault("@1024\n0;JMP", von_neumann, &[guarded]),
+            Some((
+                1024,
+                Fault {
+                    operation: Operation::Execute,
+                    target: 1024,
+                    protection: guarded
+                }
+            ))
+        );
+    }
+}
@@ -0,0 +1,834 @@
+// Runner
+//
+// Steps a machine on behalf of the GUI and the tests, and watches what it executes.
+// The machines themselves run any word they are given, like the real hardware would.
+// The runner checks each instruction before it runs, and stops on the ones
+// that are not part of the Hack machine language.
+// It also notices, when the program has finished and only waits in an idle loop.
+// Optionally, it reports reads of registers and memory, that nothing has written, see `shadow`,
+// and stops on accesses, that the memory protection regions forbid, see `protection`.
+
+use std::fmt;
+
+use super::{
+    config::{Extensions, BANK_SELECT_ADDRESS},
+    instruction::{
+        comp_mnemonic, shift_mnemonic, Instruction, JUMP_EQ, JUMP_GT, JUMP_LT, STACK_CALL,
+        STACK_GET_SP, STACK_PUSH, STACK_RET, STACK_SET_SP,
+    },
+    profiler::Profiler,
+    protection::{self, Fault, Protection},
+    shadow::{self, Location, Shadow},
+    CpuState, Machine, StepInfo,
+};
+use crate::emulated_parts::alu_emulated::{alu_emulated, shifter_emulated};
+
+/// What to do with illegal instructions.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum IllegalPolicy {
+    /// Execute them like the hardware does.
+    Ignore,
+
+    /// Execute them, but record a warning.
+    Warn,
+
+    /// Stop before executing them.
+    #[default]
+    Trap,
+}
+
+impl IllegalPolicy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            IllegalPolicy::Ignore => "Ignore",
+            IllegalPolicy::Warn => "Warn",
+            IllegalPolicy::Trap => "Trap",
+        }
+    }
+}
+
+/// An instruction, that is not part of the Hack machine language, or that misbehaves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trap {
+    /// C-instruction, whose bits 14 and 13 are not `11`.
+    IllegalPrefix { address: usize, word: i16 },
+
+    /// C-instruction, whose `a c1..c6` bits are not in the comp table.
+    UndefinedComp { address: usize, word: i16 },
+
+    /// Stack instruction with an undefined operation, or whose comp uses M.
+    IllegalStackInstruction { address: usize, word: i16 },
+
+    /// Jump to an address after the end of the loaded program, and not into the RAM,
+    /// where the Von Neumann mode executes the instructions.
+    JumpOutOfProgram {
+        address: usize,
+        word: i16,
+        target: usize,
+    },
+
+    /// The PC has reached the word right after the loaded program, so the program
+    /// has no end loop. `address` is the word after the last instruction.
+    RanOutOfProgram { address: usize, word: i16 },
+
+    /// Read of a register or a memory word, that nothing has written since power on.
+    UninitialisedRead {
+        address: usize,
+        word: i16,
+        location: Location,
+    },
+
+    /// Access to the data memory, that a protection region forbids.
+    ProtectionFault {
+        address: usize,
+        word: i16,
+        fault: Fault,
+    },
+}
+
+impl Trap {
+    /// ROM address of the offending instruction.
+    pub fn address(&self) -> usize {
+        match *self {
+            Trap::IllegalPrefix { address, .. }
+            | Trap::UndefinedComp { address, .. }
+            | Trap::IllegalStackInstruction { address, .. }
+            | Trap::JumpOutOfProgram { address, .. }
+            | Trap::RanOutOfProgram { address, .. }
+            | Trap::UninitialisedRead { address, .. }
+            | Trap::ProtectionFault { address, .. } => address,
+        }
+    }
+}
+
+impl fmt::Display for Trap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Trap::IllegalPrefix { address, word } => write!(
+                f,
+                "Illegal instruction at ROM[{}]: {:016b} (bits 14 and 13 of a C-instruction must be 11)",
+                address, word as u16
+            ),
+            Trap::UndefinedComp { address, word } => write!(
+                f,
+                "Undefined comp at ROM[{}]: {:016b} (comp {:07b} is not in the Hack table)",
+                address,
+                word as u16,
+                (word as u16 >> 6) & 0x7F
+            ),
+            Trap::IllegalStackInstruction { address, word } => write!(
+                f,
+                "Illegal stack instruction at ROM[{}]: {:016b} (the operation must be 000 to 101, and comp cannot use M)",
+                address, word as u16
+            ),
+            Trap::JumpOutOfProgram {
+                address,
+                word,
+                target,
+            } => write!(
+                f,
+                "Jump out of the program at ROM[{}]: {:016b} `{}` jumped to {}",
+                address,
+                word as u16,
+                Instruction::decode(word),
+                target
+            ),
+            Trap::RanOutOfProgram { address, word } => write!(
+                f,
+                "Ran out of the program at ROM[{}]: {:016b} is after the last instruction, and there is no end loop",
+                address, word as u16
+            ),
+            Trap::UninitialisedRead {
+                address,
+                word,
+                location,
+            } => write!(
+                f,
+                "Uninitialised read at ROM[{}]: `{}` reads {}, that nothing has written",
+                address,
+                Instruction::decode(word),
+                location
+            ),
+            Trap::ProtectionFault {
+                address,
+                word,
+                fault,
+            } => write!(
+                f,
+                "Protection fault at ROM[{}]: `{}` {}",
+                address,
+                Instruction::decode(word),
+                fault
+            ),
+        }
+    }
+}
+
+/// Whether the C-instruction jumps regardless of the D and M registers.
+fn always_jumps(comp: u16, jump: u16) -> bool {
+    match (comp_mnemonic(comp), jump) {
+        (_, 0b111) => true,
+        (Some("0"), jump) => jump & 0b010 != 0,
+        (Some("1"), jump) => jump & 0b001 != 0,
+        (Some("-1"), jump) => jump & 0b100 != 0,
+        _ => false,
+    }
+}
+
+/// Whether the jump bits of a C-instruction or a shift select the ALU output.
+fn jump_condition(jump: u16, zr: bool, ng: bool) -> bool {
+    (jump & JUMP_LT != 0 && ng)
+        || (jump & JUMP_EQ != 0 && zr)
+        || (jump & JUMP_GT != 0 && !zr && !ng)
+}
+
+/// Checks the instruction before it's executed.
+pub fn check_instruction(address: usize, word: i16) -> Option<Trap> {
+    let bits = word as u16;
+    if bits & 0x8000 == 0 {
+        return None;
+    }
+
+    if bits & 0x6000 != 0x6000 {
+        return Some(Trap::IllegalPrefix { address, word });
+    }
+
+    match Instruction::decode(word) {
+        Instruction::C { comp, .. } if comp_mnemonic(comp).is_none() => {
+            Some(Trap::UndefinedComp { address, word })
+        }
+        _ => None,
+    }
+}
+
+/// Same as `check_instruction`, but the extensions of the machine are legal too.
+pub fn check_instruction_with_extensions(
+    address: usize,
+    word: i16,
+    extensions: Extensions,
+) -> Option<Trap> {
+    match Instruction::decode(word) {
+        Instruction::Reti if extensions.interrupts() => None,
+        Instruction::Shift { comp, .. } if extensions.shifts => match shift_mnemonic(comp) {
+            Some(_) => None,
+            None => Some(Trap::UndefinedComp { address, word }),
+        },
+        Instruction::Stack { op, comp, .. } if extensions.stack => match op {
+            STACK_PUSH | STACK_SET_SP if comp & 0b1000000 != 0 => {
+                Some(Trap::IllegalStackInstruction { address, word })
+            }
+            STACK_PUSH | STACK_SET_SP if comp_mnemonic(comp).is_none() => {
+                Some(Trap::UndefinedComp { address, word })
+            }
+            STACK_PUSH..=STACK_GET_SP => None,
+            _ => Some(Trap::IllegalStackInstruction { address, word }),
+        },
+        _ => check_instruction(address, word),
+    }
+}
+
+pub struct Runner {
+    pub machine: Box<dyn Machine>,
+    pub profiler: Profiler,
+    pub policy: IllegalPolicy,
+
+    /// what to do with uninitialised reads, see `shadow`
+    pub uninitialised: IllegalPolicy,
+    shadow: Shadow,
+
+    /// regions of the data memory, whose forbidden accesses always trap
+    pub protection: Vec<Protection>,
+
+    /// the host sends key presses, which may interrupt the end loop, see `is_halted`
+    pub keyboard_interrupts: bool,
+
+    /// number of words loaded into the ROM
+    program_length: usize,
+
+    warnings: Vec<Trap>,
+    trap: Option<Trap>,
+}
+
+impl Runner {
+    pub fn new(machine: Box<dyn Machine>, program_length: usize, policy: IllegalPolicy) -> Self {
+        Self {
+            profiler: Profiler::with_config(machine.memory_map(), machine.extensions()),
+            shadow: Shadow::new(machine.memory_map(), machine.extensions()),
+            machine,
+            policy,
+            uninitialised: IllegalPolicy::Ignore,
+            protection: Vec::new(),
+            keyboard_interrupts: false,
+            program_length,
+            warnings: Vec::new(),
+            trap: None,
+        }
+    }
+
+    /// Executes one instruction. After a trap, the machine does not run anymore.
+    pub fn step(&mut self) -> Result<StepInfo, Trap> {
+        if let Some(trap) = self.trap {
+            return Err(trap);
+        }
+
+        let state = self.machine.cpu_state();
+        let address = state.pc as u16 as usize;
+        let word = self.machine.read_rom(address);
+        let extensions = self.machine.extensions();
+        let memory_map = self.machine.memory_map();
+        let fell_off =
+            self.program_address(address) == self.program_length || address == memory_map.rom_size;
+        if fell_off && !memory_map.fetches_from_ram(address) {
+            self.handle(Some(Trap::RanOutOfProgram { address, word }))?;
+        }
+        self.handle(check_instruction_with_extensions(address, word, extensions))?;
+
+        let sp = self.machine.stack_pointer().unwrap_or(0);
+        if let Some(target) = self.jump_target(word, state, sp) {
+            let outside = target >= memory_map.rom_size
+                || self.program_address(target) >= self.program_length;
+            if outside && !memory_map.fetches_from_ram(target) {
+                self.handle(Some(Trap::JumpOutOfProgram {
+                    address,
+                    word,
+                    target,
+                }))?;
+            }
+        }
+
+        if !self.protection.is_empty() {
+            let accesses = protection::accesses(address, word, state, sp, extensions, memory_map);
+            if let Some(fault) = protection::find_fault(&self.protection, accesses) {
+                self.handle_with(
+                    IllegalPolicy::Trap,
+                    Trap::ProtectionFault {
+                        address,
+                        word,
+                        fault,
+                    },
+                )?;
+            }
+        }
+
+        if self.uninitialised != IllegalPolicy::Ignore {
+            if let Some(location) = self.shadow.check(word, state, sp) {
+                self.handle_with(
+                    self.uninitialised,
+                    Trap::UninitialisedRead {
+                        address,
+                        word,
+                        location,
+                    },
+                )?;
+                // the warning comes only once
+                self.shadow.mark(location);
+            }
+        }
+
+        let info = self.profiler.step(self.machine.as_mut());
+        self.shadow.record(&info);
+
+        Ok(info)
+    }
+
+    /// Position in the program of the ROM address, in the selected ROM bank,
+    /// see `MemoryMap::rom_image_address`. Addresses after the ROM stay the same.
+    fn program_address(&self, address: usize) -> usize {
+        let memory_map = self.machine.memory_map();
+        if address >= memory_map.rom_size {
+            return address;
+        }
+        let rom_bank = match memory_map.rom_banks {
+            1 => 0,
+            _ => self.machine.read_memory(BANK_SELECT_ADDRESS) as u16 as usize,
+        };
+
+        memory_map.rom_image_address(rom_bank, address)
+    }
+
+    /// Where the instruction jumps, worked out from the state before it executes.
+    /// `None`, when it goes on with the next instruction. `sp` is the stack pointer.
+    fn jump_target(&self, word: i16, state: CpuState, sp: i16) -> Option<usize> {
+        let read = |address: i16| self.machine.read_memory(address as u16 as usize & 0x7FFF);
+        let y = |comp: u16| match comp & 0b1000000 {
+            0 => state.a,
+            _ => read(state.a),
+        };
+
+        let jumps = match shadow::executed(word, self.machine.extensions()) {
+            Instruction::C { comp, jump, .. } if jump != 0 => {
+                let (_, zr, ng) = alu_emulated(state.d, y(comp), comp & 0x3F);
+                jump_condition(jump, zr, ng)
+            }
+            Instruction::Shift { comp, jump, .. } if jump != 0 => {
+                let source = match comp & 0b1010000 {
+                    0b0010000 => state.d,
+                    _ => y(comp),
+                };
+                let (_, zr, ng) = shifter_emulated(source, comp & 0b0100000 != 0);
+                jump_condition(jump, zr, ng)
+            }
+            Instruction::Stack { op: STACK_CALL, .. } => true,
+            Instruction::Stack { op: STACK_RET, .. } => {
+                return Some(read(sp.wrapping_sub(1)) as u16 as usize & 0x7FFF);
+            }
+            _ => false,
+        };
+
+        jumps.then_some(state.a as u16 as usize & 0x7FFF)
+    }
+
+    /// Executes up to `steps` instructions. Stops at the first trap, or when the machine halts.
+    /// Returns the number of executed instructions.
+    pub fn run(&mut self, steps: usize) -> Result<usize, Trap> {
+        for executed in 0..steps {
+            if self.is_halted() {
+                return Ok(executed);
+            }
+            self.step()?;
+        }
+
+        Ok(steps)
+    }
+
+    /// Whether the machine is in an idle loop, that cannot change its state anymore.
+    ///
+    /// Hack programs end with `(END) @END 0;JMP`. The machine is halted,
+    /// when the next instruction is a jump without dest, that always jumps
+    /// either to itself, or to the A-instruction right before it, which loads its own address.
+    /// With interrupts, the loop waits for the next one: the machine is only halted,
+    /// when none is pending, no device may request one, and the host sends no key presses.
+    pub fn is_halted(&self) -> bool {
+        if self.machine.extensions().interrupts()
+            && (self.keyboard_interrupts || self.machine.may_interrupt())
+        {
+            return false;
+        }
+
+        let state = self.machine.cpu_state();
+        let pc = state.pc as u16 as usize;
+        let target = state.a as u16 as usize;
+
+        let Instruction::C {
+            comp,
+            dest: 0,
+            jump,
+        } = Instruction::decode(self.machine.read_rom(pc))
+        else {
+            return false;
+        };
+        if !always_jumps(comp, jump) {
+            return false;
+        }
+
+        target == pc || target + 1 == pc && self.machine.read_rom(target) == target as i16
+    }
+
+    /// Warm reset of the machine. After a trap, it runs again.
+    pub fn reset(&mut self) {
+        self.machine.reset();
+        self.trap = None;
+    }
+
+    /// Cold reset of the machine. After a trap, it runs again.
+    pub fn power_cycle(&mut self) {
+        self.machine.power_cycle();
+        self.shadow.clear();
+        self.trap = None;
+    }
+
+    fn handle(&mut self, trap: Option<Trap>) -> Result<(), Trap> {
+        match trap {
+            Some(trap) => self.handle_with(self.policy, trap),
+            None => Ok(()),
+        }
+    }
+
+    fn handle_with(&mut self, policy: IllegalPolicy, trap: Trap) -> Result<(), Trap> {
+        match policy {
+            IllegalPolicy::Ignore => Ok(()),
+            IllegalPolicy::Warn => {
+                self.warnings.push(trap);
+                Ok(())
+            }
+            IllegalPolicy::Trap => {
+                self.trap = Some(trap);
+                Err(trap)
+            }
+        }
+    }
+
+    /// The illegal instructions, that were executed with the `Warn` policy.
+    pub fn warnings(&self) -> &[Trap] {
+        &self.warnings
+    }
+
+    /// The trap, that stopped the machine.
+    pub fn trap(&self) -> Option<Trap> {
+        self.trap
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        emulated_parts::computer_emulated::ComputerEmulated,
+        machine::instruction::{DEST_D, RETI},
+    };
+
+    fn runner(program: Vec<i16>, policy: IllegalPolicy) -> Runner {
+        let length = program.len();
+        Runner::new(
+            Box::new(ComputerEmulated::power_on(program)),
+            length,
+            policy,
+        )
+    }
+
+    #[test]
+    fn test_check_instruction() {
+        // @5, D=A, 0;JMP
+        assert_eq!(check_instruction(0, 5), None);
+        assert_eq!(check_instruction(1, -5104), None);
+        assert_eq!(check_instruction(2, -5497), None);
+
+        // D=A with the prefix 101
+        let word = (0xA000 | 0b0110000 << 6 | 0b010 << 3) as i16;
+        assert_eq!(
+            check_instruction(3, word),
+            Some(Trap::IllegalPrefix { address: 3, word })
+        );
+
+        // comp 1111111 is not in the table
+        let word = (0xE000 | 0b1111111 << 6) as i16;
+        assert_eq!(
+            check_instruction(4, word),
+            Some(Trap::UndefinedComp { address: 4, word })
+        );
+
+        // RETI, the shifts and the stack are only legal with the extensions
+        let extensions = Extensions {
+            interrupt_vector: Some(0),
+            shifts: true,
+            stack: true,
+        };
+        assert_eq!(
+            check_instruction(5, RETI),
+            Some(Trap::IllegalPrefix {
+                address: 5,
+                word: RETI
+            })
+        );
+        assert_eq!(check_instruction_with_extensions(5, RETI, extensions), None);
+
+        // D=D<<, and the same with a comp, that is not a shift
+        let shift = Instruction::Shift {
+            comp: 0b0110000,
+            dest: DEST_D,
+            jump: 0,
+        }
+        .encode();
+        assert!(check_instruction(6, shift).is_some());
+        assert_eq!(
+            check_instruction_with_extensions(6, shift, extensions),
+            None
+        );
+        assert_eq!(
+            check_instruction_with_extensions(6, shift | 1 << 6, extensions),
+            Some(Trap::UndefinedComp {
+                address: 6,
+                word: shift | 1 << 6
+            })
+        );
+
+        // PUSH D, PUSH M, and the undefined operation 110
+        let push = |comp| {
+            Instruction::Stack {
+                op: 0,
+                comp,
+                dest: 0,
+            }
+            .encode()
+        };
+        assert!(check_instruction(7, push(0b0001100)).is_some());
+        assert_eq!(
+            check_instruction_with_extensions(7, push(0b0001100), extensions),
+            None
+        );
+        assert_eq!(
+            check_instruction_with_extensions(7, push(0b1110000), extensions),
+            Some(Trap::IllegalStackInstruction {
+                address: 7,
+                word: push(0b1110000)
+            })
+        );
+        assert_eq!(
+            check_instruction_with_extensions(7, push(0b0001100) | 0b110, extensions),
+            Some(Trap::IllegalStackInstruction {
+                address: 7,
+                word: push(0b0001100) | 0b110
+            })
+        );
+    }
+
+    #[test]
+    fn test_runner_policies() {
+        // @5, D=A, <D=A with the prefix 101>, @7
+        let illegal = (0xA000 | 0b0110000 << 6 | 0b010 << 3) as i16;
+        let program = vec![5, -5104, illegal, 7];
+
+        let mut trapping = runner(program.clone(), IllegalPolicy::Trap);
+        let trap = trapping.run(10).unwrap_err();
+        assert_eq!(trap.address(), 2);
+        assert_eq!(trapping.machine.cpu_state().pc, 2);
+        assert_eq!(trapping.step(), Err(trap));
+        assert!(trap.to_string().contains("ROM[2]: 1010110000010000"));
+
+        let mut warning = runner(program.clone(), IllegalPolicy::Warn);
+        warning.run(4).unwrap();
+        assert_eq!(warning.warnings().len(), 1);
+        assert_eq!(warning.machine.cpu_state().a, 7);
+
+        let mut ignoring = runner(program, IllegalPolicy::Ignore);
+        ignoring.run(4).unwrap();
+        assert!(ignoring.warnings().is_empty());
+    }
+
+    #[test]
+    fn test_runner_jump_out_of_program() {
+        // @100, 0;JMP
+        let mut jumping = runner(vec![100, -5497], IllegalPolicy::Trap);
+        let trap = jumping.run(10).unwrap_err();
+
+        assert_eq!(
+            trap,
+            Trap::JumpOutOfProgram {
+                address: 1,
+                word: -5497,
+                target: 100
+            }
+        );
+        assert!(trap.to_string().contains("`0;JMP` jumped to 100"));
+        // the jump has not been executed
+        assert_eq!(jumping.machine.cpu_state().pc, 1);
+
+        // @100, D=0, D;JNE does not jump, and then there is no end loop
+        let mut falling = runner(vec![100, -5488, -7419], IllegalPolicy::Trap);
+        let trap = falling.run(10).unwrap_err();
+        assert_eq!(
+            trap,
+            Trap::RanOutOfProgram {
+                address: 3,
+                word: 0
+            }
+        );
+        assert_eq!(falling.machine.cpu_state().d, 0);
+    }
+
+    #[test]
+    fn test_runner_out_of_program_in_a_bank() {
+        use crate::{
+            assembler::asm_to_binary_with_memory_map,
+            machine::config::{MachineConfig, MemoryMap},
+        };
+
+        // the window is ROM[16..32), bank 2 starts at 48 in the program
+        let memory_map = MemoryMap {
+            rom_size: 32,
+            rom_banks: 3,
+            ..MemoryMap::default()
+        };
+        let config = MachineConfig {
+            memory: memory_map,
+            ..MachineConfig::default()
+        };
+        let banked = |source: &str| {
+            let program = asm_to_binary_with_memory_map(source, &memory_map).unwrap();
+            let length = program.len();
+            let machine = ComputerEmulated::power_on_with_config(program, config);
+            Runner::new(Box::new(machine), length, IllegalPolicy::Trap)
+        };
+
+        // the code in bank 2 ends at ROM[18], at 50 in the program
+        let mut falling = banked("FARJMP FAR\n.bank 2\n(FAR)\n@1\nD=A");
+        assert_eq!(
+            falling.run(20),
+            Err(Trap::RanOutOfProgram {
+                address: 18,
+                word: 0
+            })
+        );
+
+        // ROM[20] of bank 2 is at 52, after the program
+        let mut jumping = banked("FARJMP FAR\n.bank 2\n(FAR)\n@20\n0;JMP");
+        assert_eq!(
+            jumping.run(20),
+            Err(Trap::JumpOutOfProgram {
+                address: 17,
+                word: -5497,
+                target: 20
+            })
+        );
+
+        // the same address in bank 0 is inside the program
+        let mut inside = banked("@20\n0;JMP\n.bank 2\n(FAR)\n@FAR\n0;JMP");
+        assert!(inside.run(1).is_ok());
+        assert!(inside.step().is_ok());
+    }
+
+    #[test]
+    fn test_runner_uninitialised_reads() {
+        use crate::assembler::asm_to_binary;
+
+        // sum is never set to 0, and the loop adds i to it twice
+        let program = asm_to_binary(
+            "@2\nD=A\n@i\nM=D\n(LOOP)\n@i\nD=M\n@sum\nM=D+M\n@i\nMD=M-1\n@LOOP\nD;JGT\n\
+             (END)\n@END\n0;JMP",
+        )
+        .unwrap();
+
+        let mut trapping = runner(program.clone(), IllegalPolicy::Ignore);
+        trapping.uninitialised = IllegalPolicy::Trap;
+        let trap = trapping.run(100).unwrap_err();
+        assert_eq!(
+            trap,
+            Trap::UninitialisedRead {
+                address: 7,
+                word: -3960,
+                location: Location::Memory(17)
+            }
+        );
+        assert!(trap
+            .to_string()
+            .contains("ROM[7]: `M=D+M` reads RAM[17], that nothing has written"));
+
+        // reported once, though the loop reads it again
+        let mut warning = runner(program.clone(), IllegalPolicy::Trap);
+        warning.uninitialised = IllegalPolicy::Warn;
+        warning.run(100).unwrap();
+        assert_eq!(warning.warnings(), &[trap]);
+
+        // a power cycle forgets the writes
+        warning.power_cycle();
+        warning.run(100).unwrap();
+        assert_eq!(warning.warnings(), &[trap, trap]);
+
+        let mut ignoring = runner(program, IllegalPolicy::Trap);
+        ignoring.run(100).unwrap();
+        assert!(ignoring.warnings().is_empty());
+    }
+
+    #[test]
+    fn test_runner_protection() {
+        use crate::{
+            assembler::asm_to_binary,
+            machine::protection::{Access, Operation},
+        };
+
+        // fills the screen, but the loop does not stop at the keyboard
+        let program = asm_to_binary(
+            "@SCREEN\nD=A\n@index\nM=D\n(LOOP)\n@index\nA=M\nM=-1\n@index\nM=M+1\n@LOOP\n0;JMP",
+        )
+        .unwrap();
+        let keyboard = Protection {
+            start: 24576,
+            end: 32767,
+            access: Access::ReadOnly,
+        };
+
+        let mut runner = runner(program, IllegalPolicy::Trap);
+        runner.protection = vec![keyboard];
+        let trap = runner.run(100_000).unwrap_err();
+        assert_eq!(
+            trap,
+            Trap::ProtectionFault {
+                address: 6,
+                word: -4472,
+                fault: Fault {
+                    operation: Operation::Write,
+                    target: 24576,
+                    protection: keyboard
+                }
+            }
+        );
+        assert!(trap
+            .to_string()
+            .contains("ROM[6]: `M=-1` writes RAM[24576], in the read-only region 24576-32767"));
+
+        // the screen is full, and nothing has been written after it
+        assert_eq!(runner.machine.read_memory(24575), -1);
+        assert_eq!(runner.machine.cpu_state().pc, 6);
+    }
+
+    #[test]
+    fn test_runner_halts_at_end_loop() {
+        use crate::assembler::asm_to_binary;
+
+        let program = asm_to_binary("@21\nD=A\n@2\nM=D\n(END)\n@END\n0;JMP").unwrap();
+        let mut end_loop = runner(program, IllegalPolicy::Trap);
+        assert!(!end_loop.is_halted());
+
+        assert_eq!(end_loop.run(1000), Ok(5));
+        assert!(end_loop.is_halted());
+        assert_eq!(end_loop.machine.cpu_state().pc, 5);
+        assert_eq!(end_loop.machine.read_memory(2), 21);
+        assert_eq!(end_loop.run(1000), Ok(0));
+
+        // the loop writes D, so it's not idle
+        let program = asm_to_binary("(LOOP)\n@LOOP\nD=D+1;JMP").unwrap();
+        let mut counting = runner(program, IllegalPolicy::Trap);
+        assert_eq!(counting.run(1000), Ok(1000));
+        assert!(!counting.is_halted());
+
+        // a conditional jump, whose condition is constant
+        let program = asm_to_binary("@0\n0;JEQ").unwrap();
+        let mut constant = runner(program, IllegalPolicy::Trap);
+        assert_eq!(constant.run(1000), Ok(1));
+    }
+
+    #[test]
+    fn test_runner_halts_with_interrupts() {
+        use crate::{
+            assembler::asm_to_binary,
+            machine::{
+                config::{Extensions, MachineConfig},
+                devices::timer::{Timer, TIMER_ADDRESS},
+                power_on_with_config, Backend,
+            },
+        };
+
+        // the handler at 2 counts the interrupts in R0, the main program starts
+        // a 2 ms countdown and waits in its end loop
+        let program = asm_to_binary(
+            "@MAIN\n0;JMP\n@R0\nM=M+1\nRETI\n\
+             (MAIN)\n@2\nD=A\n@24580\nM=D\n(END)\n@END\n0;JMP",
+        )
+        .unwrap();
+        let config = MachineConfig {
+            extensions: Extensions {
+                interrupt_vector: Some(2),
+                ..Extensions::default()
+            },
+            ..MachineConfig::default()
+        };
+
+        for backend in [Backend::GateLevel, Backend::Emulated] {
+            let mut machine = power_on_with_config(backend, config, program.clone()).unwrap();
+            machine
+                .attach_device(TIMER_ADDRESS, Box::new(Timer::new(10, 0)))
+                .unwrap();
+            let mut runner = Runner::new(machine, program.len(), IllegalPolicy::Trap);
+
+            // the end loop waits for the countdown, then the machine halts
+            let steps = runner.run(1000).unwrap();
+            assert!(steps > 20 && steps < 1000, "{}: {}", backend.name(), steps);
+            assert!(runner.is_halted());
+            assert_eq!(runner.machine.read_memory(0), 1, "{}", backend.name());
+
+            // key presses may still come
+            runner.keyboard_interrupts = true;
+            assert!(!runner.is_halted());
+        }
+    }
+}
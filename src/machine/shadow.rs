@@ -0,0 +1,277 @@
+// Uninitialised reads
+//
+// Like valgrind for Hack: a shadow bit for every word of the RAM and the screen,
+// and for A, D and SP, tells whether anything has written it since power on.
+// Real memory starts with garbage (see `MachineConfig::power_on_seed`), so a program,
+// that reads before it writes, only works by accident.
+//
+// The keyboard, the devices and the bank registers always count as written.
+// The shadow bits follow the addresses, not the RAM banks.
+
+use std::fmt;
+
+use super::{
+    config::{Extensions, MemoryMap, Region},
+    device::ADDRESS_SPACE,
+    instruction::{
+        Instruction, DEST_A, DEST_D, DEST_M, STACK_CALL, STACK_GET_SP, STACK_POP, STACK_PUSH,
+        STACK_RET, STACK_SET_SP,
+    },
+    CpuState, StepInfo,
+};
+
+/// A register or a memory word, that can be read before it's written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location {
+    A,
+    D,
+    Sp,
+    Memory(usize),
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::A => write!(f, "A"),
+            Location::D => write!(f, "D"),
+            Location::Sp => write!(f, "SP"),
+            Location::Memory(address) => write!(f, "RAM[{}]", address),
+        }
+    }
+}
+
+/// The instruction as the CPU executes it: without the extension,
+/// `RETI`, the shifts and the stack instructions are C-instructions.
+pub fn executed(word: i16, extensions: Extensions) -> Instruction {
+    let c_instruction = || Instruction::decode(word | 0x6000u16 as i16);
+    match Instruction::decode(word) {
+        Instruction::Reti if !extensions.interrupts() => c_instruction(),
+        Instruction::Shift { .. } if !extensions.shifts => c_instruction(),
+        Instruction::Stack { .. } if !extensions.stack => c_instruction(),
+        instruction => instruction,
+    }
+}
+
+/// Whether the instruction takes a word from the data memory: M, or the top of the stack.
+pub fn reads_memory(word: i16, extensions: Extensions) -> bool {
+    match executed(word, extensions) {
+        Instruction::C { comp, .. } | Instruction::Shift { comp, .. } => comp & 0b1000000 != 0,
+        Instruction::Stack { op, comp, .. } => {
+            op == STACK_POP || op == STACK_RET || comp & 0b1000000 != 0
+        }
+        Instruction::A(_) | Instruction::Reti => false,
+    }
+}
+
+/// Registers, that the comp of a C-instruction or a stack instruction reads.
+/// The M in y is given as the address in A.
+fn comp_reads(comp: u16, a: i16) -> [Option<Location>; 2] {
+    let x = (comp & 0b0100000 == 0).then_some(Location::D);
+    let y = (comp & 0b0001000 == 0).then_some(match comp & 0b1000000 {
+        0 => Location::A,
+        _ => Location::Memory(a as u16 as usize & 0x7FFF),
+    });
+
+    [x, y]
+}
+
+/// Everything, that the instruction reads. `sp` is the stack pointer before it.
+pub fn reads(word: i16, state: CpuState, sp: i16, extensions: Extensions) -> Vec<Location> {
+    let mut reads = Vec::new();
+    match executed(word, extensions) {
+        Instruction::A(_) | Instruction::Reti => {}
+        Instruction::C { comp, dest, jump } => {
+            reads.extend(comp_reads(comp, state.a).into_iter().flatten());
+            // A is the address of M, and the jump target
+            if comp & 0b1000000 != 0 || dest & 0b001 != 0 || jump != 0 {
+                reads.push(Location::A);
+            }
+        }
+        Instruction::Shift { comp, jump, .. } => {
+            let source = match comp & 0b1010000 {
+                0b0010000 => Location::D,
+                0b0000000 => Location::A,
+                _ => Location::Memory(state.a as u16 as usize & 0x7FFF),
+            };
+            reads.push(source);
+            if comp & 0b1000000 != 0 || jump != 0 {
+                reads.push(Location::A);
+            }
+        }
+        Instruction::Stack { op, comp, .. } => {
+            let top = Location::Memory(sp.wrapping_sub(1) as u16 as usize & 0x7FFF);
+            match op {
+                STACK_PUSH | STACK_SET_SP => {
+                    reads.extend(comp_reads(comp, state.a).into_iter().flatten());
+                }
+                STACK_POP | STACK_RET => reads.push(top),
+                STACK_CALL => reads.push(Location::A),
+                _ => {}
+            }
+            if op != STACK_SET_SP {
+                reads.push(Location::Sp);
+            }
+        }
+    }
+
+    reads
+}
+
+/// The data address, that the instruction writes, if any. `sp` is the stack pointer before it.
+pub fn writes(word: i16, state: CpuState, sp: i16, extensions: Extensions) -> Option<usize> {
+    match executed(word, extensions) {
+        Instruction::C { dest, .. } | Instruction::Shift { dest, .. } if dest & DEST_M != 0 => {
+            Some(state.a as u16 as usize & 0x7FFF)
+        }
+        Instruction::Stack {
+            op: STACK_PUSH | STACK_CALL,
+            ..
+        } => Some(sp as u16 as usize & 0x7FFF),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Shadow {
+    memory_map: MemoryMap,
+    extensions: Extensions,
+
+    /// one bit per data address, only the RAM and the screen are used
+    written: Vec<bool>,
+    a: bool,
+    d: bool,
+    sp: bool,
+}
+
+impl Shadow {
+    pub fn new(memory_map: MemoryMap, extensions: Extensions) -> Self {
+        Self {
+            memory_map,
+            extensions,
+            written: vec![false; ADDRESS_SPACE],
+            a: false,
+            d: false,
+            sp: false,
+        }
+    }
+
+    /// Forgets every write, like at power on.
+    pub fn clear(&mut self) {
+        *self = Self::new(self.memory_map, self.extensions);
+    }
+
+    /// Counts the location as written, e.g. so that an uninitialised read is reported once.
+    pub fn mark(&mut self, location: Location) {
+        match location {
+            Location::A => self.a = true,
+            Location::D => self.d = true,
+            Location::Sp => self.sp = true,
+            Location::Memory(address) => self.written[address] = true,
+        }
+    }
+
+    pub fn is_written(&self, location: Location) -> bool {
+        match location {
+            Location::A => self.a,
+            Location::D => self.d,
+            Location::Sp => self.sp,
+            Location::Memory(address) => match self.memory_map.region(address) {
+                Region::Ram | Region::Screen => self.written[address],
+                _ => true,
+            },
+        }
+    }
+
+    /// The first location, that the instruction reads before anything has written it.
+    pub fn check(&self, word: i16, state: CpuState, sp: i16) -> Option<Location> {
+        reads(word, state, sp, self.extensions)
+            .into_iter()
+            .find(|location| !self.is_written(*location))
+    }
+
+    /// Marks, what the executed instruction has written.
+    pub fn record(&mut self, info: &StepInfo) {
+        if let Some((address, _)) = info.memory_write {
+            self.written[address] = true;
+        }
+
+        let dest = match executed(info.instruction, self.extensions) {
+            Instruction::A(_) => DEST_A,
+            Instruction::C { dest, .. } | Instruction::Shift { dest, .. } => dest,
+            Instruction::Stack { op, dest, .. } => match op {
+                STACK_POP | STACK_GET_SP => dest,
+                STACK_SET_SP => {
+                    self.sp = true;
+                    0
+                }
+                _ => 0,
+            },
+            Instruction::Reti => 0,
+        };
+        self.a |= dest & DEST_A != 0;
+        self.d |= dest & DEST_D != 0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        assembler::asm_to_binary,
+        emulated_parts::computer_emulated::ComputerEmulated,
+        machine::{config::MachineConfig, Machine},
+    };
+
+    fn first_uninitialised(program: &str, extensions: Extensions) -> Option<(usize, Location)> {
+        let program = asm_to_binary(program).unwrap();
+        let config = MachineConfig {
+            extensions,
+            ..MachineConfig::default()
+        };
+        let mut computer = ComputerEmulated::power_on_with_config(program, config);
+        let mut shadow = Shadow::new(MemoryMap::default(), extensions);
+        for _ in 0..20 {
+            let state = computer.cpu_state();
+            let word = computer.read_rom(state.pc as usize);
+            let sp = computer.stack_pointer().unwrap_or(0);
+            if let Some(location) = shadow.check(word, state, sp) {
+                return Some((state.pc as usize, location));
+            }
+            let info = computer.step();
+            shadow.record(&info);
+        }
+
+        None
+    }
+
+    #[test]
+    fn test_shadow_finds_uninitialised_reads() {
+        let hack = Extensions::default();
+
+        assert_eq!(
+            first_uninitialised("@x\nM=0\n@x\nD=M+1\n@y\nD=D+M", hack),
+            Some((5, Location::Memory(17)))
+        );
+        assert_eq!(first_uninitialised("D=D+1", hack), Some((0, Location::D)));
+        assert_eq!(
+            first_uninitialised("D=1\nM=D", hack),
+            Some((1, Location::A))
+        );
+        assert_eq!(first_uninitialised("0;JMP", hack), Some((0, Location::A)));
+        // the keyboard is written by the hardware
+        assert_eq!(first_uninitialised("@KBD\nD=M\nD=!D\n@0\nAM=D", hack), None);
+
+        let stack = Extensions {
+            stack: true,
+            ..Extensions::default()
+        };
+        assert_eq!(
+            first_uninitialised("PUSH 1", stack),
+            Some((0, Location::Sp))
+        );
+        assert_eq!(
+            first_uninitialised("@256\nD=A\nSP=D\nPUSH D\nPOP A\nPOP D", stack),
+            Some((5, Location::Memory(255)))
+        );
+    }
+}
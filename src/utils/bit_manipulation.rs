@@ -9,3 +9,15 @@ pub fn set_bit_from_u16(value: i16, index: usize, new_value: bool) -> i16 {
         value & !(1 << index)
     }
 }
+
+/// Packs the bits into an integer. Index 0 is the least significant bit.
+pub fn i16_from_bits<const N: usize>(bits: [bool; N]) -> i16 {
+    bits.iter()
+        .enumerate()
+        .fold(0, |value, (i, bit)| set_bit_from_u16(value, i, *bit))
+}
+
+/// Unpacks the integer into bits. Index 0 is the least significant bit.
+pub fn bits_from_i16(value: i16) -> [bool; 16] {
+    std::array::from_fn(|i| get_bit_from_i16(value, i))
+}
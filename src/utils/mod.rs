@@ -5,3 +5,4 @@ pub mod convert_16b;
 pub mod convert_bn;
 pub mod memory;
 pub mod opcodes;
+pub mod random;